@@ -1,5 +1,5 @@
 import { forwardRef, Fragment } from "react";
-import { MZReader, Spectrum } from "mzdata";
+import { DetailLevel, MZReader, Spectrum } from "mzdata";
 import "./SpectrumList.css";
 
 import {
@@ -204,10 +204,9 @@ export function VirtualizedTable() {
         <TableVirtuoso
         totalCount={mzReader ? mzReader.length : 0}
         itemContent={(index: number) => {
-            mzReader?.setDataLoading(false);
             return rowContent(
                 index,
-                (mzReader as MZReader).at(index) as Spectrum,
+                (mzReader as MZReader).at(index, DetailLevel.MetadataOnly) as Spectrum,
                 viewerState.currentSpectrumID
             );
         }}
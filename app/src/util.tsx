@@ -1,4 +1,5 @@
 import {
+  DetailLevel,
   IonMobilityFrame,
   IsotopicModel,
   MZReader,
@@ -218,64 +219,53 @@ export class ProcessingParams {
     return new ProcessingParams(10, 1, false, [IsotopicModel.peptide()], true);
   }
 
-  applyIonMobilityFrame(frame: IonMobilityFrame) {
-    if (!frame.features()) {
-      console.log("Extracting features");
-      if (frame.msLevel == 1) {
-        frame.extractFeatures(
-          this.minimumFeatureExtractionSize,
-          this.maximumFeatureGapSize,
-          this.massErrorTolerance.copy(),
-        );
-      } else {
-        frame.extractFeatures(
-          this.minimumFeatureExtractionSize,
-          this.maximumFeatureGapSize,
-          this.massErrorTolerance.copy(),
-        );
-      }
-      console.log("Done extracting features");
-    }
-    if (!frame.deconvolutedFeatures() && this.doDeconvolution) {
-      if (!this.isotopicModels) throw new Error(this.isotopicModels);
-      console.log("Deconvolving features");
-      if (frame.msLevel == 1) {
-        frame.deconvolveFeatures(
-          this.minimumFeatureExtractionSize,
-          this.maximumFeatureGapSize,
-          this.deconvolutionScore,
-          this.isotopicModels.map((i) => i.copy()),
-          this.massErrorTolerance.copy(),
-        );
-      } else {
-        frame.deconvolveFeatures(
-          this.minimumFeatureExtractionSize,
-          this.maximumFeatureGapSize,
-          this.deconvolutionScore,
-          this.isotopicModels.map((i) => i.copy())
-        );
-      }
-      console.log("Done deconvolving features");
-    }
-    return frame;
-  }
-
-  applySpectrum(spectrum: Spectrum) {
+  /* The processing steps for these parameters, which the desktop backend runs too so a
+     spectrum processed in the browser matches one processed natively. The deconvolution
+     steps skip spectra and frames that already carry deconvoluted peaks or features. */
+  toPipeline(): mzdata.ProcessingPipeline {
+    const pipeline = new mzdata.ProcessingPipeline();
     if (this.reprofile) {
-      spectrum.reprofile(0.001, 0.01);
+      pipeline.reprofile(0.001, 0.01);
     }
     if (this.denoiseScale > 0) {
-      spectrum.denoise(this.denoiseScale);
+      pipeline.denoise(this.denoiseScale);
     }
-    if (!spectrum.deconvolutedPeaks() && this.doDeconvolution) {
-      if (!this.isotopicModels) {
-        throw new Error(this.isotopicModels);
-      }
-      spectrum.deconvolve(
+    pipeline.pickPeaks(1.0);
+    if (this.doDeconvolution) {
+      if (!this.isotopicModels) throw new Error(this.isotopicModels);
+      pipeline.deconvolve(
         this.deconvolutionScore,
         this.isotopicModels.map((i) => i.copy())
       );
     }
+    pipeline.extractFeatures(
+      this.minimumFeatureExtractionSize,
+      this.maximumFeatureGapSize,
+      this.massErrorTolerance.copy(),
+    );
+    if (this.doDeconvolution) {
+      pipeline.deconvolveFeatures(
+        this.minimumFeatureExtractionSize,
+        this.maximumFeatureGapSize,
+        this.deconvolutionScore,
+        this.isotopicModels.map((i) => i.copy()),
+        this.massErrorTolerance.copy(),
+      );
+    }
+    return pipeline;
+  }
+
+  applyIonMobilityFrame(frame: IonMobilityFrame) {
+    const pipeline = this.toPipeline();
+    pipeline.applyToFrame(frame);
+    pipeline.free();
+    return frame;
+  }
+
+  applySpectrum(spectrum: Spectrum) {
+    const pipeline = this.toPipeline();
+    pipeline.apply(spectrum);
+    pipeline.free();
     return spectrum;
   }
 
@@ -325,10 +315,7 @@ export class SpectrumViewerState {
       this.mzReader &&
       this.currentSpectrumIdx
     ) {
-      this.mzReader.setDataLoading(true);
-      const group = this.mzReader.groupAt(this.currentSpectrumIdx);
-      this.mzReader.setDataLoading(false);
-      return group;
+      return this.mzReader.groupAt(this.currentSpectrumIdx, DetailLevel.Full);
     }
   }
 
@@ -336,11 +323,10 @@ export class SpectrumViewerState {
     if (!this.mzReader || this.currentSpectrumIdx == null) {
       return;
     }
-    this.mzReader.setDataLoading(true);
     let spectrum: Spectrum | IonMobilityFrame | undefined = this.mzReader.at(
-      this.currentSpectrumIdx
+      this.currentSpectrumIdx,
+      DetailLevel.Full
     );
-    this.mzReader.setDataLoading(false);
     if (spectrum === undefined) {
       return;
     }
@@ -47,10 +47,16 @@ export class ReaderHandle {
   }
 
   async at(index: number, processing?: ProcessingParams) {
+    let pipeline = null;
+    if (processing) {
+      const wasmPipeline = processing.toPipeline();
+      pipeline = wasmPipeline.toJSON();
+      wasmPipeline.free();
+    }
     let buffer: Uint8Array | number[] = await invoke("load_data_for", {
       handle: this,
       index: index,
-      processing: processing,
+      pipeline: pipeline,
     });
     if (buffer instanceof Array) {
         buffer = new Uint8Array(buffer)
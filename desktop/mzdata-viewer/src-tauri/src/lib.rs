@@ -9,14 +9,10 @@ use mzdata::{
 };
 
 use itertools::Itertools;
-use mzdeisotope::isotopic_model::{IsotopicModels, IsotopicPatternParams};
-use mzdeisotope::scorer::{MaximizingFitFilter, PenalizedMSDeconvScorer};
-use mzdeisotope::{deconvolute_peaks, IsotopicModelLike};
+use mzdata_pipeline::ProcessingPipeline;
 use mzdeisotope_map::solution::DeconvolvedSolutionFeature;
-use mzdeisotope_map::FeatureSearchParams;
 use mzpeaks::feature::Feature;
 use mzpeaks::{CentroidPeak, IonMobility, MZ};
-use mzsignal::feature_statistics::FeatureTransform;
 use serde::{Deserialize, Serialize};
 use tauri::ipc::{Channel, InvokeResponseBody, IpcResponse, Response};
 use tauri::Manager;
@@ -63,19 +59,6 @@ impl AppData {
 
 pub type ADHandle = Mutex<AppData>;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ProcessingParams {
-    pub deconvolution_score: f32,
-    pub denoise_scale: f32,
-    pub reprofile: bool,
-    pub isotopic_models: Vec<IsotopicModels>,
-    pub do_deconvolution: bool,
-    pub minimum_feature_extraction_size: usize,
-    pub maximum_feature_gap_size: f64,
-    pub mass_error_tolerance: Tolerance,
-}
-
 pub struct ReaderHandle {
     reader: Reader,
     key: String,
@@ -197,6 +180,24 @@ impl IpcResponse for DataBufferMessage {
     }
 }
 
+/// Send the metadata of every spectrum or frame in `items` to `channel` in batches
+fn send_header_batches<T: Serialize>(
+    key: &str,
+    items: impl Iterator<Item = T>,
+    channel: &Channel<DataBufferMessage>,
+) -> Result<(), String> {
+    for (i, batch) in items.chunks(500).into_iter().enumerate() {
+        info!("Sending header batch {i} for {key}");
+        let batch: Vec<_> = batch.collect();
+        channel
+            .send(DataBufferMessage::Data {
+                data: serde_json::to_vec(&batch).unwrap(),
+            })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
 #[command]
 async fn load_all_headers<R: Runtime>(
     app: AppHandle<R>,
@@ -206,48 +207,19 @@ async fn load_all_headers<R: Runtime>(
     let state = app.state::<ADHandle>();
     let mut handles = state.lock().unwrap();
     if let Some(reader) = handles.handle(&handle.key) {
+        // Every command sets the detail level it reads at, so there is nothing to restore
         match &mut reader.reader {
             Reader::MZReader(reader) => {
-                let dl = *reader.detail_level();
                 reader.set_detail_level(mzdata::io::DetailLevel::MetadataOnly);
-                for (i, batch) in reader.iter().chunks(500).into_iter().enumerate() {
-                    info!("Sending header batch {i} for {}", handle.key);
-                    let spectra: Vec<_> = batch.collect();
-                    channel
-                        .send(DataBufferMessage::Data {
-                            data: serde_json::to_vec(&spectra).unwrap(),
-                        })
-                        .map_err(|e| e.to_string())?;
-                }
-                reader.set_detail_level(dl);
+                send_header_batches(&handle.key, reader.iter(), &channel)?;
             }
             Reader::MZReaderGZ(reader) => {
-                let dl = *reader.detail_level();
                 reader.set_detail_level(mzdata::io::DetailLevel::MetadataOnly);
-                for (i, batch) in reader.iter().chunks(500).into_iter().enumerate() {
-                    info!("Sending header batch {i} for {}", handle.key);
-                    let spectra: Vec<_> = batch.collect();
-                    channel
-                        .send(DataBufferMessage::Data {
-                            data: serde_json::to_vec(&spectra).unwrap(),
-                        })
-                        .map_err(|e| e.to_string())?;
-                }
-                reader.set_detail_level(dl);
+                send_header_batches(&handle.key, reader.iter(), &channel)?;
             }
             Reader::IMMZReader(reader) => {
-                let dl = *reader.detail_level();
                 reader.set_detail_level(mzdata::io::DetailLevel::MetadataOnly);
-                for (i, batch) in reader.iter().chunks(500).into_iter().enumerate() {
-                    info!("Sending header batch {i} for {}", handle.key);
-                    let frames: Vec<_> = batch.collect();
-                    channel
-                        .send(DataBufferMessage::Data {
-                            data: serde_json::to_vec(&frames).unwrap(),
-                        })
-                        .map_err(|e| e.to_string())?;
-                }
-                reader.set_detail_level(dl);
+                send_header_batches(&handle.key, reader.iter(), &channel)?;
             }
         }
         channel
@@ -268,7 +240,7 @@ async fn load_data_for<R: Runtime>(
     app: AppHandle<R>,
     handle: ReaderHandleRef,
     index: usize,
-    processing: Option<ProcessingParams>,
+    pipeline: Option<ProcessingPipeline>,
 ) -> Result<Response, String> {
     let state = app.state::<ADHandle>();
     let mut handles = state.lock().unwrap();
@@ -305,49 +277,10 @@ async fn load_data_for<R: Runtime>(
     }
     drop(handles);
 
-
     if let Some(mut spectrum) = spectrum_opt {
-        if let Some(procs) = processing.as_ref() {
-            info!("Transforming spectrum {index} with {procs:?}");
-            if procs.reprofile {
-                spectrum
-                    .reprofile_with_shape(0.001, 0.005)
-                    .map_err(|e| e.to_string())?;
-            }
-            if procs.denoise_scale > 0.0 {
-                spectrum
-                    .denoise(procs.denoise_scale)
-                    .map_err(|e| e.to_string())?;
-            }
-            spectrum.pick_peaks(1.0).map_err(|e| e.to_string())?;
-            if procs.do_deconvolution {
-                let peaks = spectrum.peaks.clone().unwrap();
-                let max_z = spectrum
-                    .description()
-                    .precursor
-                    .as_ref()
-                    .and_then(|p| p.charge())
-                    .unwrap_or(8)
-                    .abs();
-                let models =
-                    IsotopicModelLike::from_iter(procs.isotopic_models.iter().map(|i| *i));
-                let mut iso_params = IsotopicPatternParams::default();
-                iso_params.incremental_truncation = Some(0.95);
-                iso_params.truncate_after = 0.9999;
-                let solution = deconvolute_peaks(
-                    peaks,
-                    models,
-                    Tolerance::PPM(15.0),
-                    (1, max_z),
-                    PenalizedMSDeconvScorer::new(0.02, 2.0),
-                    MaximizingFitFilter::new(procs.deconvolution_score),
-                    1,
-                    iso_params,
-                    true,
-                )
-                .unwrap();
-                spectrum.deconvoluted_peaks = Some(solution);
-            }
+        if let Some(pipeline) = pipeline.as_ref() {
+            info!("Transforming spectrum {index} with {pipeline:?}");
+            pipeline.process_spectrum(&mut spectrum)?;
         }
         info!("Done processing spectrum, sending");
         return Ok(Response::new(InvokeResponseBody::Raw(
@@ -355,61 +288,9 @@ async fn load_data_for<R: Runtime>(
         )));
     }
     if let Some(mut frame) = frame_opt {
-        if let Some(procs) = processing.as_ref() {
-            info!("Transforming ion mobility frame {index} with {procs:?}");
-            frame
-                .extract_features_simple(
-                    procs.mass_error_tolerance,
-                    procs.minimum_feature_extraction_size,
-                    procs.maximum_feature_gap_size,
-                    None,
-                )
-                .map_err(|e| e.to_string())?;
-
-            if procs.do_deconvolution {
-                let mut features = frame.features.clone().unwrap();
-                features.iter_mut().for_each(|f| {
-                    f.smooth(1);
-                });
-
-                let max_z = frame
-                    .description()
-                    .precursor
-                    .as_ref()
-                    .and_then(|p| p.charge())
-                    .unwrap_or(8)
-                    .abs();
-                let mut feature_params = FeatureSearchParams::default();
-                if frame.ms_level() == 1 {
-                    feature_params.truncate_after = 0.95;
-                } else {
-                    feature_params.truncate_after = 0.8;
-                }
-                let isotopic_model: IsotopicModelLike = procs
-                    .isotopic_models
-                    .first()
-                    .copied()
-                    .map(|m| m.into())
-                    .unwrap();
-                let res = mzdeisotope_map::deconvolute_features(
-                    features,
-                    feature_params,
-                    isotopic_model,
-                    PenalizedMSDeconvScorer::new(0.04, 2.0),
-                    MaximizingFitFilter::new(procs.deconvolution_score),
-                    procs.mass_error_tolerance,
-                    (1, max_z),
-                    procs.minimum_feature_extraction_size,
-                    procs.maximum_feature_gap_size,
-                    5.0,
-                    2,
-                )
-                .inspect_err(|e| {
-                    log::error!("Error occured during deconvolution: {e}");
-                })
-                .unwrap();
-                frame.deconvoluted_features = Some(res);
-            }
+        if let Some(pipeline) = pipeline.as_ref() {
+            info!("Transforming ion mobility frame {index} with {pipeline:?}");
+            pipeline.process_frame(&mut frame)?;
         }
         info!("Done processing frame, sending");
         return Ok(Response::new(InvokeResponseBody::Raw(
@@ -0,0 +1,134 @@
+//! Generate the CV term table `src/psi_ms_terms.tsv` bundled by `src/cv.rs` from the
+//! PSI-MS and Unit Ontology OBO files. Run with `just cv`, which fetches them into `cv/`
+//! first, and commit the regenerated table.
+//!
+//! ```text
+//! cargo run --example cv_table -- <table.tsv> <obo files>...
+//! ```
+
+use std::env;
+use std::fs;
+
+const PREFIXES: [&str; 2] = ["MS:", "UO:"];
+
+#[derive(Default)]
+struct Term {
+    accession: String,
+    name: String,
+    parents: Vec<String>,
+    unit: Option<String>,
+    definition: String,
+    obsolete: bool,
+}
+
+impl Term {
+    fn keep(&self) -> bool {
+        !self.obsolete && PREFIXES.iter().any(|p| self.accession.starts_with(p))
+    }
+
+    fn to_row(&self) -> String {
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.accession,
+            clean(&self.name),
+            self.parents.join(","),
+            self.unit.as_deref().unwrap_or_default(),
+            clean(summary(&self.definition)),
+        )
+    }
+}
+
+/// The reference of a tag value like `MS:1000031 ! instrument model`
+fn reference(value: &str) -> Option<String> {
+    let accession = value.split_whitespace().next()?;
+    PREFIXES
+        .iter()
+        .any(|p| accession.starts_with(p))
+        .then(|| accession.to_string())
+}
+
+/// The quoted text of a `def` tag, unescaping the quotes within it
+fn definition(value: &str) -> String {
+    let mut text = String::new();
+    let mut chars = value.trim_start_matches('"').chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            '"' => break,
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// The first sentence of a definition, which is all the table keeps to bound its size
+fn summary(definition: &str) -> &str {
+    match definition.find(". ") {
+        Some(i) => &definition[..=i],
+        None => definition,
+    }
+}
+
+fn parse_obo(text: &str, terms: &mut Vec<Term>) {
+    let mut current: Option<Term> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            terms.extend(current.take().filter(Term::keep));
+            if line == "[Term]" {
+                current = Some(Term::default());
+            }
+            continue;
+        }
+        let Some(term) = current.as_mut() else {
+            continue;
+        };
+        let Some((tag, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match tag {
+            "id" => term.accession = value.to_string(),
+            "name" => term.name = value.to_string(),
+            "def" => term.definition = definition(value),
+            "is_a" => term.parents.extend(reference(value)),
+            "is_obsolete" => term.obsolete = value == "true",
+            "relationship" => {
+                if let Some(unit) = value.strip_prefix("has_units") {
+                    if term.unit.is_none() {
+                        term.unit = reference(unit.trim());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    terms.extend(current.take().filter(Term::keep));
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let out = args.next();
+    let obo_files: Vec<String> = args.collect();
+    let Some(out) = out.filter(|_| !obo_files.is_empty()) else {
+        panic!("Usage: cv_table <table.tsv> <obo files>...");
+    };
+
+    let mut terms = Vec::new();
+    for path in obo_files.iter() {
+        let text =
+            fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {path}: {e}"));
+        parse_obo(&text, &mut terms);
+    }
+    terms.sort_by(|a, b| a.accession.cmp(&b.accession));
+    let mut table = String::from(
+        "# Generated by `just cv` from the PSI-MS and Unit Ontology OBO files, do not edit\n#\n\
+         # Columns: accession, name, parent accessions (comma separated), default unit accession, definition\n",
+    );
+    for term in terms.iter() {
+        table.push_str(&term.to_row());
+    }
+    fs::write(&out, table).unwrap_or_else(|e| panic!("Failed to write {out}: {e}"));
+    eprintln!("Wrote {} terms to {out}", terms.len());
+}
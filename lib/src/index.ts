@@ -1,8 +1,9 @@
 export * as wasm from "mzdata-wasm";
 export { MZReader, type SpectrumGroup } from "./mem_reader";
-export { writeMGF, writeMzML } from './mem_writer';
+export { writeMGF, writeMzML, writeMzQC } from './mem_writer';
 
 export { readFile } from "./io_tools";
+export { ProcessingPool, type ProcessingOptions, type ProcessedChunk } from "./worker_pool";
 
 export {
   SimplePeak,
@@ -16,6 +17,8 @@ export {
   IonMobilityFrame,
   Feature,
   FeatureFit,
+  PeakModel,
+  PeakShapeKind,
   FeaturePoint,
   DeconvolvedFeature,
   IsolationWindow,
@@ -23,4 +26,46 @@ export {
   SimpleChargedPeak,
   ScanWindow,
   ScanEvent,
+  FeatureTable,
+  FeatureTableRow,
+  Peptide,
+  FragmentIonType,
+  TheoreticalFragment,
+  FragmentMatch,
+  DiagnosticIonPanel,
+  DiagnosticIonScan,
+  DiagnosticIonReport,
+  SimilarityMethod,
+  SimilarityParams,
+  SimilarityResult,
+  MatchedPeakPair,
+  spectrumSimilarity,
+  peakListSimilarity,
+  SpectralLibrary,
+  LibraryHit,
+  LibrarySearchResult,
+  MolecularNetwork,
+  NetworkParams,
+  NetworkNode,
+  NetworkEdge,
+  ConsensusParams,
+  buildConsensusSpectra,
+  RunQCParams,
+  RunQCReport,
+  QCDistribution,
+  LockMassObservation,
+  MassCalibration,
+  CalibrationModel,
+  CalibrationScope,
+  PackedBatch,
+  ProcessingPipeline,
+  Polarity,
+  CVTerm,
+  MassAnalyzer,
+  DIAScheme,
+  DIAWindow,
+  FragmentXICMatrix,
+  AcquisitionTree,
+  SpectrumIterator,
+  DetailLevel,
 } from "mzdata-wasm";
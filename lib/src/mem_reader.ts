@@ -37,6 +37,11 @@ const readFileToBuffer = async (file: File) => {
   return buffer
 }
 
+// Make the spectrum iterators handed out by the reader usable in `for...of` loops
+(wasm.SpectrumIterator.prototype as any)[Symbol.iterator] = function () {
+  return this;
+};
+
 export class IMMZReader {
   reader: wasm.MemWebIMMZReader;
 
@@ -55,9 +60,11 @@ export class IMMZReader {
 export class MZReader {
   reader: wasm.MemWebMZReader;
 
-  static async open(file: File) {
+  static async open(file: File, index?: Uint8Array) {
     const buffer = await readFileToBuffer(file);
-    const reader = wasm.MemWebMZReader.from_buffer(buffer);
+    const reader = index
+      ? wasm.MemWebMZReader.from_buffer_with_index(buffer, index)
+      : wasm.MemWebMZReader.from_buffer(buffer);
     return new MZReader(reader);
   }
 
@@ -65,6 +72,19 @@ export class MZReader {
     this.reader = reader;
   }
 
+  // Another reader over the same file with its own cursor, sharing the bytes and index
+  clone() {
+    return new MZReader(this.reader.clone());
+  }
+
+  get fingerprint() {
+    return this.reader.fingerprint;
+  }
+
+  exportIndex() {
+    return this.reader.exportIndex();
+  }
+
   fileFormat() {
     this.reader.file_format
   }
@@ -74,6 +94,14 @@ export class MZReader {
     return this;
   }
 
+  get detailLevel() {
+    return this.reader.detailLevel;
+  }
+
+  set detailLevel(value: wasm.DetailLevel) {
+    this.reader.detailLevel = value;
+  }
+
   setPeakPicking(value: boolean) {
     this.reader.set_peak_picking(value);
     return this;
@@ -83,35 +111,89 @@ export class MZReader {
     return this.reader.length;
   }
 
-  at(index: number) {
-    return this.getSpectrumByIndex(index);
+  at(index: number, detailLevel?: wasm.DetailLevel) {
+    return this.getSpectrumByIndex(index, detailLevel);
   }
 
   *[Symbol.iterator]() {
-    const n = this.reader.length;
-    for (let i = 0; i < n; i++) {
-      yield this.getSpectrumByIndex(i);
+    const it = this.reader.iter();
+    try {
+      while (true) {
+        const batch = it.nextBatch(64);
+        if (batch.length == 0) break;
+        yield* batch;
+      }
+    } finally {
+      it.free();
     }
   }
 
-  iter() {
-    return this[Symbol.iterator]();
+  iter(options?: { start?: number, end?: number, detailLevel?: wasm.DetailLevel }) {
+    return this.reader.iter(options?.start, options?.end, options?.detailLevel) as wasm.SpectrumIterator & Iterable<Spectrum>;
+  }
+
+  getSpectraByIndices(indices: number[] | Uint32Array, detailLevel?: wasm.DetailLevel) {
+    return this.reader.getSpectraByIndices(Uint32Array.from(indices), detailLevel);
+  }
+
+  getRange(start: number, end: number, detailLevel?: wasm.DetailLevel) {
+    return this.reader.getRange(start, end, detailLevel);
   }
 
-  getSpectrumByIndex(index: number) {
-    return this.reader.get_spectrum_by_index(index);
+  getSpectrumByIndex(index: number, detailLevel?: wasm.DetailLevel) {
+    return this.reader.get_spectrum_by_index(index, detailLevel);
   }
 
-  getSpectrumById(id: string) {
-    return this.reader.get_spectrum_by_id(id);
+  getSpectrumById(id: string, detailLevel?: wasm.DetailLevel) {
+    return this.reader.get_spectrum_by_id(id, detailLevel);
   }
 
-  getSpectrumByTime(time: number) {
-    return this.reader.get_spectrum_by_time(time);
+  getSpectrumByTime(time: number, detailLevel?: wasm.DetailLevel) {
+    return this.reader.get_spectrum_by_time(time, detailLevel);
   }
 
-  groupAt(index: number): SpectrumGroup | undefined {
-    const group = this.reader.group_at(index) as SpectrumGroup | undefined;
+  analyzerFor(spectrum: wasm.Spectrum) {
+    return this.reader.analyzerFor(spectrum);
+  }
+
+  groupAt(index: number, detailLevel?: wasm.DetailLevel): SpectrumGroup | undefined {
+    const group = this.reader.group_at(index, detailLevel) as SpectrumGroup | undefined;
     return group
   }
+
+  acquisitionTree() {
+    return this.reader.acquisitionTree();
+  }
+
+  compensationVoltages() {
+    return Array.from(this.reader.compensationVoltages());
+  }
+
+  *iterCompensationVoltage(voltage: number, detailLevel?: wasm.DetailLevel) {
+    for (const i of this.reader.indicesAtCompensationVoltage(voltage)) {
+      yield this.getSpectrumByIndex(i, detailLevel);
+    }
+  }
+
+  *groupsAtCompensationVoltage(voltage: number, detailLevel?: wasm.DetailLevel) {
+    const groups = this.reader.groupIndicesAtCompensationVoltage(voltage) as { precursor: number | null, products: number[] }[];
+    for (const group of groups) {
+      yield {
+        precursor: group.precursor !== null ? this.getSpectrumByIndex(group.precursor, detailLevel) ?? null : null,
+        products: this.getSpectraByIndices(group.products, detailLevel),
+      } as SpectrumGroup;
+    }
+  }
+
+  groupAtCompensationVoltage(voltage: number, index: number, detailLevel?: wasm.DetailLevel): SpectrumGroup | undefined {
+    return this.reader.groupAtCompensationVoltage(voltage, index, detailLevel) as SpectrumGroup | undefined;
+  }
+
+  diaScheme() {
+    return this.reader.diaScheme();
+  }
+
+  diaFragmentXICs(windowIndex: number, startTime: number, endTime: number, fragmentMzs?: Float64Array, errorTolerance?: wasm.Tolerance, topN?: number) {
+    return this.reader.diaFragmentXICs(windowIndex, startTime, endTime, fragmentMzs, errorTolerance, topN);
+  }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-import { write_spectra_to_mgf, Spectrum, _write_spectra_to_mzml } from "mzdata-wasm"
+import { write_spectra_to_mgf, Spectrum, _write_spectra_to_mzml, _write_standalone_spectra_to_mzml, write_run_qc_to_mzqc, RunQCReport } from "mzdata-wasm"
 import { MZReader } from "./mem_reader"
 
 
@@ -7,9 +7,15 @@ export function writeMGF(spectra: Spectrum[]) {
 }
 
 
-export function writeMzML(spectra: Spectrum[], reader: MZReader) {
+export function writeMzML(spectra: Spectrum[], reader?: MZReader) {
+    if (!reader) return _write_standalone_spectra_to_mzml(spectra.map((s) => s.copy()))
     return _write_spectra_to_mzml(
         spectra.map((s) => s.copy()),
         reader.reader
     )
 }
+
+
+export function writeMzQC(report: RunQCReport, reader: MZReader, fileName?: string) {
+    return write_run_qc_to_mzqc(report, reader.reader, new Date().toISOString(), fileName)
+}
@@ -0,0 +1,59 @@
+import * as wasm from "mzdata-wasm";
+import type { ProcessingOptions } from "./worker_pool";
+
+/* The worker side of `ProcessingPool`. Each worker opens its own reader over the
+   shared file bytes and processes the index ranges it is sent, posting packed
+   batches of spectra back as they are produced. */
+
+export type WorkerRequest =
+  | { type: "open"; buffer: Uint8Array; index: Uint8Array }
+  | { type: "process"; jobId: number; start: number; end: number; options?: ProcessingOptions; chunkSize: number };
+
+export type WorkerResponse =
+  | { type: "ready" }
+  | { type: "chunk"; jobId: number; first: number; bytes: Uint8Array }
+  | { type: "done"; jobId: number; count: number }
+  | { type: "error"; jobId?: number; message: string };
+
+let reader: wasm.MemWebMZReader | null = null;
+
+const post = (message: WorkerResponse, transfer: Transferable[] = []) => {
+  (self as unknown as Worker).postMessage(message, transfer);
+};
+
+self.onmessage = (event: MessageEvent<WorkerRequest>) => {
+  const request = event.data;
+  try {
+    switch (request.type) {
+      case "open": {
+        // The reader copies the bytes into its own memory, so a shared buffer is never written to
+        reader = wasm.MemWebMZReader.from_buffer_with_index(
+          new Uint8Array(request.buffer),
+          request.index
+        );
+        post({ type: "ready" });
+        break;
+      }
+      case "process": {
+        if (!reader) throw new Error("Worker received work before a file was opened");
+        const count = reader.processRange(
+          request.start,
+          request.end,
+          request.options,
+          request.chunkSize,
+          (bytes: Uint8Array, first: number) => {
+            post({ type: "chunk", jobId: request.jobId, first, bytes }, [bytes.buffer]);
+          }
+        );
+        post({ type: "done", jobId: request.jobId, count });
+        break;
+      }
+    }
+  } catch (err) {
+    post({
+      type: "error",
+      jobId: request.type === "process" ? request.jobId : undefined,
+      message: String(err),
+    });
+  }
+};
@@ -0,0 +1,196 @@
+import * as wasm from "mzdata-wasm";
+import { MZReader } from "./mem_reader";
+import type { WorkerRequest, WorkerResponse } from "./processing_worker";
+
+/* The serialized form of a `ProcessingPipeline`, as returned by its `toJSON` method */
+export type ProcessingOptions = {
+  steps: { step: string; [param: string]: any }[];
+};
+
+export type ProcessedChunk = {
+  first: number;
+  spectra: wasm.Spectrum[];
+};
+
+const canUseWorkers = () => typeof Worker !== "undefined";
+
+/* Copy the reader's file out of wasm memory for the workers to open. Shared memory is only
+   available on cross-origin isolated pages, elsewhere posting the bytes gives each worker its
+   own copy of them. Either way the copy is only held until the workers have opened the file,
+   though each worker's reader keeps the file in its own wasm memory. */
+const fileBytes = (reader: MZReader) => {
+  const size = reader.reader.fileSize;
+  const canShare = typeof SharedArrayBuffer !== "undefined" && globalThis.crossOriginIsolated;
+  const bytes = new Uint8Array(canShare ? new SharedArrayBuffer(size) : new ArrayBuffer(size));
+  reader.reader.copyFileBytes(bytes);
+  return bytes;
+};
+
+const unpackChunk = (first: number, bytes: Uint8Array): ProcessedChunk => {
+  const batch = wasm.PackedBatch.unpack(bytes);
+  const spectra = batch.spectra();
+  batch.free();
+  return { first, spectra };
+};
+
+/* The chunks and state of one `process` call, filled in by the workers' message handlers */
+type Job = {
+  queue: ProcessedChunk[];
+  pending: number;
+  failure: Error | null;
+  wake: (() => void) | null;
+};
+
+/* A pool of Web Workers that process ranges of spectra from a reader's file in
+   parallel. When workers are unavailable, or the pool is created with a single
+   thread, the work runs on the calling thread in chunks instead.
+
+   Spectra are processed by `options` alone. The reader's own peak picking, mass
+   calibration and processing pipeline are not applied, on the workers or inline. */
+export class ProcessingPool {
+  private reader: MZReader;
+  private workers: Worker[];
+  private jobs = new Map<number, Job>();
+  private nextJobId = 0;
+
+  static async create(reader: MZReader, size?: number) {
+    const n = size ?? Math.max((navigator.hardwareConcurrency ?? 2) - 1, 1);
+    if (!canUseWorkers() || n <= 1) return new ProcessingPool(reader, []);
+
+    const buffer = fileBytes(reader);
+    const index = reader.exportIndex();
+    const workers = await Promise.all(
+      Array.from({ length: n }, () => {
+        const worker = new Worker(new URL("./processing_worker.ts", import.meta.url), {
+          type: "module",
+        });
+        return new Promise<Worker>((resolve, reject) => {
+          worker.onmessage = (event: MessageEvent<WorkerResponse>) => {
+            if (event.data.type === "ready") resolve(worker);
+            else if (event.data.type === "error") reject(new Error(event.data.message));
+          };
+          worker.postMessage({ type: "open", buffer, index } as WorkerRequest);
+        });
+      })
+    );
+    const pool = new ProcessingPool(reader, workers);
+    // One handler per worker for the life of the pool, routing each message to its job
+    for (const worker of workers) {
+      worker.onmessage = (event: MessageEvent<WorkerResponse>) => pool.route(event.data);
+    }
+    return pool;
+  }
+
+  private constructor(reader: MZReader, workers: Worker[]) {
+    this.reader = reader;
+    this.workers = workers;
+  }
+
+  get size() {
+    return Math.max(this.workers.length, 1);
+  }
+
+  private route(message: WorkerResponse) {
+    if (!("jobId" in message) || message.jobId === undefined) {
+      if (message.type === "error") console.error(message.message);
+      return;
+    }
+    const job = this.jobs.get(message.jobId);
+    if (!job) return;
+    switch (message.type) {
+      case "chunk":
+        job.queue.push(unpackChunk(message.first, message.bytes));
+        break;
+      case "done":
+        job.pending -= 1;
+        break;
+      case "error":
+        job.failure = new Error(message.message);
+        job.pending -= 1;
+        break;
+    }
+    if (job.wake) {
+      job.wake();
+      job.wake = null;
+    }
+  }
+
+  /* Process the spectra with indices in `[start, end)`, yielding chunks as soon as
+     any worker finishes them. Chunks are not guaranteed to arrive in index order.
+     Several calls may run at once, sharing the workers. */
+  async *process(
+    start: number,
+    end: number,
+    options?: ProcessingOptions | wasm.ProcessingPipeline,
+    chunkSize: number = 50
+  ): AsyncGenerator<ProcessedChunk> {
+    end = Math.min(end, this.reader.length);
+    if (options instanceof wasm.ProcessingPipeline) options = options.toJSON() as ProcessingOptions;
+    if (this.workers.length == 0) {
+      yield* this.processInline(start, end, options, chunkSize);
+      return;
+    }
+
+    const jobId = this.nextJobId++;
+    const job: Job = { queue: [], pending: 0, failure: null, wake: null };
+    this.jobs.set(jobId, job);
+
+    const span = Math.ceil((end - start) / this.workers.length);
+    this.workers.forEach((worker, i) => {
+      const lo = start + i * span;
+      const hi = Math.min(lo + span, end);
+      if (lo >= hi) return;
+      job.pending += 1;
+      worker.postMessage({
+        type: "process",
+        jobId,
+        start: lo,
+        end: hi,
+        options,
+        chunkSize,
+      } as WorkerRequest);
+    });
+
+    try {
+      while (job.pending > 0 || job.queue.length > 0) {
+        if (job.queue.length > 0) {
+          yield job.queue.shift()!;
+          continue;
+        }
+        if (job.failure) throw job.failure;
+        await new Promise<void>((resolve) => (job.wake = resolve));
+      }
+      if (job.failure) throw job.failure;
+    } finally {
+      this.jobs.delete(jobId);
+    }
+  }
+
+  private async *processInline(
+    start: number,
+    end: number,
+    options: ProcessingOptions | undefined,
+    chunkSize: number
+  ): AsyncGenerator<ProcessedChunk> {
+    for (let lo = start; lo < end; lo += chunkSize) {
+      const chunks: ProcessedChunk[] = [];
+      this.reader.reader.processRange(
+        lo,
+        Math.min(lo + chunkSize, end),
+        options,
+        chunkSize,
+        (bytes: Uint8Array, first: number) => {
+          chunks.push(unpackChunk(first, bytes));
+        }
+      );
+      yield* chunks;
+      // Let the page handle events between chunks
+      await new Promise((resolve) => setTimeout(resolve, 0));
+    }
+  }
+
+  terminate() {
+    for (const worker of this.workers) worker.terminate();
+    this.workers = [];
+  }
+}
@@ -20,4 +20,8 @@ export default defineConfig({
     },
   },
   plugins: [wasm(), topLevelAwait()],
+  worker: {
+    format: "es",
+    plugins: () => [wasm(), topLevelAwait()],
+  },
 });
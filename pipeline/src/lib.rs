@@ -0,0 +1,295 @@
+//! A declarative, serializable spectrum and frame processing pipeline.
+//!
+//! This crate does not depend on any JS or WebAssembly bindings so the same
+//! [`ProcessingPipeline`] can be run by `mzdata-wasm` and by the native desktop backend.
+
+use mzdata::prelude::*;
+use mzdata::spectrum::{MultiLayerIonMobilityFrame, MultiLayerSpectrum, SignalContinuity};
+use mzdeisotope::isotopic_model::{IsotopicModels, IsotopicPatternParams};
+use mzdeisotope::scorer::{MaximizingFitFilter, PenalizedMSDeconvScorer};
+use mzdeisotope::{deconvolute_peaks, DeconvolvedSolutionPeak, IsotopicModelLike};
+use mzdeisotope_map::solution::DeconvolvedSolutionFeature;
+use mzdeisotope_map::FeatureSearchParams;
+use mzpeaks::feature::Feature;
+use mzpeaks::{CentroidPeak, IonMobility, Tolerance, MZ};
+use mzsignal::feature_statistics::FeatureTransform;
+use serde::{Deserialize, Serialize};
+
+pub type PipelineSpectrum = MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>;
+pub type PipelineFrame =
+    MultiLayerIonMobilityFrame<Feature<MZ, IonMobility>, DeconvolvedSolutionFeature<IonMobility>>;
+
+fn max_charge(precursor: Option<&mzdata::spectrum::Precursor>) -> i32 {
+    precursor.and_then(|p| p.charge()).unwrap_or(8).abs()
+}
+
+/// Re-create a profile signal from centroid peaks, skipping spectra that are already profile
+pub fn reprofile_spectrum(
+    spectrum: &mut PipelineSpectrum,
+    dx: f64,
+    fwhm: f32,
+) -> Result<(), String> {
+    if spectrum.signal_continuity() == SignalContinuity::Profile {
+        return Ok(());
+    }
+    spectrum
+        .reprofile_with_shape(dx, fwhm)
+        .map_err(|e| e.to_string())?;
+    spectrum.description_mut().signal_continuity = SignalContinuity::Profile;
+    Ok(())
+}
+
+pub fn denoise_spectrum(spectrum: &mut PipelineSpectrum, scale: f32) -> Result<(), String> {
+    if spectrum.raw_arrays().is_none() {
+        log::warn!("Cannot denoise a spectrum that has no profile signal");
+        return Ok(());
+    }
+    spectrum.denoise(scale).map_err(|e| e.to_string())
+}
+
+/// Pick peaks from the spectrum's signal, if it has any to pick from
+pub fn pick_spectrum_peaks(
+    spectrum: &mut PipelineSpectrum,
+    signal_to_noise_threshold: f32,
+) -> Result<(), String> {
+    if spectrum.signal_continuity() != SignalContinuity::Profile && spectrum.raw_arrays().is_none()
+    {
+        return Ok(());
+    }
+    spectrum
+        .pick_peaks(signal_to_noise_threshold)
+        .map_err(|e| e.to_string())
+}
+
+/// Deconvolve the spectrum's centroid peaks, picking them first if needed
+pub fn deconvolve_spectrum(
+    spectrum: &mut PipelineSpectrum,
+    score_threshold: f32,
+    isotopic_models: &[IsotopicModels],
+) -> Result<(), String> {
+    if spectrum.peaks.is_none() {
+        pick_spectrum_peaks(spectrum, 1.0)?;
+    }
+    let peaks = spectrum
+        .peaks
+        .as_ref()
+        .ok_or_else(|| "Cannot deconvolve a spectrum without peaks".to_string())?;
+    let max_z = max_charge(spectrum.description().precursor.as_ref());
+    let models = IsotopicModelLike::from_iter(isotopic_models.iter().copied());
+    let mut iso_params = IsotopicPatternParams::default();
+    iso_params.incremental_truncation = Some(0.95);
+    iso_params.truncate_after = 0.9999;
+    let solution = deconvolute_peaks(
+        peaks.clone(),
+        models,
+        Tolerance::PPM(15.0),
+        (1, max_z),
+        PenalizedMSDeconvScorer::new(0.02, 2.0),
+        MaximizingFitFilter::new(score_threshold),
+        1,
+        iso_params,
+        true,
+    )
+    .map_err(|e| e.to_string())?;
+    spectrum.deconvoluted_peaks = Some(solution);
+    Ok(())
+}
+
+pub fn extract_frame_features(
+    frame: &mut PipelineFrame,
+    min_length: usize,
+    maximum_gap_size: f64,
+    error_tolerance: Tolerance,
+) -> Result<(), String> {
+    frame
+        .extract_features_simple(error_tolerance, min_length, maximum_gap_size, None)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Deconvolve the frame's features, extracting them first if needed
+pub fn deconvolve_frame_features(
+    frame: &mut PipelineFrame,
+    min_length: usize,
+    maximum_gap_size: f64,
+    score_threshold: f32,
+    isotopic_models: &[IsotopicModels],
+    error_tolerance: Tolerance,
+) -> Result<(), String> {
+    if frame.features.is_none() {
+        extract_frame_features(frame, min_length, maximum_gap_size, error_tolerance)?;
+    }
+    let mut features = frame.features.as_ref().unwrap().clone();
+    features.iter_mut().for_each(|f| {
+        f.smooth(1);
+    });
+    let max_z = max_charge(frame.description().precursor.as_ref());
+    let mut feature_params = FeatureSearchParams::default();
+    if frame.ms_level() == 1 {
+        feature_params.truncate_after = 0.95;
+    } else {
+        feature_params.truncate_after = 0.8;
+    }
+    let isotopic_model: IsotopicModelLike = isotopic_models
+        .first()
+        .copied()
+        .map(|m| m.into())
+        .ok_or_else(|| "No isotopic model given for feature deconvolution".to_string())?;
+    let solution = mzdeisotope_map::deconvolute_features(
+        features,
+        feature_params,
+        isotopic_model,
+        PenalizedMSDeconvScorer::new(0.04, 2.0),
+        MaximizingFitFilter::new(score_threshold),
+        error_tolerance,
+        (1, max_z),
+        min_length,
+        maximum_gap_size,
+        5.0,
+        2,
+    )
+    .map_err(|e| e.to_string())?;
+    frame.deconvoluted_features = Some(solution);
+    Ok(())
+}
+
+fn default_isotopic_models() -> Vec<IsotopicModels> {
+    vec![IsotopicModels::Peptide]
+}
+
+pub fn default_error_tolerance() -> Tolerance {
+    Tolerance::PPM(15.0)
+}
+
+/// A single processing step. Spectrum steps are skipped for frames and vice versa, and the
+/// deconvolution steps skip spectra and frames that have already been deconvolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "step",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ProcessingStep {
+    Reprofile {
+        dx: f64,
+        fwhm: f32,
+    },
+    Denoise {
+        scale: f32,
+    },
+    PickPeaks {
+        signal_to_noise: f32,
+    },
+    Deconvolve {
+        score_threshold: f32,
+        #[serde(default = "default_isotopic_models")]
+        isotopic_models: Vec<IsotopicModels>,
+    },
+    ExtractFeatures {
+        min_length: usize,
+        maximum_gap_size: f64,
+        #[serde(default = "default_error_tolerance")]
+        error_tolerance: Tolerance,
+    },
+    DeconvolveFeatures {
+        min_length: usize,
+        maximum_gap_size: f64,
+        score_threshold: f32,
+        #[serde(default = "default_isotopic_models")]
+        isotopic_models: Vec<IsotopicModels>,
+        #[serde(default = "default_error_tolerance")]
+        error_tolerance: Tolerance,
+    },
+}
+
+/// An ordered list of [`ProcessingStep`]s, serialized as `{"steps": [{"step": "pickPeaks", ...}]}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcessingPipeline {
+    pub steps: Vec<ProcessingStep>,
+}
+
+impl ProcessingPipeline {
+    pub fn new(steps: Vec<ProcessingStep>) -> Self {
+        Self { steps }
+    }
+
+    pub fn push(&mut self, step: ProcessingStep) -> &mut Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn process_spectrum(&self, spectrum: &mut PipelineSpectrum) -> Result<(), String> {
+        for step in self.steps.iter() {
+            match step {
+                ProcessingStep::Reprofile { dx, fwhm } => reprofile_spectrum(spectrum, *dx, *fwhm)?,
+                ProcessingStep::Denoise { scale } => {
+                    if *scale > 0.0 {
+                        denoise_spectrum(spectrum, *scale)?
+                    }
+                }
+                ProcessingStep::PickPeaks { signal_to_noise } => {
+                    pick_spectrum_peaks(spectrum, *signal_to_noise)?
+                }
+                ProcessingStep::Deconvolve {
+                    score_threshold,
+                    isotopic_models,
+                } => {
+                    if spectrum.deconvoluted_peaks.is_none() {
+                        deconvolve_spectrum(spectrum, *score_threshold, isotopic_models)?
+                    }
+                }
+                ProcessingStep::ExtractFeatures { .. }
+                | ProcessingStep::DeconvolveFeatures { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn process_frame(&self, frame: &mut PipelineFrame) -> Result<(), String> {
+        for step in self.steps.iter() {
+            match step {
+                ProcessingStep::ExtractFeatures {
+                    min_length,
+                    maximum_gap_size,
+                    error_tolerance,
+                } => {
+                    if frame.features.is_none() {
+                        extract_frame_features(
+                            frame,
+                            *min_length,
+                            *maximum_gap_size,
+                            *error_tolerance,
+                        )?
+                    }
+                }
+                ProcessingStep::DeconvolveFeatures {
+                    min_length,
+                    maximum_gap_size,
+                    score_threshold,
+                    isotopic_models,
+                    error_tolerance,
+                } => {
+                    if frame.deconvoluted_features.is_none() {
+                        deconvolve_frame_features(
+                            frame,
+                            *min_length,
+                            *maximum_gap_size,
+                            *score_threshold,
+                            isotopic_models,
+                            *error_tolerance,
+                        )?
+                    }
+                }
+                ProcessingStep::Reprofile { .. }
+                | ProcessingStep::Denoise { .. }
+                | ProcessingStep::PickPeaks { .. }
+                | ProcessingStep::Deconvolve { .. } => {}
+            }
+        }
+        Ok(())
+    }
+}
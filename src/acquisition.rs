@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use mzdata::prelude::*;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::pipeline::PipelineSpectrum;
+
+/// A precursor spectrum and the product spectra acquired from it, by spectrum index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexGroup {
+    pub precursor: Option<usize>,
+    pub products: Vec<usize>,
+}
+
+/// Gather `(key, value)` pairs with keys below `n` into one flat array of values ordered
+/// by key, returning the offset of each key's values and the values
+fn compact(n: usize, pairs: impl Iterator<Item = (u32, u32)> + Clone) -> (Vec<u32>, Vec<u32>) {
+    let mut offsets = vec![0u32; n + 1];
+    for (key, _) in pairs.clone() {
+        offsets[key as usize + 1] += 1;
+    }
+    for i in 0..n {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut next = offsets.clone();
+    let mut values = vec![0u32; offsets[n] as usize];
+    for (key, value) in pairs {
+        values[next[key as usize] as usize] = value;
+        next[key as usize] += 1;
+    }
+    (offsets, values)
+}
+
+/// The precursor-product tree of every spectrum in a run, from MS1 spectra through
+/// MS2, MS3 and supplemental activation spectra, so that acquisition groups can be
+/// looked up without re-reading the run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AcquisitionTree {
+    ms_levels: Vec<u8>,
+    /// The parent of each spectrum, or `u32::MAX` for spectra without one
+    parents: Vec<u32>,
+    /// The topmost ancestor of each spectrum, itself for spectra without a parent
+    roots: Vec<u32>,
+    child_offsets: Vec<u32>,
+    children: Vec<u32>,
+    /// The members of the tree under each root, the root first and the rest in index order
+    group_offsets: Vec<u32>,
+    group_members: Vec<u32>,
+}
+
+const NO_PARENT: u32 = u32::MAX;
+
+impl AcquisitionTree {
+    /// Build the tree from every spectrum in a run, in index order. Only the spectrum
+    /// metadata is used.
+    ///
+    /// A spectrum's parent is the spectrum its precursor scan ID names, or failing that,
+    /// the most recent spectrum one MS level up.
+    pub fn from_spectra<I: IntoIterator<Item = PipelineSpectrum>>(spectra: I) -> Self {
+        let mut this = Self::default();
+        let mut index_of_id: HashMap<String, u32> = HashMap::new();
+        let mut last_at_level: Vec<Option<u32>> = Vec::new();
+        for (i, spectrum) in spectra.into_iter().enumerate() {
+            let i = i as u32;
+            let ms_level = spectrum.ms_level();
+            let level = ms_level as usize;
+            let named = spectrum
+                .precursor()
+                .and_then(|p| p.precursor_id.as_deref())
+                .and_then(|id| index_of_id.get(id).copied());
+            let parent = named.or_else(|| {
+                level
+                    .checked_sub(1)
+                    .filter(|up| *up > 0)
+                    .and_then(|up| last_at_level.get(up).copied().flatten())
+            });
+            let root = parent.map(|p| this.roots[p as usize]).unwrap_or(i);
+
+            if last_at_level.len() <= level {
+                last_at_level.resize(level + 1, None);
+            }
+            last_at_level[level] = Some(i);
+            // An MS1 spectrum starts a new cycle, so deeper levels can no longer fall back
+            // to spectra from the previous one
+            if ms_level == 1 {
+                last_at_level.truncate(2);
+            }
+            index_of_id.insert(spectrum.id().to_string(), i);
+
+            this.ms_levels.push(ms_level);
+            this.parents.push(parent.unwrap_or(NO_PARENT));
+            this.roots.push(root);
+        }
+
+        let n = this.parents.len();
+        let edges = this
+            .parents
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| **p != NO_PARENT)
+            .map(|(i, p)| (*p, i as u32));
+        (this.child_offsets, this.children) = compact(n, edges);
+        let members = this.roots.iter().enumerate().map(|(i, r)| (*r, i as u32));
+        (this.group_offsets, this.group_members) = compact(n, members);
+        this
+    }
+
+    pub fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub fn parent_of(&self, index: usize) -> Option<usize> {
+        self.parents
+            .get(index)
+            .filter(|p| **p != NO_PARENT)
+            .map(|p| *p as usize)
+    }
+
+    pub fn root_of(&self, index: usize) -> Option<usize> {
+        self.roots.get(index).map(|r| *r as usize)
+    }
+
+    pub fn children_of(&self, index: usize) -> &[u32] {
+        if index >= self.len() {
+            return &[];
+        }
+        &self.children[self.child_offsets[index] as usize..self.child_offsets[index + 1] as usize]
+    }
+
+    /// The acquisition group the spectrum at `index` belongs to: the MS1 spectrum at the
+    /// root of its tree and every spectrum descended from it. Spectra with no MS1 ancestor
+    /// are grouped with their topmost ancestor as a product and no precursor.
+    pub fn group_of(&self, index: usize) -> Option<IndexGroup> {
+        let root = self.root_of(index)?;
+        let members = &self.group_members
+            [self.group_offsets[root] as usize..self.group_offsets[root + 1] as usize];
+        let members = members.iter().map(|i| *i as usize);
+        if self.ms_levels[root] == 1 {
+            Some(IndexGroup {
+                precursor: Some(root),
+                products: members.skip(1).collect(),
+            })
+        } else {
+            Some(IndexGroup {
+                precursor: None,
+                products: members.collect(),
+            })
+        }
+    }
+}
+
+/// The precursor-product tree of a run, as flat arrays indexed by spectrum index
+#[wasm_bindgen(js_name = "AcquisitionTree")]
+#[derive(Debug, Clone)]
+pub struct WebAcquisitionTree(AcquisitionTree);
+
+impl From<AcquisitionTree> for WebAcquisitionTree {
+    fn from(value: AcquisitionTree) -> Self {
+        Self(value)
+    }
+}
+
+#[wasm_bindgen(js_class = "AcquisitionTree")]
+impl WebAcquisitionTree {
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The parent of each spectrum, -1 for spectra without one
+    #[wasm_bindgen(getter)]
+    pub fn parents(&self) -> Vec<i32> {
+        self.0
+            .parents
+            .iter()
+            .map(|p| if *p == NO_PARENT { -1 } else { *p as i32 })
+            .collect()
+    }
+
+    /// The children of spectrum `i` are `children[childOffsets[i]..childOffsets[i + 1]]`
+    #[wasm_bindgen(getter, js_name = "childOffsets")]
+    pub fn child_offsets(&self) -> Vec<u32> {
+        self.0.child_offsets.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn children(&self) -> Vec<u32> {
+        self.0.children.clone()
+    }
+
+    #[wasm_bindgen(js_name = "parentOf")]
+    pub fn parent_of(&self, index: usize) -> Option<usize> {
+        self.0.parent_of(index)
+    }
+
+    #[wasm_bindgen(js_name = "rootOf")]
+    pub fn root_of(&self, index: usize) -> Option<usize> {
+        self.0.root_of(index)
+    }
+
+    #[wasm_bindgen(js_name = "childrenOf")]
+    pub fn children_of(&self, index: usize) -> Vec<u32> {
+        self.0.children_of(index).to_vec()
+    }
+
+    #[wasm_bindgen(js_name = "msLevelOf")]
+    pub fn ms_level_of(&self, index: usize) -> Option<u8> {
+        self.0.ms_levels.get(index).copied()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(&self.0).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::TestSpectrum;
+
+    fn spectrum(index: usize, ms_level: u8, precursor_id: Option<usize>) -> PipelineSpectrum {
+        let spectrum = TestSpectrum::new(index, ms_level);
+        match precursor_id {
+            Some(i) => spectrum.precursor_of(i),
+            None => spectrum,
+        }
+        .build()
+    }
+
+    #[test]
+    fn test_compact() {
+        let pairs = [(2, 5), (0, 1), (2, 6), (0, 7)];
+        let (offsets, values) = compact(3, pairs.into_iter());
+        assert_eq!(offsets, [0, 2, 2, 4]);
+        assert_eq!(values, [1, 7, 5, 6]);
+    }
+
+    #[test]
+    fn test_ms3_chain() {
+        let tree = AcquisitionTree::from_spectra([
+            spectrum(0, 1, None),
+            spectrum(1, 2, Some(0)),
+            spectrum(2, 3, Some(1)),
+            spectrum(3, 2, Some(0)),
+            // No precursor ID, so the most recent MS2 is the parent
+            spectrum(4, 3, None),
+            spectrum(5, 1, None),
+            // Names a precursor that was never read, so the most recent MS1 is the parent
+            spectrum(6, 2, Some(100)),
+        ]);
+        assert_eq!(tree.len(), 7);
+        let parents: Vec<_> = (0..7).map(|i| tree.parent_of(i)).collect();
+        assert_eq!(
+            parents,
+            [None, Some(0), Some(1), Some(0), Some(3), None, Some(5)]
+        );
+        assert_eq!(tree.children_of(0), [1, 3]);
+        assert_eq!(tree.children_of(3), [4]);
+        assert_eq!(tree.root_of(4), Some(0));
+
+        let group = IndexGroup {
+            precursor: Some(0),
+            products: vec![1, 2, 3, 4],
+        };
+        for i in 0..5 {
+            assert_eq!(tree.group_of(i).as_ref(), Some(&group));
+        }
+        assert_eq!(
+            tree.group_of(6),
+            Some(IndexGroup {
+                precursor: Some(5),
+                products: vec![6],
+            })
+        );
+        assert_eq!(tree.group_of(7), None);
+    }
+
+    #[test]
+    fn test_orphan_msn() {
+        let tree = AcquisitionTree::from_spectra([
+            spectrum(0, 2, None),
+            spectrum(1, 3, None),
+            spectrum(2, 1, None),
+            // The MS2 this would fall back to belongs to the previous cycle
+            spectrum(3, 3, None),
+        ]);
+        assert_eq!(tree.parent_of(1), Some(0));
+        assert_eq!(
+            tree.group_of(1),
+            Some(IndexGroup {
+                precursor: None,
+                products: vec![0, 1],
+            })
+        );
+        assert_eq!(tree.parent_of(3), None);
+        assert_eq!(
+            tree.group_of(3),
+            Some(IndexGroup {
+                precursor: None,
+                products: vec![3],
+            })
+        );
+        assert_eq!(
+            tree.group_of(2),
+            Some(IndexGroup {
+                precursor: Some(2),
+                products: vec![],
+            })
+        );
+    }
+}
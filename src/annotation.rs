@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+
+use chemical_elements::ChemicalComposition;
+use mzdata::utils::mass_charge_ratio;
+use mzpeaks::{prelude::*, Tolerance};
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{WebSpectrum, WebTolerance};
+
+pub(crate) fn formula_mass(formula: &str) -> Result<f64, String> {
+    let composition: ChemicalComposition = formula
+        .parse()
+        .map_err(|e| format!("Failed to parse formula {formula}: {e:?}"))?;
+    Ok(composition.mass())
+}
+
+fn residue_formula(residue: char) -> Option<&'static str> {
+    let formula = match residue {
+        'G' => "C2H3NO",
+        'A' => "C3H5NO",
+        'S' => "C3H5NO2",
+        'P' => "C5H7NO",
+        'V' => "C5H9NO",
+        'T' => "C4H7NO2",
+        'C' => "C3H5NOS",
+        'L' => "C6H11NO",
+        'I' => "C6H11NO",
+        'N' => "C4H6N2O2",
+        'D' => "C4H5NO3",
+        'Q' => "C5H8N2O2",
+        'K' => "C6H12N2O",
+        'E' => "C5H7NO3",
+        'M' => "C5H9NOS",
+        'H' => "C6H7N3O",
+        'F' => "C9H9NO",
+        'R' => "C6H12N4O",
+        'Y' => "C9H9NO2",
+        'W' => "C11H10N2O",
+        'U' => "C3H5NOSe",
+        'O' => "C12H19N3O2",
+        _ => return None,
+    };
+    Some(formula)
+}
+
+#[wasm_bindgen(js_name = "FragmentIonType")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebFragmentIonType {
+    A,
+    B,
+    C,
+    X,
+    Y,
+    Z,
+}
+
+impl WebFragmentIonType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+            Self::C => "c",
+            Self::X => "x",
+            Self::Y => "y",
+            Self::Z => "z",
+        }
+    }
+
+    fn is_n_terminal(&self) -> bool {
+        matches!(self, Self::A | Self::B | Self::C)
+    }
+
+    /// The formula added to (or removed from) the summed residue composition for this series
+    fn terminal_offset(&self) -> Result<f64, String> {
+        let water = formula_mass("H2O")?;
+        let offset = match self {
+            Self::A => -formula_mass("CO")?,
+            Self::B => 0.0,
+            Self::C => formula_mass("NH3")?,
+            Self::X => water + formula_mass("CO")? - formula_mass("H2")?,
+            Self::Y => water,
+            // The z• radical ion
+            Self::Z => water - formula_mass("NH3")? + formula_mass("H")?,
+        };
+        Ok(offset)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Residue {
+    symbol: char,
+    mass: f64,
+}
+
+/// A peptide sequence with mass-delta modifications.
+///
+/// Modifications are written in brackets after the residue they modify, e.g.
+/// `PEPTM[15.9949]IDEK`. A bracketed delta before the first residue modifies the
+/// N-terminus, and one at the very end after a `-` modifies the C-terminus, e.g.
+/// `[42.0106]-PEPTIDE-[-0.9840]`. A `-` anywhere else is an error.
+#[wasm_bindgen(js_name = "Peptide")]
+#[derive(Debug, Clone)]
+pub struct WebPeptide {
+    residues: Vec<Residue>,
+    n_term: f64,
+    c_term: f64,
+}
+
+impl WebPeptide {
+    pub fn parse(
+        sequence: &str,
+        fixed_modifications: &HashMap<String, f64>,
+    ) -> Result<Self, String> {
+        let mut residues: Vec<Residue> = Vec::new();
+        let mut n_term = 0.0;
+        let mut c_term = 0.0;
+        let mut chars = sequence.chars();
+        let mut seen_n_term_mod = false;
+        let mut seen_c_term_sep = false;
+        let mut seen_c_term_mod = false;
+        while let Some(c) = chars.next() {
+            if seen_c_term_mod && !c.is_whitespace() {
+                return Err(format!(
+                    "Unexpected character {c} after the C-terminal modification"
+                ));
+            }
+            match c {
+                '[' => {
+                    let mut buf = String::new();
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        buf.push(c);
+                    }
+                    let delta: f64 = buf
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Failed to parse modification {buf}: {e}"))?;
+                    if seen_c_term_sep {
+                        c_term += delta;
+                        seen_c_term_mod = true;
+                    } else if let Some(last) = residues.last_mut() {
+                        last.mass += delta;
+                    } else {
+                        n_term += delta;
+                        seen_n_term_mod = true;
+                    }
+                }
+                '-' if residues.is_empty() && seen_n_term_mod => {}
+                '-' if !residues.is_empty() && !seen_c_term_sep => {
+                    seen_c_term_sep = true;
+                }
+                '-' => {
+                    return Err(
+                        "A - may only follow an N-terminal or precede a C-terminal modification"
+                            .to_string(),
+                    )
+                }
+                c if c.is_ascii_alphabetic() && seen_c_term_sep => {
+                    return Err(format!(
+                        "Unexpected residue {c} after the C-terminal separator"
+                    ));
+                }
+                c if c.is_ascii_alphabetic() => {
+                    let c = c.to_ascii_uppercase();
+                    let formula = residue_formula(c)
+                        .ok_or_else(|| format!("Unknown amino acid residue {c}"))?;
+                    let mut mass = formula_mass(formula)?;
+                    if let Some(delta) = fixed_modifications.get(&c.to_string()) {
+                        mass += delta;
+                    }
+                    residues.push(Residue { symbol: c, mass });
+                }
+                c if c.is_whitespace() => {}
+                c => return Err(format!("Unexpected character {c} in peptide sequence")),
+            }
+        }
+        if seen_c_term_sep && !seen_c_term_mod {
+            return Err("Missing C-terminal modification after -".to_string());
+        }
+        if let Some(delta) = fixed_modifications.get("N-term") {
+            n_term += delta;
+        }
+        if let Some(delta) = fixed_modifications.get("C-term") {
+            c_term += delta;
+        }
+        Ok(Self {
+            residues,
+            n_term,
+            c_term,
+        })
+    }
+
+    pub fn theoretical_fragments(
+        &self,
+        ion_types: &[WebFragmentIonType],
+        neutral_losses: &[(String, f64)],
+        max_charge: i32,
+    ) -> Result<Vec<WebTheoreticalFragment>, String> {
+        let n = self.residues.len();
+        let mut fragments = Vec::new();
+        if n < 2 {
+            return Ok(fragments);
+        }
+        let mut losses = vec![(String::new(), 0.0)];
+        losses.extend(neutral_losses.iter().cloned());
+
+        for ion_type in ion_types {
+            let offset = ion_type.terminal_offset()?;
+            for i in 1..n {
+                let (residues, terminal) = if ion_type.is_n_terminal() {
+                    (&self.residues[..i], self.n_term)
+                } else {
+                    (&self.residues[n - i..], self.c_term)
+                };
+                let base_mass: f64 =
+                    residues.iter().map(|r| r.mass).sum::<f64>() + terminal + offset;
+                for (loss_name, loss_mass) in losses.iter() {
+                    let neutral_mass = base_mass - loss_mass;
+                    for z in 1..=max_charge.max(1) {
+                        fragments.push(WebTheoreticalFragment {
+                            ion_type: *ion_type,
+                            series_index: i,
+                            charge: z,
+                            neutral_loss: if loss_name.is_empty() {
+                                None
+                            } else {
+                                Some(loss_name.clone())
+                            },
+                            neutral_mass,
+                            mz: mass_charge_ratio(neutral_mass, z),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(fragments)
+    }
+
+    pub fn annotate_spectrum(
+        &self,
+        spectrum: &WebSpectrum,
+        ion_types: &[WebFragmentIonType],
+        neutral_losses: &[(String, f64)],
+        max_charge: i32,
+        error_tolerance: Tolerance,
+    ) -> Result<Vec<WebFragmentMatch>, String> {
+        let fragments = self.theoretical_fragments(ion_types, neutral_losses, max_charge)?;
+        let spectrum = spectrum.decoded();
+        let mut matches = Vec::new();
+        if let Some(peaks) = spectrum.deconvoluted_peaks.as_ref() {
+            for frag in fragments {
+                let hit = peaks
+                    .all_peaks_for(frag.neutral_mass, error_tolerance)
+                    .iter()
+                    .filter(|p| p.charge == frag.charge)
+                    .max_by(|a, b| a.intensity.total_cmp(&b.intensity));
+                if let Some(peak) = hit {
+                    matches.push(WebFragmentMatch::new(
+                        frag,
+                        peak.mz(),
+                        peak.neutral_mass,
+                        peak.intensity,
+                    ));
+                }
+            }
+        } else if let Some(peaks) = spectrum.peaks.as_ref() {
+            for frag in fragments {
+                let hit = peaks
+                    .all_peaks_for(frag.mz, error_tolerance)
+                    .iter()
+                    .max_by(|a, b| a.intensity.total_cmp(&b.intensity));
+                if let Some(peak) = hit {
+                    let neutral_mass = mzdata::utils::neutral_mass(peak.mz, frag.charge);
+                    matches.push(WebFragmentMatch::new(
+                        frag,
+                        peak.mz,
+                        neutral_mass,
+                        peak.intensity,
+                    ));
+                }
+            }
+        }
+        Ok(matches)
+    }
+}
+
+fn parse_neutral_losses(neutral_losses: Option<Vec<String>>) -> Result<Vec<(String, f64)>, String> {
+    neutral_losses
+        .unwrap_or_default()
+        .into_iter()
+        .map(|formula| formula_mass(&formula).map(|mass| (formula, mass)))
+        .collect()
+}
+
+#[wasm_bindgen(js_class = "Peptide")]
+impl WebPeptide {
+    /// Parse a peptide sequence, applying `fixedModifications`, an object mapping a
+    /// residue symbol (or `N-term`/`C-term`) to a mass delta.
+    #[wasm_bindgen(constructor)]
+    pub fn new(sequence: &str, fixed_modifications: JsValue) -> Result<WebPeptide, JsError> {
+        let fixed_modifications: HashMap<String, f64> =
+            if fixed_modifications.is_undefined() || fixed_modifications.is_null() {
+                HashMap::new()
+            } else {
+                serde_wasm_bindgen::from_value(fixed_modifications)
+                    .map_err(|e| JsError::new(&e.to_string()))?
+            };
+        Self::parse(sequence, &fixed_modifications).map_err(|e| JsError::new(&e))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sequence(&self) -> String {
+        self.residues.iter().map(|r| r.symbol).collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.residues.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = "neutralMass")]
+    pub fn neutral_mass(&self) -> Result<f64, JsError> {
+        let water = formula_mass("H2O").map_err(|e| JsError::new(&e))?;
+        Ok(self.residues.iter().map(|r| r.mass).sum::<f64>() + self.n_term + self.c_term + water)
+    }
+
+    /// Compute the theoretical fragments of `ionTypes`, optionally also losing each of
+    /// `neutralLosses` (given as chemical formulae like `H2O` or `H3PO4`), up to `maxCharge`.
+    pub fn fragments(
+        &self,
+        ion_types: Vec<WebFragmentIonType>,
+        neutral_losses: Option<Vec<String>>,
+        max_charge: Option<i32>,
+    ) -> Result<Vec<WebTheoreticalFragment>, JsError> {
+        let neutral_losses = parse_neutral_losses(neutral_losses).map_err(|e| JsError::new(&e))?;
+        self.theoretical_fragments(&ion_types, &neutral_losses, max_charge.unwrap_or(1))
+            .map_err(|e| JsError::new(&e))
+    }
+
+    /// Match the theoretical fragments against `spectrum`'s deconvoluted peaks if present,
+    /// otherwise its centroid peaks.
+    pub fn annotate(
+        &self,
+        spectrum: &WebSpectrum,
+        ion_types: Vec<WebFragmentIonType>,
+        neutral_losses: Option<Vec<String>>,
+        max_charge: Option<i32>,
+        error_tolerance: Option<WebTolerance>,
+    ) -> Result<Vec<WebFragmentMatch>, JsError> {
+        let neutral_losses = parse_neutral_losses(neutral_losses).map_err(|e| JsError::new(&e))?;
+        let max_charge = max_charge.unwrap_or_else(|| {
+            spectrum
+                .as_ref()
+                .description()
+                .precursor
+                .as_ref()
+                .and_then(|p| p.ions.first())
+                .and_then(|i| i.charge)
+                .map(|z| (z.abs() - 1).max(1))
+                .unwrap_or(1)
+        });
+        let error_tolerance = error_tolerance
+            .map(Tolerance::from)
+            .unwrap_or(Tolerance::PPM(20.0));
+        self.annotate_spectrum(
+            spectrum,
+            &ion_types,
+            &neutral_losses,
+            max_charge,
+            error_tolerance,
+        )
+        .map_err(|e| JsError::new(&e))
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "TheoreticalFragment")]
+#[derive(Debug, Clone)]
+pub struct WebTheoreticalFragment {
+    #[wasm_bindgen(js_name = "ionType")]
+    pub ion_type: WebFragmentIonType,
+    #[wasm_bindgen(js_name = "seriesIndex")]
+    pub series_index: usize,
+    pub charge: i32,
+    #[wasm_bindgen(js_name = "neutralLoss")]
+    pub neutral_loss: Option<String>,
+    #[wasm_bindgen(js_name = "neutralMass")]
+    pub neutral_mass: f64,
+    pub mz: f64,
+}
+
+#[wasm_bindgen(js_class = "TheoreticalFragment")]
+impl WebTheoreticalFragment {
+    /// A conventional label like `b4`, `y7-H2O` or `y10^2+`
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> String {
+        let mut label = format!("{}{}", self.ion_type.label(), self.series_index);
+        if let Some(loss) = self.neutral_loss.as_ref() {
+            label.push('-');
+            label.push_str(loss);
+        }
+        if self.charge > 1 {
+            label.push_str(&format!("^{}+", self.charge));
+        }
+        label
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "FragmentMatch")]
+#[derive(Debug, Clone)]
+pub struct WebFragmentMatch {
+    pub fragment: WebTheoreticalFragment,
+    #[wasm_bindgen(js_name = "observedMz")]
+    pub observed_mz: f64,
+    #[wasm_bindgen(js_name = "observedNeutralMass")]
+    pub observed_neutral_mass: f64,
+    pub intensity: f32,
+    #[wasm_bindgen(js_name = "massErrorPPM")]
+    pub mass_error_ppm: f64,
+    #[wasm_bindgen(js_name = "massErrorDa")]
+    pub mass_error_da: f64,
+}
+
+impl WebFragmentMatch {
+    fn new(
+        fragment: WebTheoreticalFragment,
+        observed_mz: f64,
+        observed_neutral_mass: f64,
+        intensity: f32,
+    ) -> Self {
+        let mass_error_da = observed_neutral_mass - fragment.neutral_mass;
+        let mass_error_ppm = mass_error_da / fragment.neutral_mass * 1e6;
+        Self {
+            fragment,
+            observed_mz,
+            observed_neutral_mass,
+            intensity,
+            mass_error_ppm,
+            mass_error_da,
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "FragmentMatch")]
+impl WebFragmentMatch {
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> String {
+        self.fragment.label()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peptide(sequence: &str) -> Result<WebPeptide, String> {
+        WebPeptide::parse(sequence, &HashMap::new())
+    }
+
+    /// The singly charged m/z of each fragment of `ion_type`, in series order
+    fn ladder(peptide: &WebPeptide, ion_type: WebFragmentIonType) -> Vec<f64> {
+        peptide
+            .theoretical_fragments(&[ion_type], &[], 1)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.mz)
+            .collect()
+    }
+
+    fn assert_masses(observed: &[f64], expected: &[f64]) {
+        assert_eq!(observed.len(), expected.len());
+        for (o, e) in observed.iter().zip(expected) {
+            assert!((o - e).abs() < 1e-3, "{observed:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn test_fragment_ladders() {
+        let peptide = peptide("PEPTIDE").unwrap();
+        let mass = peptide.residues.iter().map(|r| r.mass).sum::<f64>();
+        assert!((mass + formula_mass("H2O").unwrap() - 799.3600).abs() < 1e-3);
+        let b = [98.0600, 227.1026, 324.1554, 425.2031, 538.2871, 653.3141];
+        let y = [148.0604, 263.0874, 376.1714, 477.2191, 574.2719, 703.3145];
+        let c = [115.0866, 244.1292, 341.1819, 442.2296, 555.3137, 670.3406];
+        let z = [132.0417, 247.0687, 360.1527, 461.2004, 558.2532, 687.2958];
+        assert_masses(&ladder(&peptide, WebFragmentIonType::B), &b);
+        assert_masses(&ladder(&peptide, WebFragmentIonType::Y), &y);
+        assert_masses(&ladder(&peptide, WebFragmentIonType::C), &c);
+        assert_masses(&ladder(&peptide, WebFragmentIonType::Z), &z);
+
+        let fragments = peptide
+            .theoretical_fragments(&[WebFragmentIonType::Y], &[], 2)
+            .unwrap();
+        let y2 = fragments
+            .iter()
+            .find(|f| f.series_index == 2 && f.charge == 2)
+            .unwrap();
+        assert_eq!(y2.label(), "y2^2+");
+        assert!((y2.mz - 132.0473).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_modifications() {
+        let peptide = peptide("[42.010565]-PM[15.9949]EPTIDE-[-0.984016]").unwrap();
+        assert_eq!(peptide.sequence(), "PMEPTIDE");
+        assert!((peptide.n_term - 42.010565).abs() < 1e-9);
+        assert!((peptide.c_term + 0.984016).abs() < 1e-9);
+        let methionine = formula_mass("C5H9NOS").unwrap();
+        assert!((peptide.residues[1].mass - methionine - 15.9949).abs() < 1e-9);
+        // The N-terminal modification shifts the b ions, the C-terminal one the y ions
+        let b = ladder(&peptide, WebFragmentIonType::B);
+        assert!((b[0] - (98.0600 + 42.010565)).abs() < 1e-3);
+        let y = ladder(&peptide, WebFragmentIonType::Y);
+        assert!((y[0] - (148.0604 - 0.984016)).abs() < 1e-3);
+
+        let mut fixed = HashMap::new();
+        fixed.insert("C".to_string(), 57.021464);
+        fixed.insert("N-term".to_string(), 1.0);
+        let peptide = WebPeptide::parse("ACK", &fixed).unwrap();
+        let cysteine = formula_mass("C3H5NOS").unwrap();
+        assert!((peptide.residues[1].mass - cysteine - 57.021464).abs() < 1e-9);
+        assert_eq!(peptide.n_term, 1.0);
+    }
+
+    #[test]
+    fn test_malformed_sequences() {
+        for sequence in [
+            "PEP-TIDE",
+            "-PEPTIDE",
+            "PEPTIDE-",
+            "PEPTIDE-[1.0]K",
+            "PEPTIDE--[1.0]",
+            "PEPT[x]IDE",
+            "PEPTIDB",
+            "PEP*TIDE",
+        ] {
+            assert!(peptide(sequence).is_err(), "{sequence} parsed");
+        }
+    }
+}
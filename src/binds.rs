@@ -1,15 +1,19 @@
+use std::borrow::Cow;
+
 use js_sys::{Array, Float32Array, Float64Array, Int32Array, Object, Reflect, Uint8Array};
 use log::{self, info};
 
 use mzdata::{
+    io::DetailLevel,
     prelude::{ByteArrayView, IonMobilityFrameLike, IonProperties, SpectrumLike},
     spectrum::{
-        bindata::ArrayRetrievalError, Activation, ArrayType, BinaryArrayMap,
-        MultiLayerIonMobilityFrame, MultiLayerSpectrum, PeakDataLevel, RefPeakDataLevel, ScanEvent,
+        bindata::{ArrayRetrievalError, BinaryCompressionType},
+        Activation, ArrayType, BinaryArrayMap, DissociationMethodTerm, MultiLayerIonMobilityFrame,
+        MultiLayerSpectrum, PeakDataLevel, RefPeakDataLevel, ScanEvent,
     },
     utils::mass_charge_ratio,
 };
-use mzdeisotope_map::{solution::DeconvolvedSolutionFeature, FeatureSearchParams};
+use mzdeisotope_map::solution::DeconvolvedSolutionFeature;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
@@ -17,22 +21,26 @@ use mzpeaks::{
     feature::{Feature, SimpleFeature},
     peak::MZPoint,
     prelude::*,
-    CentroidPeak, DeconvolutedPeak, IonMobility, Time, Tolerance, MZ,
+    CentroidPeak, DeconvolutedPeak, IonMobility, MZPeakSetType, MassPeakSetType, Time, Tolerance,
+    MZ,
 };
 
-use mzsignal::feature_statistics::{FeatureTransform, FitPeaksOn, MultiPeakShapeFit};
-
-use mzdeisotope::{
-    deconvolute_peaks,
-    isotopic_model::{IsotopicModels, IsotopicPatternParams},
-    scorer::{MaximizingFitFilter, PenalizedMSDeconvScorer},
-    DeconvolvedSolutionPeak, IsotopicModelLike,
+use mzsignal::feature_statistics::{
+    FeatureTransform, FitPeaksOn, PeakFitArgs, PeakShape, PeakShapeModel,
 };
 
+use mzdeisotope::{isotopic_model::IsotopicModels, DeconvolvedSolutionPeak};
+
+use crate::cv;
+use crate::pipeline;
+
 use mzdata::{
-    params::{Param, ParamDescribed, ParamLike, ValueRef},
+    params::{
+        ControlledVocabulary, Param, ParamDescribed, ParamLike, Unit, Value, ValueRef, CURIE,
+    },
     spectrum::{
-        IsolationWindow, Precursor, ScanWindow, SelectedIon, SignalContinuity, SpectrumDescription,
+        IsolationWindow, Precursor, ScanPolarity, ScanWindow, SelectedIon, SignalContinuity,
+        SpectrumDescription,
     },
 };
 
@@ -82,6 +90,18 @@ pub fn array_map_to_js(arrays: &BinaryArrayMap) -> Result<Object, ArrayRetrieval
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct WebTolerance(Tolerance);
 
+impl From<WebTolerance> for Tolerance {
+    fn from(value: WebTolerance) -> Self {
+        value.0
+    }
+}
+
+impl From<Tolerance> for WebTolerance {
+    fn from(value: Tolerance) -> Self {
+        Self(value)
+    }
+}
+
 #[wasm_bindgen(js_class = "Tolerance")]
 impl WebTolerance {
     pub fn ppm(value: f64) -> WebTolerance {
@@ -213,8 +233,66 @@ impl From<&DeconvolvedSolutionPeak> for SimpleWebChargedPeak {
 #[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WebParam(Param);
 
+/// Convert a JS string, number, boolean or `null` into a parameter value
+pub(crate) fn param_value_from_js(value: &JsValue) -> Result<Value, JsError> {
+    let value = if value.is_null() || value.is_undefined() {
+        Value::Empty
+    } else if let Some(x) = value.as_bool() {
+        Value::Boolean(x)
+    } else if let Some(x) = value.as_f64() {
+        if x.fract() == 0.0 && x.abs() < i64::MAX as f64 {
+            Value::Int(x as i64)
+        } else {
+            Value::Float(x)
+        }
+    } else if let Some(x) = value.as_string() {
+        Value::String(x)
+    } else {
+        return Err(JsError::new(&format!(
+            "Parameter values must be strings, numbers or booleans, got {value:?}"
+        )));
+    };
+    Ok(value)
+}
+
+impl WebParam {
+    pub fn as_param(&self) -> &Param {
+        &self.0
+    }
+}
+
+impl From<Param> for WebParam {
+    fn from(value: Param) -> Self {
+        Self(value)
+    }
+}
+
+impl From<WebParam> for Param {
+    fn from(value: WebParam) -> Self {
+        value.0
+    }
+}
+
 #[wasm_bindgen(js_class = "Param")]
 impl WebParam {
+    /// Create a parameter, optionally controlled by `accession` written as a CURIE like `MS:1000511`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        name: String,
+        value: JsValue,
+        accession: Option<String>,
+    ) -> Result<WebParam, JsError> {
+        let mut param = Param::new_key_value(name, param_value_from_js(&value)?);
+        if let Some(accession) = accession {
+            let curie: CURIE = accession
+                .parse()
+                .map_err(|e| JsError::new(&format!("Invalid accession {accession}: {e:?}")))?;
+            param.controlled_vocabulary = Some(curie.controlled_vocabulary);
+            param.accession = Some(curie.accession);
+        }
+        Ok(Self(param))
+    }
+
     #[wasm_bindgen(getter)]
     pub fn name(&self) -> String {
         self.0.name().to_string()
@@ -349,9 +427,96 @@ impl WebScanWindow {
     }
 }
 
+/// The families of mass analyzer the typed scan accessors distinguish
+#[wasm_bindgen(js_name = "MassAnalyzer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WebMassAnalyzer {
+    #[default]
+    Unknown,
+    Orbitrap,
+    IonTrap,
+    TimeOfFlight,
+    Quadrupole,
+    FTICR,
+    MagneticSector,
+}
+
+const MASS_ANALYZER_TERMS: [(&str, WebMassAnalyzer); 6] = [
+    ("MS:1000484", WebMassAnalyzer::Orbitrap),
+    ("MS:1000264", WebMassAnalyzer::IonTrap),
+    ("MS:1000084", WebMassAnalyzer::TimeOfFlight),
+    ("MS:1000081", WebMassAnalyzer::Quadrupole),
+    ("MS:1000079", WebMassAnalyzer::FTICR),
+    ("MS:1000080", WebMassAnalyzer::MagneticSector),
+];
+
+impl WebMassAnalyzer {
+    /// Classify a mass analyzer type term, given by accession or name
+    pub fn from_term(term: &str) -> Self {
+        MASS_ANALYZER_TERMS
+            .iter()
+            .find(|(parent, _)| cv::term_is_a(term, parent))
+            .map(|(_, kind)| *kind)
+            .unwrap_or_default()
+    }
+
+    /// Guess the analyzer from the leading token of a Thermo filter string. Astral
+    /// (`ASTMS`) scans have no matching kind and are `Unknown`.
+    pub fn from_filter_string(filter: &str) -> Self {
+        match filter.split_whitespace().next() {
+            Some("FTMS") => Self::Orbitrap,
+            Some("ITMS") => Self::IonTrap,
+            _ => Self::Unknown,
+        }
+    }
+}
+
 #[wasm_bindgen(js_name = "ScanEvent")]
 pub struct WebScanEvent(ScanEvent);
 
+impl WebScanEvent {
+    fn ms_param_value(&self, accessions: &[u32]) -> Option<f64> {
+        find_ms_param(self.0.params(), accessions).and_then(|p| p.to_f64().ok())
+    }
+}
+
+/// Find the first PSI-MS parameter with one of `accessions`, in order of preference
+fn find_ms_param<'a>(params: &'a [Param], accessions: &[u32]) -> Option<&'a Param> {
+    accessions.iter().find_map(|acc| {
+        params.iter().find(|p| {
+            p.controlled_vocabulary == Some(ControlledVocabulary::MS) && p.accession == Some(*acc)
+        })
+    })
+}
+
+const MASS_RESOLVING_POWER: [u32; 1] = [1000800];
+const SCAN_ION_MOBILITY: [u32; 2] = [1002815, 1002476];
+const FAIMS_COMPENSATION_VOLTAGE: [u32; 1] = [1001581];
+
+fn scan_compensation_voltage(event: &ScanEvent) -> Option<f64> {
+    find_ms_param(event.params(), &FAIMS_COMPENSATION_VOLTAGE)
+        .and_then(|p| p.to_f64().ok())
+        .or_else(|| {
+            event.filter_string().and_then(|f| {
+                f.split_whitespace()
+                    .find_map(|tok| tok.strip_prefix("cv="))
+                    .and_then(|v| v.parse().ok())
+            })
+        })
+}
+
+/// The FAIMS compensation voltage of a spectrum, from its first scan event or its own parameters
+pub(crate) fn compensation_voltage(description: &SpectrumDescription) -> Option<f64> {
+    description
+        .acquisition
+        .first_scan()
+        .and_then(scan_compensation_voltage)
+        .or_else(|| {
+            find_ms_param(&description.params, &FAIMS_COMPENSATION_VOLTAGE)
+                .and_then(|p| p.to_f64().ok())
+        })
+}
+
 #[wasm_bindgen(js_class = "ScanEvent")]
 impl WebScanEvent {
     #[wasm_bindgen(getter, js_name = "startTime")]
@@ -383,6 +548,39 @@ impl WebScanEvent {
         self.0.filter_string().map(|s| s.to_string())
     }
 
+    /// The mass resolving power the scan was acquired at, if recorded
+    #[wasm_bindgen(getter)]
+    pub fn resolution(&self) -> Option<f64> {
+        self.ms_param_value(&MASS_RESOLVING_POWER)
+    }
+
+    /// The FAIMS compensation voltage, from the scan's parameters or its filter string
+    #[wasm_bindgen(getter, js_name = "faimsCompensationVoltage")]
+    pub fn faims_compensation_voltage(&self) -> Option<f64> {
+        scan_compensation_voltage(&self.0)
+    }
+
+    /// The ion mobility the scan was acquired at, as inverse reduced ion mobility or drift time
+    #[wasm_bindgen(getter, js_name = "ionMobility")]
+    pub fn ion_mobility(&self) -> Option<f64> {
+        self.ms_param_value(&SCAN_ION_MOBILITY)
+    }
+
+    #[wasm_bindgen(getter, js_name = "ionMobilityType")]
+    pub fn ion_mobility_type(&self) -> Option<String> {
+        find_ms_param(self.0.params(), &SCAN_ION_MOBILITY).map(|p| p.name().to_string())
+    }
+
+    /// The mass analyzer, as far as it can be told from the filter string. Use
+    /// `MZReader.analyzerFor` to look it up in the file's instrument configurations.
+    #[wasm_bindgen(getter)]
+    pub fn analyzer(&self) -> WebMassAnalyzer {
+        self.0
+            .filter_string()
+            .map(|f| WebMassAnalyzer::from_filter_string(&f))
+            .unwrap_or_default()
+    }
+
     pub fn params(&self) -> Vec<WebParam> {
         self.0
             .params()
@@ -392,12 +590,40 @@ impl WebScanEvent {
     }
 }
 
+/// A selected precursor ion. Ions read from a precursor are copies, so edit a spectrum's
+/// ions with its `setPrecursorIon` and related methods.
 #[wasm_bindgen(js_name = "SelectedIon")]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WebSelectedIon(SelectedIon);
 
 #[wasm_bindgen(js_class = "SelectedIon")]
 impl WebSelectedIon {
+    /// Create a selected ion, with an ion mobility of the term `ionMobilityType` if given.
+    /// See [`WebSpectrum::set_precursor_ion_mobility`] for the ion mobility terms.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        mz: f64,
+        intensity: Option<f32>,
+        charge: Option<i32>,
+        ion_mobility: Option<f64>,
+        ion_mobility_type: Option<String>,
+    ) -> Result<WebSelectedIon, JsError> {
+        let mut ion = SelectedIon {
+            mz,
+            intensity: intensity.unwrap_or_default(),
+            charge,
+            ..Default::default()
+        };
+        if ion_mobility.is_some() {
+            let term = ion_mobility_type
+                .as_deref()
+                .map(ion_mobility_term)
+                .transpose()?;
+            set_ion_mobility(&mut ion, ion_mobility, term);
+        }
+        Ok(Self(ion))
+    }
+
     #[wasm_bindgen(getter)]
     pub fn mz(&self) -> f64 {
         self.0.mz
@@ -413,6 +639,18 @@ impl WebSelectedIon {
         self.0.charge
     }
 
+    /// The ion mobility of this ion, from whichever ion mobility term it carries
+    #[wasm_bindgen(getter, js_name = "ionMobility")]
+    pub fn ion_mobility(&self) -> Option<f64> {
+        ion_mobility_param(&self.0).and_then(|p| p.to_f64().ok())
+    }
+
+    /// The name of the ion mobility term, like `inverse reduced ion mobility`
+    #[wasm_bindgen(getter, js_name = "ionMobilityType")]
+    pub fn ion_mobility_type(&self) -> Option<String> {
+        ion_mobility_param(&self.0).map(|p| p.name().to_string())
+    }
+
     pub fn params(&self) -> Vec<WebParam> {
         self.0
             .params()
@@ -421,14 +659,128 @@ impl WebSelectedIon {
             .map(|p| WebParam(p))
             .collect()
     }
+
+    pub fn copy(&self) -> Self {
+        self.clone()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(&self.0).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+type IonMobilityTerm = (u32, &'static str, Unit);
+
+/// The PSI-MS ion mobility terms a selected ion may carry, with their units
+const ION_MOBILITY_TERMS: [IonMobilityTerm; 3] = [
+    (
+        1002815,
+        "inverse reduced ion mobility",
+        Unit::VoltSecondPerSquareCentimeter,
+    ),
+    (1002476, "ion mobility drift time", Unit::Millisecond),
+    (1001581, "FAIMS compensation voltage", Unit::Volt),
+];
+
+/// Look up an ion mobility term by its name or CURIE
+fn ion_mobility_term(kind: &str) -> Result<IonMobilityTerm, JsError> {
+    ION_MOBILITY_TERMS
+        .iter()
+        .copied()
+        .find(|(acc, name, _)| *name == kind || format!("MS:{acc}") == kind)
+        .ok_or_else(|| JsError::new(&format!("{kind} is not an ion mobility term")))
+}
+
+fn is_ion_mobility_param(param: &Param) -> bool {
+    param.controlled_vocabulary == Some(ControlledVocabulary::MS)
+        && ION_MOBILITY_TERMS
+            .iter()
+            .any(|(acc, _, _)| param.accession == Some(*acc))
+}
+
+fn ion_mobility_param(ion: &SelectedIon) -> Option<&Param> {
+    ion.params().iter().find(|p| is_ion_mobility_param(p))
+}
+
+/// Replace the ion mobility of `ion` with `value` as the term `term`, or as the term it
+/// already carries if not given. Setting it to `None` removes it.
+fn set_ion_mobility(ion: &mut SelectedIon, value: Option<f64>, term: Option<IonMobilityTerm>) {
+    let term = term.unwrap_or_else(|| {
+        ion_mobility_param(ion)
+            .and_then(|p| p.accession)
+            .and_then(|acc| {
+                ION_MOBILITY_TERMS
+                    .iter()
+                    .copied()
+                    .find(|(a, _, _)| *a == acc)
+            })
+            .unwrap_or(ION_MOBILITY_TERMS[0])
+    });
+    while let Some(i) = ion.params().iter().position(is_ion_mobility_param) {
+        ion.remove_param(i);
+    }
+    if let Some(value) = value {
+        let (accession, name, unit) = term;
+        ion.add_param(ms_param(accession, name, Value::Float(value), unit));
+    }
+}
+
+fn ms_param(accession: u32, name: &str, value: Value, unit: Unit) -> Param {
+    let mut param = Param::new_key_value(name, value);
+    param.controlled_vocabulary = Some(ControlledVocabulary::MS);
+    param.accession = Some(accession);
+    param.unit = unit;
+    param
+}
+
+/// Look up a dissociation method by its name or CURIE
+fn dissociation_method(term: &str) -> Result<DissociationMethodTerm, JsError> {
+    let found = match term.parse::<CURIE>() {
+        Ok(curie) if curie.controlled_vocabulary == ControlledVocabulary::MS => {
+            DissociationMethodTerm::from_accession(curie.accession)
+        }
+        _ => DissociationMethodTerm::from_name(term),
+    };
+    found.ok_or_else(|| JsError::new(&format!("{term} is not a dissociation method")))
 }
 
+const SUPPLEMENTAL_ENERGY: (u32, &str) = (1002680, "supplemental collision energy");
+
+/// The activation of a precursor. A precursor's activation is a copy, so edit a spectrum's
+/// activation with its `setActivation` and `setActivationEnergy` methods.
 #[wasm_bindgen(js_name = "Activation")]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WebActivation(Activation);
 
 #[wasm_bindgen(js_class = "Activation")]
 impl WebActivation {
+    /// Create an activation with the dissociation method `method`, given by name or CURIE,
+    /// and optionally a supplemental activation with `supplementalMethod` at `supplementalEnergy`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        method: &str,
+        energy: Option<f32>,
+        supplemental_method: Option<String>,
+        supplemental_energy: Option<f32>,
+    ) -> Result<WebActivation, JsError> {
+        let mut activation = Activation::default();
+        activation.methods_mut().push(dissociation_method(method)?);
+        activation.energy = energy.unwrap_or_default();
+        if let Some(method) = supplemental_method {
+            activation.methods_mut().push(dissociation_method(&method)?);
+            if let Some(energy) = supplemental_energy {
+                activation.params.push(ms_param(
+                    SUPPLEMENTAL_ENERGY.0,
+                    SUPPLEMENTAL_ENERGY.1,
+                    Value::Float(energy as f64),
+                    Unit::Electronvolt,
+                ));
+            }
+        }
+        Ok(Self(activation))
+    }
+
     #[wasm_bindgen(getter)]
     pub fn method(&self) -> Option<String> {
         self.0.method().map(|m| m.name().to_string())
@@ -439,6 +791,25 @@ impl WebActivation {
         self.0.energy
     }
 
+    /// The supplemental dissociation method, if any, like `supplemental collision-induced dissociation`
+    #[wasm_bindgen(getter, js_name = "supplementalMethod")]
+    pub fn supplemental_method(&self) -> Option<String> {
+        self.0
+            .methods()
+            .iter()
+            .find(|m| m.name().starts_with("supplemental"))
+            .map(|m| m.name().to_string())
+    }
+
+    #[wasm_bindgen(getter, js_name = "supplementalEnergy")]
+    pub fn supplemental_energy(&self) -> Option<f32> {
+        self.0
+            .params
+            .iter()
+            .find(|p| p.accession == Some(SUPPLEMENTAL_ENERGY.0))
+            .and_then(|p| p.to_f32().ok())
+    }
+
     pub fn methods(&self) -> Vec<WebParam> {
         self.0
             .methods()
@@ -451,6 +822,10 @@ impl WebActivation {
         self.0.params.iter().cloned().map(|p| WebParam(p)).collect()
     }
 
+    pub fn copy(&self) -> Self {
+        self.clone()
+    }
+
     #[wasm_bindgen(js_name = "toJSON")]
     pub fn to_json(&self) -> Result<Object, JsValue> {
         let entries = Array::of3(
@@ -462,38 +837,130 @@ impl WebActivation {
     }
 }
 
-#[wasm_bindgen(getter_with_clone, inspectable, js_name = "Precursor")]
+/// A precursor ion description. A spectrum's `precursor` is a copy, as are the `ions`,
+/// `isolationWindow` and `activation` of a precursor, so they are read-only. Edit a spectrum's
+/// precursor in place with its `setPrecursorIon`, `setActivation` and related methods, or
+/// replace it by assigning a new precursor to its `precursor`.
+#[wasm_bindgen(js_name = "Precursor")]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct WebPrecursor {
-    _inner: Precursor,
-    pub ions: Vec<WebSelectedIon>,
-    #[wasm_bindgen(js_name = "isolationWindow")]
-    pub isolation_window: WebIsolationWindow,
-    pub activation: WebActivation,
+    inner: Precursor,
+}
+
+impl WebPrecursor {
+    pub fn to_precursor(&self) -> Precursor {
+        self.inner.clone()
+    }
+}
+
+impl From<Precursor> for WebPrecursor {
+    fn from(value: Precursor) -> Self {
+        Self { inner: value }
+    }
 }
 
 #[wasm_bindgen(js_class = "Precursor")]
 impl WebPrecursor {
-    fn new(precursor: Precursor) -> Self {
-        let ions = precursor
+    /// Create a precursor with a single selected ion, isolated from the spectrum
+    /// `precursorScanID` if given
+    #[wasm_bindgen(constructor)]
+    pub fn create(
+        mz: f64,
+        charge: Option<i32>,
+        intensity: Option<f32>,
+        precursor_scan_id: Option<String>,
+    ) -> Self {
+        let mut precursor = Precursor::default();
+        precursor.ions = vec![SelectedIon {
+            mz,
+            intensity: intensity.unwrap_or_default(),
+            charge,
+            ..Default::default()
+        }];
+        precursor.precursor_id = precursor_scan_id;
+        Self::from(precursor)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ions(&self) -> Vec<WebSelectedIon> {
+        self.inner
             .ions
             .iter()
             .cloned()
-            .map(|i| WebSelectedIon(i))
-            .collect();
-        let isolation_window = WebIsolationWindow(precursor.isolation_window.clone());
-        let activation = WebActivation(precursor.activation.clone());
-        Self {
-            _inner: precursor,
-            ions,
-            isolation_window,
-            activation,
-        }
+            .map(WebSelectedIon)
+            .collect()
+    }
+
+    #[wasm_bindgen(getter, js_name = "isolationWindow")]
+    pub fn isolation_window(&self) -> WebIsolationWindow {
+        WebIsolationWindow(self.inner.isolation_window.clone())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn activation(&self) -> WebActivation {
+        WebActivation(self.inner.activation.clone())
     }
 
     #[wasm_bindgen(getter, js_name = "precursorScanID")]
     pub fn precursor_id(&self) -> Option<String> {
-        self._inner.precursor_id.clone()
+        self.inner.precursor_id.clone()
+    }
+
+    pub fn copy(&self) -> Self {
+        self.clone()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(&self.inner).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// In-place edits to the precursor of a spectrum or frame description, behind the
+/// `setPrecursor*`, `setActivation*` and `setIsolationWindow` methods of both
+trait PrecursorEditor {
+    fn precursor_slot(&mut self) -> &mut Option<Precursor>;
+
+    fn existing_precursor(&mut self) -> Result<&mut Precursor, JsError> {
+        self.precursor_slot()
+            .as_mut()
+            .ok_or_else(|| JsError::new("No precursor to edit, set one with setPrecursor first"))
+    }
+
+    fn existing_ion(&mut self, index: usize) -> Result<&mut SelectedIon, JsError> {
+        let ions = &mut self.existing_precursor()?.ions;
+        let n = ions.len();
+        ions.get_mut(index)
+            .ok_or_else(|| JsError::new(&format!("Ion index {index} out of range for {n} ions")))
+    }
+
+    /// Replace the selected ion at `index`, or append it if `index` is the number of ions
+    fn put_ion(&mut self, index: usize, ion: SelectedIon) -> Result<(), JsError> {
+        let ions = &mut self.existing_precursor()?.ions;
+        if index < ions.len() {
+            ions[index] = ion;
+        } else if index == ions.len() {
+            ions.push(ion);
+        } else {
+            return Err(JsError::new(&format!(
+                "Ion index {index} out of range for {} ions",
+                ions.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl PrecursorEditor for SpectrumDescription {
+    fn precursor_slot(&mut self) -> &mut Option<Precursor> {
+        &mut self.precursor
+    }
+}
+
+impl PrecursorEditor for mzdata::spectrum::IonMobilityFrameDescription {
+    fn precursor_slot(&mut self) -> &mut Option<Precursor> {
+        &mut self.precursor
     }
 }
 
@@ -506,10 +973,84 @@ pub enum WebSignalContinuity {
     Profile,
 }
 
+#[wasm_bindgen(js_name = "Polarity")]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Hash, Eq)]
+pub enum WebPolarity {
+    #[default]
+    Unknown,
+    Positive,
+    Negative,
+}
+
+impl From<ScanPolarity> for WebPolarity {
+    fn from(value: ScanPolarity) -> Self {
+        match value {
+            ScanPolarity::Unknown => Self::Unknown,
+            ScanPolarity::Positive => Self::Positive,
+            ScanPolarity::Negative => Self::Negative,
+        }
+    }
+}
+
+impl From<WebPolarity> for ScanPolarity {
+    fn from(value: WebPolarity) -> Self {
+        match value {
+            WebPolarity::Unknown => Self::Unknown,
+            WebPolarity::Positive => Self::Positive,
+            WebPolarity::Negative => Self::Negative,
+        }
+    }
+}
+
+/// How much of a spectrum to read
+#[wasm_bindgen(js_name = "DetailLevel")]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Hash, Eq)]
+pub enum WebDetailLevel {
+    /// Read the metadata and decode the data arrays
+    #[default]
+    Full,
+    /// Read the data arrays but only decode them when they are first used
+    Lazy,
+    /// Read only the metadata, skipping the data arrays
+    MetadataOnly,
+}
+
+impl From<DetailLevel> for WebDetailLevel {
+    fn from(value: DetailLevel) -> Self {
+        match value {
+            DetailLevel::Full => Self::Full,
+            DetailLevel::Lazy => Self::Lazy,
+            DetailLevel::MetadataOnly => Self::MetadataOnly,
+        }
+    }
+}
+
+impl From<WebDetailLevel> for DetailLevel {
+    fn from(value: WebDetailLevel) -> Self {
+        match value {
+            WebDetailLevel::Full => Self::Full,
+            WebDetailLevel::Lazy => Self::Lazy,
+            WebDetailLevel::MetadataOnly => Self::MetadataOnly,
+        }
+    }
+}
+
 #[wasm_bindgen(js_name = "IsotopicModel")]
 #[derive(Debug, Clone, Copy)]
 pub struct WebIsotopicModel(IsotopicModels);
 
+impl From<IsotopicModels> for WebIsotopicModel {
+    fn from(value: IsotopicModels) -> Self {
+        Self(value)
+    }
+}
+
+impl From<WebIsotopicModel> for IsotopicModels {
+    fn from(value: WebIsotopicModel) -> Self {
+        value.0
+    }
+}
+
 #[wasm_bindgen(js_class = "IsotopicModel")]
 impl WebIsotopicModel {
     pub fn peptide() -> Self {
@@ -555,8 +1096,19 @@ impl From<SignalContinuity> for WebSignalContinuity {
     }
 }
 
+impl From<WebSignalContinuity> for SignalContinuity {
+    fn from(value: WebSignalContinuity) -> Self {
+        match value {
+            WebSignalContinuity::Unknown => Self::Unknown,
+            WebSignalContinuity::Centroid => Self::Centroid,
+            WebSignalContinuity::Profile => Self::Profile,
+        }
+    }
+}
+
 #[wasm_bindgen(js_name = "Spectrum")]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct WebSpectrum {
     inner: MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>,
 }
@@ -601,6 +1153,33 @@ impl WebSpectrum {
         &self.inner
     }
 
+    pub fn as_mut(&mut self) -> &mut MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak> {
+        &mut self.inner
+    }
+
+    /// Wrap a spectrum read with [`DetailLevel::Lazy`] without decoding its data arrays or
+    /// building its peaks, which [`WebSpectrum::decode_arrays`] does when they are needed
+    pub fn from_lazy(value: MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>) -> Self {
+        Self { inner: value }
+    }
+
+    /// Decode any data arrays that are still encoded and build the peaks from them
+    pub fn decode_arrays(&mut self) -> Result<(), ArrayRetrievalError> {
+        if self.is_decoded() {
+            return Ok(());
+        }
+        if let Some(arrays) = self.inner.arrays.as_mut() {
+            arrays.decode_all_arrays()?;
+        }
+        if let Err(e) = self.inner.try_build_peaks() {
+            info!(
+                "Failed to build peaks for spectrum {}: {e}",
+                self.inner.id()
+            );
+        }
+        Ok(())
+    }
+
     pub fn new(description: SpectrumDescription) -> Self {
         Self {
             inner: MultiLayerSpectrum::from_peaks_data_levels_and_description(
@@ -627,15 +1206,96 @@ impl WebSpectrum {
         self.inner.description_mut()
     }
 
+    /// The peaks as they are stored, which for a spectrum read lazily are only its
+    /// still-encoded arrays until it is decoded. Use [`WebSpectrum::decoded`] when the
+    /// spectrum may not have been decoded yet.
     pub fn peaks(
         &self,
     ) -> mzdata::spectrum::RefPeakDataLevel<'_, CentroidPeak, DeconvolvedSolutionPeak> {
         self.inner.peaks()
     }
+
+    /// The spectrum with its data arrays decoded and peaks built, borrowed if that has
+    /// already happened and decoded into a copy otherwise
+    pub fn decoded(&self) -> Cow<'_, MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>> {
+        if self.is_decoded() {
+            return Cow::Borrowed(&self.inner);
+        }
+        let mut this = self.clone();
+        if let Err(e) = this.decode_arrays() {
+            info!("Failed to decode spectrum {}: {e}", self.inner.id());
+        }
+        Cow::Owned(this.inner)
+    }
+
+    /// Decode the spectrum in place before its peaks are used, so a spectrum read lazily
+    /// is only decoded once
+    fn decode_for_peaks(&mut self) {
+        if let Err(e) = self.decode_arrays() {
+            info!("Failed to decode spectrum {}: {e}", self.inner.id());
+        }
+    }
+
+    /// Convert whatever the most processed data are into [`SimpleWebPeak`]s, decoding a copy
+    /// of the spectrum if it has not been decoded yet
+    pub fn peak_array(&self) -> Vec<SimpleWebPeak> {
+        self.decoded().peaks().iter().map(|p| p.into()).collect()
+    }
+
+    /// The total ion current of the most processed data, decoding a copy of the spectrum
+    /// if it has not been decoded yet
+    pub fn total_ion_current(&self) -> f32 {
+        self.decoded().peaks().tic()
+    }
+
+    /// The peaks within `error_tolerance` of `query`, decoding a copy of the spectrum
+    /// if it has not been decoded yet
+    pub fn peaks_for(&self, query: f64, error_tolerance: Tolerance) -> Vec<SimpleWebPeak> {
+        match self.decoded().peaks() {
+            RefPeakDataLevel::Missing => Vec::new(),
+            RefPeakDataLevel::RawData(_) => Vec::new(),
+            RefPeakDataLevel::Centroid(peak_set_vec) => peak_set_vec
+                .all_peaks_for(query, error_tolerance)
+                .into_iter()
+                .map(|p| p.into())
+                .collect(),
+            RefPeakDataLevel::Deconvoluted(peak_set_vec) => peak_set_vec
+                .all_peaks_for(query, error_tolerance)
+                .into_iter()
+                .map(|p| p.into())
+                .collect(),
+        }
+    }
 }
 
 #[wasm_bindgen(js_class = "Spectrum")]
 impl WebSpectrum {
+    /// Create a spectrum from scratch, with centroid peaks if `mzs` and `intensities` are given
+    #[wasm_bindgen(constructor)]
+    pub fn create(
+        id: String,
+        ms_level: u8,
+        start_time: f64,
+        mzs: Option<Vec<f64>>,
+        intensities: Option<Vec<f32>>,
+    ) -> Result<WebSpectrum, JsError> {
+        let mut description = SpectrumDescription::default();
+        description.id = id;
+        description.ms_level = ms_level;
+        let mut this = Self::new(description);
+        this.set_start_time(start_time);
+        match (mzs, intensities) {
+            (Some(mzs), Some(intensities)) => this.set_centroid_peaks(&mzs, &intensities)?,
+            (None, None) => {}
+            _ => {
+                return Err(JsError::new(
+                    "Both m/z and intensity arrays are needed to add peaks",
+                ))
+            }
+        }
+        Ok(this)
+    }
+
     #[wasm_bindgen(js_name = "toJSON")]
     pub fn to_json(&self) -> Result<JsValue, JsError> {
         serde_wasm_bindgen::to_value(&self.inner).map_err(|e| JsError::new(&e.to_string()))
@@ -643,7 +1303,18 @@ impl WebSpectrum {
 
     #[wasm_bindgen(js_name = "fromJSON")]
     pub fn from_json(val: JsValue) -> Result<Self, JsError> {
-        let inner = serde_wasm_bindgen::from_value(val).map_err(|e| JsError::new(&e.to_string()))?;
+        let inner =
+            serde_wasm_bindgen::from_value(val).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    /// Encode this spectrum as MessagePack, decoding it first if it was read lazily
+    pub fn pack(&self) -> Result<Vec<u8>, JsError> {
+        rmp_serde::to_vec_named(&*self.decoded()).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn unpack(val: &[u8]) -> Result<Self, JsError> {
+        let inner = rmp_serde::from_slice(val).map_err(|e| JsError::new(&e.to_string()))?;
         Ok(Self { inner })
     }
 
@@ -692,10 +1363,7 @@ impl WebSpectrum {
 
     #[wasm_bindgen(getter)]
     pub fn precursor(&self) -> Option<WebPrecursor> {
-        self.description()
-            .precursor
-            .clone()
-            .map(|p| WebPrecursor::new(p))
+        self.description().precursor.clone().map(WebPrecursor::from)
     }
 
     pub fn params(&self) -> Vec<WebParam> {
@@ -716,12 +1384,270 @@ impl WebSpectrum {
             .collect()
     }
 
+    fn first_scan_event(&self) -> Option<WebScanEvent> {
+        self.description()
+            .acquisition
+            .first_scan()
+            .map(|e| WebScanEvent(e.clone()))
+    }
+
+    /// The ion injection time of the first scan event. mzdata stores a missing injection
+    /// time as zero, so zero is treated as not recorded.
+    #[wasm_bindgen(getter, js_name = "injectionTime")]
+    pub fn injection_time(&self) -> Option<f32> {
+        self.first_scan_event()
+            .map(|e| e.injection_time())
+            .filter(|t| *t > 0.0)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn resolution(&self) -> Option<f64> {
+        self.first_scan_event().and_then(|e| e.resolution())
+    }
+
+    #[wasm_bindgen(getter, js_name = "faimsCompensationVoltage")]
+    pub fn faims_compensation_voltage(&self) -> Option<f64> {
+        compensation_voltage(self.description())
+    }
+
+    #[wasm_bindgen(getter, js_name = "ionMobility")]
+    pub fn ion_mobility(&self) -> Option<f64> {
+        self.first_scan_event().and_then(|e| e.ion_mobility())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn analyzer(&self) -> WebMassAnalyzer {
+        self.first_scan_event()
+            .map(|e| e.analyzer())
+            .unwrap_or_default()
+    }
+
+    /// The ion mobility of the first selected precursor ion
+    #[wasm_bindgen(getter, js_name = "precursorIonMobility")]
+    pub fn precursor_ion_mobility(&self) -> Option<f64> {
+        self.description()
+            .precursor
+            .as_ref()
+            .and_then(|p| p.ions.first())
+            .and_then(ion_mobility_param)
+            .and_then(|p| p.to_f64().ok())
+    }
+
     pub fn copy(&self) -> Self {
         Self {
             inner: self.inner.clone(),
         }
     }
 
+    #[wasm_bindgen(setter)]
+    pub fn set_id(&mut self, id: String) {
+        self.description_mut().id = id;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_index(&mut self, index: usize) {
+        self.description_mut().index = index;
+    }
+
+    #[wasm_bindgen(setter, js_name = "msLevel")]
+    pub fn set_ms_level(&mut self, ms_level: u8) {
+        self.description_mut().ms_level = ms_level;
+    }
+
+    #[wasm_bindgen(setter, js_name = "startTime")]
+    pub fn set_start_time(&mut self, start_time: f64) {
+        let acquisition = &mut self.description_mut().acquisition;
+        if acquisition.scans.is_empty() {
+            acquisition.scans.push(ScanEvent::default());
+        }
+        acquisition.scans[0].start_time = start_time;
+    }
+
+    #[wasm_bindgen(setter, js_name = "signalContinuity")]
+    pub fn set_signal_continuity(&mut self, continuity: WebSignalContinuity) {
+        self.description_mut().signal_continuity = continuity.into();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn polarity(&self) -> WebPolarity {
+        self.description().polarity.into()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_polarity(&mut self, polarity: WebPolarity) {
+        self.description_mut().polarity = polarity.into();
+    }
+
+    /// Replace the precursor of this spectrum, or remove it if `precursor` is not given
+    #[wasm_bindgen(js_name = "setPrecursor")]
+    pub fn set_precursor(&mut self, precursor: Option<WebPrecursor>) {
+        self.description_mut().precursor = precursor.map(|p| p.to_precursor());
+    }
+
+    /// Write an edited copy of the precursor back to this spectrum
+    #[wasm_bindgen(setter = precursor)]
+    pub fn set_precursor_value(&mut self, precursor: &WebPrecursor) {
+        self.description_mut().precursor = Some(precursor.to_precursor());
+    }
+
+    /// Replace the selected precursor ion at `index` in place, or append it if `index`
+    /// is the number of ions
+    #[wasm_bindgen(js_name = "setPrecursorIon")]
+    pub fn set_precursor_ion(&mut self, index: usize, ion: &WebSelectedIon) -> Result<(), JsError> {
+        self.description_mut().put_ion(index, ion.0.clone())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorIonMz")]
+    pub fn set_precursor_ion_mz(&mut self, index: usize, mz: f64) -> Result<(), JsError> {
+        self.description_mut().existing_ion(index)?.mz = mz;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorIonCharge")]
+    pub fn set_precursor_ion_charge(
+        &mut self,
+        index: usize,
+        charge: Option<i32>,
+    ) -> Result<(), JsError> {
+        self.description_mut().existing_ion(index)?.charge = charge;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorIonIntensity")]
+    pub fn set_precursor_ion_intensity(
+        &mut self,
+        index: usize,
+        intensity: f32,
+    ) -> Result<(), JsError> {
+        self.description_mut().existing_ion(index)?.intensity = intensity;
+        Ok(())
+    }
+
+    /// Set the ion mobility of the selected precursor ion at `index` as the term `kind`, given
+    /// by name or CURIE: inverse reduced ion mobility (`MS:1002815`), drift time (`MS:1002476`)
+    /// or FAIMS compensation voltage (`MS:1001581`). Without `kind` the ion's current term is
+    /// kept, or inverse reduced ion mobility is used. Setting it to `undefined` removes it.
+    #[wasm_bindgen(js_name = "setPrecursorIonMobility")]
+    pub fn set_precursor_ion_mobility(
+        &mut self,
+        index: usize,
+        value: Option<f64>,
+        kind: Option<String>,
+    ) -> Result<(), JsError> {
+        let term = kind.as_deref().map(ion_mobility_term).transpose()?;
+        let ion = self.description_mut().existing_ion(index)?;
+        set_ion_mobility(ion, value, term);
+        Ok(())
+    }
+
+    /// Replace the activation of the precursor in place
+    #[wasm_bindgen(js_name = "setActivation")]
+    pub fn set_activation(&mut self, activation: &WebActivation) -> Result<(), JsError> {
+        let precursor = self.description_mut().existing_precursor()?;
+        precursor.activation = activation.0.clone();
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setActivationEnergy")]
+    pub fn set_activation_energy(&mut self, energy: f32) -> Result<(), JsError> {
+        let precursor = self.description_mut().existing_precursor()?;
+        precursor.activation.energy = energy;
+        Ok(())
+    }
+
+    /// Replace the isolation window of the precursor in place
+    #[wasm_bindgen(js_name = "setIsolationWindow")]
+    pub fn set_isolation_window(
+        &mut self,
+        isolation_window: &WebIsolationWindow,
+    ) -> Result<(), JsError> {
+        let precursor = self.description_mut().existing_precursor()?;
+        precursor.isolation_window = isolation_window.0.clone();
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorScanID")]
+    pub fn set_precursor_scan_id(&mut self, precursor_id: Option<String>) -> Result<(), JsError> {
+        let precursor = self.description_mut().existing_precursor()?;
+        precursor.precursor_id = precursor_id;
+        Ok(())
+    }
+
+    /// Replace the centroid peaks of this spectrum, dropping any raw signal arrays
+    /// so that writers emit the new peaks.
+    #[wasm_bindgen(js_name = "setCentroidPeaks")]
+    pub fn set_centroid_peaks(&mut self, mzs: &[f64], intensities: &[f32]) -> Result<(), JsError> {
+        if mzs.len() != intensities.len() {
+            return Err(JsError::new(&format!(
+                "Got {} m/z values but {} intensities",
+                mzs.len(),
+                intensities.len()
+            )));
+        }
+        let peaks: MZPeakSetType<CentroidPeak> = mzs
+            .iter()
+            .zip(intensities.iter())
+            .map(|(mz, intensity)| CentroidPeak::new(*mz, *intensity, 0))
+            .collect();
+        self.inner.arrays = None;
+        self.inner.peaks = Some(peaks);
+        self.description_mut().signal_continuity = SignalContinuity::Centroid;
+        Ok(())
+    }
+
+    /// Replace the deconvoluted peaks of this spectrum, dropping any raw signal arrays
+    /// so that writers emit the new peaks.
+    #[wasm_bindgen(js_name = "setDeconvolutedPeaks")]
+    pub fn set_deconvoluted_peaks(
+        &mut self,
+        neutral_masses: &[f64],
+        intensities: &[f32],
+        charges: &[i32],
+    ) -> Result<(), JsError> {
+        if neutral_masses.len() != intensities.len() || neutral_masses.len() != charges.len() {
+            return Err(JsError::new(&format!(
+                "Got {} neutral masses, {} intensities and {} charges",
+                neutral_masses.len(),
+                intensities.len(),
+                charges.len()
+            )));
+        }
+        let peaks: MassPeakSetType<DeconvolvedSolutionPeak> = neutral_masses
+            .iter()
+            .zip(intensities.iter())
+            .zip(charges.iter())
+            .map(|((mass, intensity), charge)| {
+                DeconvolvedSolutionPeak::new(*mass, *intensity, *charge, 0, 0.0, Default::default())
+            })
+            .collect();
+        self.inner.arrays = None;
+        self.inner.deconvoluted_peaks = Some(peaks);
+        self.description_mut().signal_continuity = SignalContinuity::Centroid;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "clearPeaks")]
+    pub fn clear_peaks(&mut self) {
+        self.inner.arrays = None;
+        self.inner.peaks = None;
+        self.inner.deconvoluted_peaks = None;
+    }
+
+    #[wasm_bindgen(js_name = "addParam")]
+    pub fn add_param(&mut self, param: &WebParam) {
+        self.description_mut().params.push(param.0.clone());
+    }
+
+    /// Remove every parameter whose name or accession CURIE is `key`, returning how many
+    /// were removed
+    #[wasm_bindgen(js_name = "removeParam")]
+    pub fn remove_param(&mut self, key: &str) -> usize {
+        let params = &mut self.description_mut().params;
+        let n = params.len();
+        params.retain(|p| p.name() != key && p.curie_str().as_deref() != Some(key));
+        n - params.len()
+    }
+
     #[wasm_bindgen(js_name = "pickPeaks")]
     pub fn pick_peaks(&mut self, signal_to_noise_threshold: f32) {
         if !self.is_profile() && self.inner.raw_arrays().is_none() {
@@ -746,43 +1672,26 @@ impl WebSpectrum {
     }
 
     pub fn deconvolve(&mut self, score_threshold: f32, isotopic_models: Vec<WebIsotopicModel>) {
-        if self.inner.peaks.is_none() {
-            self.pick_peaks(1.0);
-        }
-        let peaks = self.inner.peaks.as_ref().unwrap();
-        let max_z = self
-            .description()
-            .precursor
-            .as_ref()
-            .and_then(|p| p.charge())
-            .unwrap_or(8)
-            .abs();
-        let models = IsotopicModelLike::from_iter(isotopic_models.iter().map(|i| i.0));
-        let mut iso_params = IsotopicPatternParams::default();
-        iso_params.incremental_truncation = Some(0.95);
-        iso_params.truncate_after = 0.9999;
-        let solution = deconvolute_peaks(
-            peaks.clone(),
-            models,
-            Tolerance::PPM(15.0),
-            (1, max_z),
-            PenalizedMSDeconvScorer::new(0.02, 2.0),
-            MaximizingFitFilter::new(score_threshold),
-            1,
-            iso_params,
-            true,
-        )
-        .unwrap();
-        self.inner.deconvoluted_peaks = Some(solution);
+        let models: Vec<_> = isotopic_models
+            .into_iter()
+            .map(IsotopicModels::from)
+            .collect();
+        pipeline::deconvolve_spectrum(&mut self.inner, score_threshold, &models).unwrap();
     }
 
     #[wasm_bindgen(getter)]
-    pub fn length(&self) -> usize {
+    pub fn length(&mut self) -> usize {
+        self.decode_for_peaks();
         self.inner.peaks().len()
     }
 
     #[wasm_bindgen(js_name = "hasPeak")]
-    pub fn has_peak(&self, query: f64, error_tolerance: &WebTolerance) -> Option<SimpleWebPeak> {
+    pub fn has_peak(
+        &mut self,
+        query: f64,
+        error_tolerance: &WebTolerance,
+    ) -> Option<SimpleWebPeak> {
+        self.decode_for_peaks();
         self.peaks()
             .search(query, error_tolerance.0)
             .and_then(|i| self.peaks().get(i))
@@ -793,24 +1702,17 @@ impl WebSpectrum {
     }
 
     #[wasm_bindgen(js_name = "allPeaksFor")]
-    pub fn all_peaks_for(&self, query: f64, error_tolerance: &WebTolerance) -> Vec<SimpleWebPeak> {
-        match self.peaks() {
-            RefPeakDataLevel::Missing => Vec::new(),
-            RefPeakDataLevel::RawData(_) => Vec::new(),
-            RefPeakDataLevel::Centroid(peak_set_vec) => peak_set_vec
-                .all_peaks_for(query, error_tolerance.0)
-                .into_iter()
-                .map(|p| p.into())
-                .collect(),
-            RefPeakDataLevel::Deconvoluted(peak_set_vec) => peak_set_vec
-                .all_peaks_for(query, error_tolerance.0)
-                .into_iter()
-                .map(|p| p.into())
-                .collect(),
-        }
+    pub fn all_peaks_for(
+        &mut self,
+        query: f64,
+        error_tolerance: &WebTolerance,
+    ) -> Vec<SimpleWebPeak> {
+        self.decode_for_peaks();
+        self.peaks_for(query, error_tolerance.0)
     }
 
-    pub fn at(&self, index: usize) -> Option<SimpleWebPeak> {
+    pub fn at(&mut self, index: usize) -> Option<SimpleWebPeak> {
+        self.decode_for_peaks();
         self.peaks().get(index).map(|p| SimpleWebPeak {
             mz: p.mz,
             intensity: p.intensity,
@@ -818,16 +1720,19 @@ impl WebSpectrum {
     }
 
     #[wasm_bindgen(js_name = "basePeak")]
-    pub fn base_peak(&self) -> SimpleWebPeak {
+    pub fn base_peak(&mut self) -> SimpleWebPeak {
+        self.decode_for_peaks();
         let p = self.peaks().base_peak();
         SimpleWebPeak::from(&p)
     }
 
-    pub fn tic(&self) -> f32 {
-        self.peaks().tic()
+    pub fn tic(&mut self) -> f32 {
+        self.decode_for_peaks();
+        self.total_ion_current()
     }
 
-    pub fn between(&self, low: f64, high: f64) -> Vec<SimpleWebPeak> {
+    pub fn between(&mut self, low: f64, high: f64) -> Vec<SimpleWebPeak> {
+        self.decode_for_peaks();
         match &self.peaks() {
             RefPeakDataLevel::Missing => Vec::new(),
             RefPeakDataLevel::RawData(_) => Vec::new(),
@@ -846,12 +1751,33 @@ impl WebSpectrum {
 
     /// Convert whatever the most processed data are into [`SimplePeak`]s
     #[wasm_bindgen(js_name = "toArray")]
-    pub fn to_array(&self) -> Vec<SimpleWebPeak> {
-        self.peaks().iter().map(|p| p.into()).collect()
+    pub fn to_array(&mut self) -> Vec<SimpleWebPeak> {
+        self.decode_for_peaks();
+        self.peak_array()
+    }
+
+    /// Whether the data arrays have been decoded, false for spectra read lazily until
+    /// their data are first used
+    #[wasm_bindgen(getter, js_name = "isDecoded")]
+    pub fn is_decoded(&self) -> bool {
+        self.inner.arrays.as_ref().is_none_or(|arrays| {
+            arrays
+                .iter()
+                .all(|(_, a)| a.compression == BinaryCompressionType::Decoded)
+        })
+    }
+
+    /// Decode the data arrays of a spectrum read lazily. This happens on its own the first
+    /// time its arrays or peaks are used, by `rawArrays`, `toArray`, `at`, `between` and
+    /// the other peak accessors.
+    pub fn decode(&mut self) -> Result<(), JsError> {
+        self.decode_arrays()
+            .map_err(|e| JsError::new(&format!("Failed to decode {}: {e}", self.id())))
     }
 
     #[wasm_bindgen(js_name = "rawArrays")]
-    pub fn raw_arrays(&self) -> Option<Object> {
+    pub fn raw_arrays(&mut self) -> Option<Object> {
+        self.decode().ok()?;
         self.inner
             .arrays
             .as_ref()
@@ -862,7 +1788,8 @@ impl WebSpectrum {
     }
 
     #[wasm_bindgen(js_name = "centroidPeaks")]
-    pub fn centroid_peaks(&self) -> Option<Vec<SimpleWebPeak>> {
+    pub fn centroid_peaks(&mut self) -> Option<Vec<SimpleWebPeak>> {
+        self.decode().ok()?;
         self.inner
             .peaks
             .as_ref()
@@ -870,7 +1797,8 @@ impl WebSpectrum {
     }
 
     #[wasm_bindgen(js_name = "deconvolutedPeaks")]
-    pub fn deconvoluted_peaks(&self) -> Option<Vec<SimpleWebChargedPeak>> {
+    pub fn deconvoluted_peaks(&mut self) -> Option<Vec<SimpleWebChargedPeak>> {
+        self.decode().ok()?;
         self.inner
             .deconvoluted_peaks
             .as_ref()
@@ -954,6 +1882,12 @@ impl From<SimpleFeature<MZ, IonMobility>> for WebFeature {
     }
 }
 
+impl WebFeature {
+    pub fn as_inner(&self) -> &Feature<MZ, IonMobility> {
+        &self.0
+    }
+}
+
 #[wasm_bindgen(js_class = "Feature")]
 impl WebFeature {
     #[wasm_bindgen(js_name = "toJSON")]
@@ -966,6 +1900,16 @@ impl WebFeature {
         let inner = serde_wasm_bindgen::from_value(text).map_err(|e| JsError::new(&e.to_string()))?;
         Ok(Self(inner))
     }
+
+    /// Encode this feature as MessagePack
+    pub fn pack(&self) -> Result<Vec<u8>, JsError> {
+        rmp_serde::to_vec_named(&self.0).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn unpack(val: &[u8]) -> Result<Self, JsError> {
+        let inner = rmp_serde::from_slice(val).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self(inner))
+    }
     #[wasm_bindgen(constructor)]
     pub fn new(x: Vec<f64>, y: Vec<f64>, z: Vec<f32>) -> Self {
         Self(Feature::new(x, y, z))
@@ -1028,9 +1972,26 @@ impl WebFeature {
         self.0.mz()
     }
 
+    /// Fit peak shape models to this feature's intensity profile. If `shapes` is
+    /// given, only those model kinds are tried.
     #[wasm_bindgen(js_name = "fitPeaks")]
-    pub fn fit_peaks(&self) -> FeatureFit {
-        FeatureFit(self.0.fit_peaks_with(Default::default()).peak_fits)
+    pub fn fit_peaks(&self, shapes: Option<Vec<WebPeakShapeKind>>) -> FeatureFit {
+        let (_, times, intensities) = self.0.as_view().into_inner();
+        match shapes {
+            Some(shapes) if !shapes.is_empty() => {
+                FeatureFit::fit_shapes(times.to_vec(), intensities.to_vec(), &shapes)
+            }
+            _ => FeatureFit::new(
+                self.0
+                    .fit_peaks_with(Default::default())
+                    .peak_fits
+                    .iter()
+                    .cloned()
+                    .collect(),
+                times.to_vec(),
+                intensities.to_vec(),
+            ),
+        }
     }
 
     pub fn area(&self) -> f32 {
@@ -1048,6 +2009,12 @@ impl From<DeconvolvedSolutionFeature<IonMobility>> for WebDeconvolvedFeature {
     }
 }
 
+impl WebDeconvolvedFeature {
+    pub fn as_inner(&self) -> &DeconvolvedSolutionFeature<IonMobility> {
+        &self.0
+    }
+}
+
 #[wasm_bindgen(js_class = "DeconvolvedFeature")]
 impl WebDeconvolvedFeature {
     pub fn clone(&self) -> Self {
@@ -1065,6 +2032,16 @@ impl WebDeconvolvedFeature {
         Ok(Self(inner))
     }
 
+    /// Encode this feature as MessagePack
+    pub fn pack(&self) -> Result<Vec<u8>, JsError> {
+        rmp_serde::to_vec_named(&self.0).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn unpack(val: &[u8]) -> Result<Self, JsError> {
+        let inner = rmp_serde::from_slice(val).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self(inner))
+    }
+
     #[wasm_bindgen(getter, js_name = "startTime")]
     pub fn start_time(&self) -> Option<f64> {
         self.0.start_time()
@@ -1177,14 +2154,28 @@ impl WebDeconvolvedFeature {
         self.0.mz()
     }
 
+    /// Fit peak shape models to this feature's intensity profile. If `shapes` is
+    /// given, only those model kinds are tried.
     #[wasm_bindgen(js_name = "fitPeaks")]
-    pub fn fit_peaks(&self) -> FeatureFit {
-        FeatureFit(
-            self.0
-                .as_inner()
-                .fit_peaks_with(Default::default())
-                .peak_fits,
-        )
+    pub fn fit_peaks(&self, shapes: Option<Vec<WebPeakShapeKind>>) -> FeatureFit {
+        let times = self.times().to_vec();
+        let intensities = self.intensities().to_vec();
+        match shapes {
+            Some(shapes) if !shapes.is_empty() => {
+                FeatureFit::fit_shapes(times, intensities, &shapes)
+            }
+            _ => FeatureFit::new(
+                self.0
+                    .as_inner()
+                    .fit_peaks_with(Default::default())
+                    .peak_fits
+                    .iter()
+                    .cloned()
+                    .collect(),
+                times,
+                intensities,
+            ),
+        }
     }
 
     pub fn area(&self) -> f32 {
@@ -1192,24 +2183,228 @@ impl WebDeconvolvedFeature {
     }
 }
 
+#[wasm_bindgen(js_name = "PeakShapeKind")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebPeakShapeKind {
+    Gaussian,
+    SkewedGaussian,
+    BiGaussian,
+}
+
+impl From<&PeakShape> for WebPeakShapeKind {
+    fn from(value: &PeakShape) -> Self {
+        match value {
+            PeakShape::Gaussian(_) => Self::Gaussian,
+            PeakShape::SkewedGaussian(_) => Self::SkewedGaussian,
+            PeakShape::BiGaussian(_) => Self::BiGaussian,
+        }
+    }
+}
+
+/// The parameters of a single fitted peak shape model.
+///
+/// `sigmaLow` and `sigmaHigh` are the widths on either side of `center`, and are
+/// equal to `sigma` for symmetric shapes. `skew` is zero for all but the skewed
+/// Gaussian.
+#[wasm_bindgen(inspectable, js_name = "PeakModel")]
+#[derive(Debug, Clone, Copy)]
+pub struct WebPeakModel {
+    pub kind: WebPeakShapeKind,
+    pub center: f64,
+    pub sigma: f64,
+    #[wasm_bindgen(js_name = "sigmaLow")]
+    pub sigma_low: f64,
+    #[wasm_bindgen(js_name = "sigmaHigh")]
+    pub sigma_high: f64,
+    pub skew: f64,
+    pub amplitude: f64,
+}
+
+impl From<&PeakShape> for WebPeakModel {
+    fn from(value: &PeakShape) -> Self {
+        let kind = value.into();
+        match value {
+            PeakShape::Gaussian(m) => Self {
+                kind,
+                center: m.mu,
+                sigma: m.sigma,
+                sigma_low: m.sigma,
+                sigma_high: m.sigma,
+                skew: 0.0,
+                amplitude: m.amplitude,
+            },
+            PeakShape::SkewedGaussian(m) => Self {
+                kind,
+                center: m.mu,
+                sigma: m.sigma,
+                sigma_low: m.sigma,
+                sigma_high: m.sigma,
+                skew: m.lambda,
+                amplitude: m.amplitude,
+            },
+            PeakShape::BiGaussian(m) => Self {
+                kind,
+                center: m.mu,
+                sigma: (m.sigma_low + m.sigma_high) / 2.0,
+                sigma_low: m.sigma_low,
+                sigma_high: m.sigma_high,
+                skew: 0.0,
+                amplitude: m.amplitude,
+            },
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "PeakModel")]
+impl WebPeakModel {
+    /// The time interval covering `width` standard deviations on either side of the center
+    pub fn bounds(&self, width: Option<f64>) -> Vec<f64> {
+        let width = width.unwrap_or(3.0);
+        vec![
+            self.center - width * self.sigma_low,
+            self.center + width * self.sigma_high,
+        ]
+    }
+}
+
+/// Trapezoid integration of `f` over `[low, high]` with `n` intervals
+fn integrate<F: Fn(f64) -> f64>(f: F, low: f64, high: f64, n: usize) -> f64 {
+    if high <= low || n == 0 {
+        return 0.0;
+    }
+    let step = (high - low) / n as f64;
+    let mut acc = (f(low) + f(high)) / 2.0;
+    for i in 1..n {
+        acc += f(low + step * i as f64);
+    }
+    acc * step
+}
+
 #[wasm_bindgen]
-pub struct FeatureFit(MultiPeakShapeFit);
+#[derive(Debug, Clone)]
+pub struct FeatureFit {
+    models: Vec<PeakShape>,
+    times: Vec<f64>,
+    intensities: Vec<f32>,
+}
+
+impl FeatureFit {
+    pub fn new(models: Vec<PeakShape>, times: Vec<f64>, intensities: Vec<f32>) -> Self {
+        Self {
+            models,
+            times,
+            intensities,
+        }
+    }
+
+    /// Fit each of `shapes` to the entire profile and keep the one with the best
+    /// goodness-of-fit.
+    pub fn fit_shapes(times: Vec<f64>, intensities: Vec<f32>, shapes: &[WebPeakShapeKind]) -> Self {
+        let best = shapes
+            .iter()
+            .map(|kind| {
+                let args = PeakFitArgs::from((times.as_slice(), intensities.as_slice()));
+                let (model, _) = match kind {
+                    WebPeakShapeKind::Gaussian => PeakShape::fit_gaussian(args),
+                    WebPeakShapeKind::SkewedGaussian => PeakShape::fit_skewed_gaussian(args),
+                    WebPeakShapeKind::BiGaussian => PeakShape::fit_bigaussian(args),
+                };
+                let fit = Self::new(vec![model], times.clone(), intensities.clone());
+                let score = fit.r_squared();
+                (fit, score)
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(fit, _)| fit);
+        best.unwrap_or_else(|| Self::new(Vec::new(), times, intensities))
+    }
+
+    pub fn as_models(&self) -> &[PeakShape] {
+        &self.models
+    }
+}
 
 #[wasm_bindgen]
 impl FeatureFit {
     pub fn models(&self) -> Vec<JsValue> {
-        self.0
+        self.models
             .iter()
             .map(|f| serde_wasm_bindgen::to_value(f).unwrap())
             .collect()
     }
 
+    #[wasm_bindgen(js_name = "peakModels")]
+    pub fn peak_models(&self) -> Vec<WebPeakModel> {
+        self.models.iter().map(WebPeakModel::from).collect()
+    }
+
     pub fn predict(&self, times: &[f64]) -> Vec<f64> {
-        self.0.predict(times).to_vec()
+        times.iter().map(|t| self.density(*t)).collect()
     }
 
     pub fn density(&self, time: f64) -> f64 {
-        self.0.density(time)
+        self.models.iter().map(|m| m.density(time)).sum()
+    }
+
+    /// The observed intensity minus the fitted intensity at each observed time point
+    pub fn residuals(&self) -> Vec<f64> {
+        self.times
+            .iter()
+            .zip(self.intensities.iter())
+            .map(|(t, i)| *i as f64 - self.density(*t))
+            .collect()
+    }
+
+    /// The coefficient of determination of the fit over the observed time points
+    #[wasm_bindgen(getter, js_name = "rSquared")]
+    pub fn r_squared(&self) -> f64 {
+        if self.intensities.is_empty() {
+            return 0.0;
+        }
+        let mean =
+            self.intensities.iter().map(|i| *i as f64).sum::<f64>() / self.intensities.len() as f64;
+        let ss_tot: f64 = self
+            .intensities
+            .iter()
+            .map(|i| (*i as f64 - mean).powi(2))
+            .sum();
+        let ss_res: f64 = self.residuals().iter().map(|r| r.powi(2)).sum();
+        if ss_tot == 0.0 {
+            return 0.0;
+        }
+        1.0 - ss_res / ss_tot
+    }
+
+    /// The root mean squared error of the fit over the observed time points
+    #[wasm_bindgen(getter)]
+    pub fn rmse(&self) -> f64 {
+        if self.intensities.is_empty() {
+            return 0.0;
+        }
+        let ss_res: f64 = self.residuals().iter().map(|r| r.powi(2)).sum();
+        (ss_res / self.intensities.len() as f64).sqrt()
+    }
+
+    /// The time interval spanned by all fitted models, `width` standard deviations
+    /// beyond their centers
+    #[wasm_bindgen(js_name = "integrationBounds")]
+    pub fn integration_bounds(&self, width: Option<f64>) -> Vec<f64> {
+        self.peak_models()
+            .iter()
+            .map(|m| m.bounds(width))
+            .reduce(|acc, b| vec![acc[0].min(b[0]), acc[1].max(b[1])])
+            .unwrap_or_default()
+    }
+
+    /// Integrate the fitted curve between `low` and `high`, defaulting to [`FeatureFit::integration_bounds`]
+    #[wasm_bindgen(js_name = "areaUnderFit")]
+    pub fn area_under_fit(&self, low: Option<f64>, high: Option<f64>) -> f64 {
+        let bounds = self.integration_bounds(None);
+        if bounds.is_empty() {
+            return 0.0;
+        }
+        let low = low.unwrap_or(bounds[0]);
+        let high = high.unwrap_or(bounds[1]);
+        integrate(|t| self.density(t), low, high, 1000)
     }
 
     #[wasm_bindgen(js_name = "toJSON")]
@@ -1219,7 +2414,8 @@ impl FeatureFit {
 }
 
 #[wasm_bindgen(js_name = "IonMobilityFrame")]
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct WebIonMobilityFrame {
     inner: MultiLayerIonMobilityFrame<
         Feature<MZ, IonMobility>,
@@ -1231,6 +2427,13 @@ impl WebIonMobilityFrame {
     fn description(&self) -> &mzdata::spectrum::IonMobilityFrameDescription {
         self.inner.description()
     }
+
+    pub fn as_mut(
+        &mut self,
+    ) -> &mut MultiLayerIonMobilityFrame<Feature<MZ, IonMobility>, DeconvolvedSolutionFeature<IonMobility>>
+    {
+        &mut self.inner
+    }
 }
 
 impl
@@ -1266,10 +2469,16 @@ impl WebIonMobilityFrame {
 
     #[wasm_bindgen(js_name = "fromJSON")]
     pub fn from_json(val: JsValue) -> Result<Self, JsError> {
-        let inner = serde_wasm_bindgen::from_value(val).map_err(|e| JsError::new(&e.to_string()))?;
+        let inner =
+            serde_wasm_bindgen::from_value(val).map_err(|e| JsError::new(&e.to_string()))?;
         Ok(Self { inner })
     }
 
+    /// Encode this frame as MessagePack
+    pub fn pack(&self) -> Result<Vec<u8>, JsError> {
+        rmp_serde::to_vec_named(&self.inner).map_err(|e| JsError::new(&e.to_string()))
+    }
+
     pub fn unpack(val: &[u8]) -> Result<Self, JsError> {
         let inner = rmp_serde::from_slice(val).map_err(|e| JsError::new(&e.to_string()))?;
         Ok(Self { inner })
@@ -1315,10 +2524,96 @@ impl WebIonMobilityFrame {
 
     #[wasm_bindgen(getter)]
     pub fn precursor(&self) -> Option<WebPrecursor> {
-        self.description()
-            .precursor
-            .clone()
-            .map(|p| WebPrecursor::new(p))
+        self.description().precursor.clone().map(WebPrecursor::from)
+    }
+
+    /// Write an edited copy of the precursor back to this frame
+    #[wasm_bindgen(setter = precursor)]
+    pub fn set_precursor(&mut self, precursor: &WebPrecursor) {
+        self.inner.description_mut().precursor = Some(precursor.to_precursor());
+    }
+
+    /// Replace the selected precursor ion at `index` in place, or append it if `index`
+    /// is the number of ions
+    #[wasm_bindgen(js_name = "setPrecursorIon")]
+    pub fn set_precursor_ion(&mut self, index: usize, ion: &WebSelectedIon) -> Result<(), JsError> {
+        self.inner.description_mut().put_ion(index, ion.0.clone())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorIonMz")]
+    pub fn set_precursor_ion_mz(&mut self, index: usize, mz: f64) -> Result<(), JsError> {
+        self.inner.description_mut().existing_ion(index)?.mz = mz;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorIonCharge")]
+    pub fn set_precursor_ion_charge(
+        &mut self,
+        index: usize,
+        charge: Option<i32>,
+    ) -> Result<(), JsError> {
+        self.inner.description_mut().existing_ion(index)?.charge = charge;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorIonIntensity")]
+    pub fn set_precursor_ion_intensity(
+        &mut self,
+        index: usize,
+        intensity: f32,
+    ) -> Result<(), JsError> {
+        self.inner.description_mut().existing_ion(index)?.intensity = intensity;
+        Ok(())
+    }
+
+    /// Set the ion mobility of the selected precursor ion at `index` as the term `kind`, given
+    /// by name or CURIE: inverse reduced ion mobility (`MS:1002815`), drift time (`MS:1002476`)
+    /// or FAIMS compensation voltage (`MS:1001581`). Without `kind` the ion's current term is
+    /// kept, or inverse reduced ion mobility is used. Setting it to `undefined` removes it.
+    #[wasm_bindgen(js_name = "setPrecursorIonMobility")]
+    pub fn set_precursor_ion_mobility(
+        &mut self,
+        index: usize,
+        value: Option<f64>,
+        kind: Option<String>,
+    ) -> Result<(), JsError> {
+        let term = kind.as_deref().map(ion_mobility_term).transpose()?;
+        let ion = self.inner.description_mut().existing_ion(index)?;
+        set_ion_mobility(ion, value, term);
+        Ok(())
+    }
+
+    /// Replace the activation of the precursor in place
+    #[wasm_bindgen(js_name = "setActivation")]
+    pub fn set_activation(&mut self, activation: &WebActivation) -> Result<(), JsError> {
+        let precursor = self.inner.description_mut().existing_precursor()?;
+        precursor.activation = activation.0.clone();
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setActivationEnergy")]
+    pub fn set_activation_energy(&mut self, energy: f32) -> Result<(), JsError> {
+        let precursor = self.inner.description_mut().existing_precursor()?;
+        precursor.activation.energy = energy;
+        Ok(())
+    }
+
+    /// Replace the isolation window of the precursor in place
+    #[wasm_bindgen(js_name = "setIsolationWindow")]
+    pub fn set_isolation_window(
+        &mut self,
+        isolation_window: &WebIsolationWindow,
+    ) -> Result<(), JsError> {
+        let precursor = self.inner.description_mut().existing_precursor()?;
+        precursor.isolation_window = isolation_window.0.clone();
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = "setPrecursorScanID")]
+    pub fn set_precursor_scan_id(&mut self, precursor_id: Option<String>) -> Result<(), JsError> {
+        let precursor = self.inner.description_mut().existing_precursor()?;
+        precursor.precursor_id = precursor_id;
+        Ok(())
     }
 
     pub fn params(&self) -> Vec<WebParam> {
@@ -1383,9 +2678,13 @@ impl WebIonMobilityFrame {
         error_tolerance: Option<WebTolerance>,
     ) {
         let error_tolerance = error_tolerance.map(|e| e.0).unwrap_or(Tolerance::PPM(15.0));
-        self.inner
-            .extract_features_simple(error_tolerance, min_length, maximum_gap_size, None)
-            .unwrap();
+        pipeline::extract_frame_features(
+            &mut self.inner,
+            min_length,
+            maximum_gap_size,
+            error_tolerance,
+        )
+        .unwrap();
     }
 
     pub fn features(&self) -> Option<Vec<WebFeature>> {
@@ -1413,53 +2712,23 @@ impl WebIonMobilityFrame {
         isotopic_models: Vec<WebIsotopicModel>,
         error_tolerance: Option<WebTolerance>,
     ) {
-        if self.inner.features.is_none() {
-            self.extract_features(min_length, maximum_gap_size, error_tolerance);
-        }
-
         let error_tolerance = error_tolerance.map(|e| e.0).unwrap_or(Tolerance::PPM(15.0));
-
-        let mut features = self.inner.features.as_ref().unwrap().clone();
-        features.iter_mut().for_each(|f| {
-            f.smooth(1);
-        });
-        let max_z = self
-            .description()
-            .precursor
-            .as_ref()
-            .and_then(|p| p.charge())
-            .unwrap_or(8)
-            .abs();
-        let mut feature_params = FeatureSearchParams::default();
-        if self.inner.ms_level() == 1 {
-            feature_params.truncate_after = 0.95;
-        } else {
-            feature_params.truncate_after = 0.8;
-        }
-        let isotopic_model: IsotopicModelLike = isotopic_models
-            .first()
-            .copied()
-            .map(|m| m.0.into())
-            .unwrap();
-        let res = mzdeisotope_map::deconvolute_features(
-            features,
-            feature_params,
-            isotopic_model,
-            PenalizedMSDeconvScorer::new(0.04, 2.0),
-            MaximizingFitFilter::new(score_threshold),
-            error_tolerance,
-            (1, max_z),
+        let models: Vec<_> = isotopic_models
+            .into_iter()
+            .map(IsotopicModels::from)
+            .collect();
+        pipeline::deconvolve_frame_features(
+            &mut self.inner,
             min_length,
             maximum_gap_size,
-            5.0,
-            2,
+            score_threshold,
+            &models,
+            error_tolerance,
         )
         .inspect_err(|e| {
             log::error!("Error occured during deconvolution: {e}");
         })
         .unwrap();
-
-        self.inner.deconvoluted_features = Some(res);
     }
 
     #[wasm_bindgen(js_name = "rawArrays")]
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{WebDeconvolvedFeature, WebFeature, WebIonMobilityFrame, WebSpectrum};
+
+/// Bumped whenever the layout of [`PackedBatch`] changes so stale caches can be detected
+const PACKED_BATCH_VERSION: u32 = 1;
+
+/// A collection of spectra, frames and features encoded together as one MessagePack
+/// message, for caching processed results or passing them between workers.
+///
+/// Each entry is encoded the same way as the `pack` method of its type.
+#[wasm_bindgen]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PackedBatch {
+    version: u32,
+    spectra: Vec<WebSpectrum>,
+    frames: Vec<WebIonMobilityFrame>,
+    features: Vec<WebFeature>,
+    deconvolved_features: Vec<WebDeconvolvedFeature>,
+}
+
+impl Default for PackedBatch {
+    fn default() -> Self {
+        Self {
+            version: PACKED_BATCH_VERSION,
+            spectra: Vec::new(),
+            frames: Vec::new(),
+            features: Vec::new(),
+            deconvolved_features: Vec::new(),
+        }
+    }
+}
+
+impl PackedBatch {
+    /// Add `spectrum`, decoding it first if it was read lazily so the batch carries its peaks
+    pub fn push_spectrum(&mut self, mut spectrum: WebSpectrum) {
+        if let Err(e) = spectrum.decode_arrays() {
+            log::warn!("Failed to decode spectrum {}: {e}", spectrum.id());
+        }
+        self.spectra.push(spectrum);
+    }
+}
+
+#[wasm_bindgen]
+impl PackedBatch {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen(js_name = "addSpectrum")]
+    pub fn add_spectrum(&mut self, spectrum: &WebSpectrum) {
+        self.push_spectrum(spectrum.clone());
+    }
+
+    #[wasm_bindgen(js_name = "addFrame")]
+    pub fn add_frame(&mut self, frame: &WebIonMobilityFrame) {
+        self.frames.push(frame.clone());
+    }
+
+    #[wasm_bindgen(js_name = "addFeature")]
+    pub fn add_feature(&mut self, feature: &WebFeature) {
+        self.features.push(feature.clone());
+    }
+
+    #[wasm_bindgen(js_name = "addDeconvolvedFeature")]
+    pub fn add_deconvolved_feature(&mut self, feature: &WebDeconvolvedFeature) {
+        self.deconvolved_features.push(feature.clone());
+    }
+
+    pub fn spectra(&self) -> Vec<WebSpectrum> {
+        self.spectra.clone()
+    }
+
+    pub fn frames(&self) -> Vec<WebIonMobilityFrame> {
+        self.frames.clone()
+    }
+
+    pub fn features(&self) -> Vec<WebFeature> {
+        self.features.iter().map(|f| f.clone()).collect()
+    }
+
+    #[wasm_bindgen(js_name = "deconvolvedFeatures")]
+    pub fn deconvolved_features(&self) -> Vec<WebDeconvolvedFeature> {
+        self.deconvolved_features
+            .iter()
+            .map(|f| f.clone())
+            .collect()
+    }
+
+    /// The total number of entries of all kinds
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.spectra.len()
+            + self.frames.len()
+            + self.features.len()
+            + self.deconvolved_features.len()
+    }
+
+    pub fn pack(&self) -> Result<Vec<u8>, JsError> {
+        rmp_serde::to_vec_named(self).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn unpack(val: &[u8]) -> Result<PackedBatch, JsError> {
+        let this: Self = rmp_serde::from_slice(val).map_err(|e| JsError::new(&e.to_string()))?;
+        if this.version != PACKED_BATCH_VERSION {
+            return Err(JsError::new(&format!(
+                "Unsupported packed batch version {}, expected {PACKED_BATCH_VERSION}",
+                this.version
+            )));
+        }
+        Ok(this)
+    }
+}
@@ -0,0 +1,509 @@
+use mzdata::meta::{DataProcessing, ProcessingMethod, Software};
+use mzdata::params::{ControlledVocabulary, Param};
+use mzdata::prelude::*;
+use mzdata::spectrum::{ArrayType, BinaryDataArrayType, DataArray};
+use mzdata::utils::neutral_mass;
+use mzdeisotope::DeconvolvedSolutionPeak;
+use mzpeaks::{prelude::*, CentroidPeak, MZPeakSetType, MassPeakSetType, Tolerance};
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{WebSpectrum, WebTolerance};
+
+/// The shape of the m/z error curve fit to the calibrant observations
+#[wasm_bindgen(js_name = "CalibrationModel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WebCalibrationModel {
+    #[default]
+    Linear,
+    Quadratic,
+}
+
+impl WebCalibrationModel {
+    fn degree(&self) -> usize {
+        match self {
+            Self::Linear => 1,
+            Self::Quadratic => 2,
+        }
+    }
+}
+
+/// Whether one calibration curve is fit to the whole run, or one per scan
+#[wasm_bindgen(js_name = "CalibrationScope")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WebCalibrationScope {
+    #[default]
+    RunWide,
+    PerScan,
+}
+
+/// The ID of the data processing entry describing the reader's current calibration
+const MASS_CALIBRATION_PROCESSING_ID: &str = "mass_calibration";
+
+/// The m/z values are divided by this before fitting to keep the normal equations well conditioned
+const MZ_SCALE: f64 = 1000.0;
+
+/// A polynomial in m/z giving the PPM error of an observed m/z
+#[derive(Debug, Clone, Copy, Default)]
+struct ErrorCurve {
+    coefficients: [f64; 3],
+}
+
+impl ErrorCurve {
+    /// Least squares fit of `ppm_error ~ mz`, lowering the degree when there are too few points
+    fn fit(points: &[(f64, f64)], degree: usize) -> Option<Self> {
+        if points.is_empty() {
+            return None;
+        }
+        let degree = degree.min(points.len() - 1);
+        let n = degree + 1;
+        let mut lhs = [[0.0f64; 4]; 3];
+        for (mz, err) in points.iter() {
+            let x = mz / MZ_SCALE;
+            let powers = [1.0, x, x * x];
+            for i in 0..n {
+                for j in 0..n {
+                    lhs[i][j] += powers[i] * powers[j];
+                }
+                lhs[i][3] += powers[i] * err;
+            }
+        }
+
+        // Gaussian elimination with partial pivoting on the augmented system
+        for col in 0..n {
+            let pivot =
+                (col..n).max_by(|a, b| lhs[*a][col].abs().total_cmp(&lhs[*b][col].abs()))?;
+            if lhs[pivot][col].abs() < f64::EPSILON {
+                return None;
+            }
+            lhs.swap(col, pivot);
+            for row in (col + 1)..n {
+                let factor = lhs[row][col] / lhs[col][col];
+                for k in col..4 {
+                    lhs[row][k] -= factor * lhs[col][k];
+                }
+            }
+        }
+        let mut coefficients = [0.0; 3];
+        for row in (0..n).rev() {
+            let mut acc = lhs[row][3];
+            for k in (row + 1)..n {
+                acc -= lhs[row][k] * coefficients[k];
+            }
+            coefficients[row] = acc / lhs[row][row];
+        }
+        Some(Self { coefficients })
+    }
+
+    fn ppm_error(&self, mz: f64) -> f64 {
+        let x = mz / MZ_SCALE;
+        self.coefficients[0] + self.coefficients[1] * x + self.coefficients[2] * x * x
+    }
+
+    fn correct(&self, mz: f64) -> f64 {
+        mz / (1.0 + self.ppm_error(mz) * 1e-6)
+    }
+
+    fn mean<'a>(curves: impl Iterator<Item = &'a ErrorCurve>) -> Option<Self> {
+        let mut acc = [0.0; 3];
+        let mut n = 0;
+        for curve in curves {
+            for (a, c) in acc.iter_mut().zip(curve.coefficients) {
+                *a += c;
+            }
+            n += 1;
+        }
+        if n == 0 {
+            return None;
+        }
+        Some(Self {
+            coefficients: acc.map(|a| a / n as f64),
+        })
+    }
+}
+
+/// A mass calibration fit from the observed m/z of known calibrant or lock mass ions.
+///
+/// Observations are gathered with [`MassCalibration::observe`] from spectra at `msLevel`,
+/// accumulating until [`MassCalibration::clear`], then [`MassCalibration::fit`] fits the
+/// calibration curve(s) to all of them. In the `PerScan` scope the curve applied at a given
+/// time is the mean of the per-scan curves within `timeWindow` of it, or the nearest one if
+/// there are none.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct MassCalibration {
+    calibrants: Vec<f64>,
+    error_tolerance: Tolerance,
+    model: WebCalibrationModel,
+    scope: WebCalibrationScope,
+    time_window: f64,
+    ms_level: u8,
+    /// (time, observed m/z, PPM error) of each calibrant found
+    observations: Vec<(f64, f64, f64)>,
+    /// The fit curves sorted by time. The run-wide curve has a time of zero.
+    curves: Vec<(f64, ErrorCurve)>,
+}
+
+impl MassCalibration {
+    /// Find the most intense peak matching each calibrant in `spectrum`
+    pub fn observe(&mut self, spectrum: &WebSpectrum) -> usize {
+        if spectrum.ms_level() != self.ms_level {
+            return 0;
+        }
+        let decoded = spectrum.decoded();
+        let peaks = match decoded.peaks.as_ref() {
+            Some(peaks) => peaks,
+            None => return 0,
+        };
+        let time = spectrum.start_time();
+        let mut found = 0;
+        for calibrant in self.calibrants.iter().copied() {
+            let best = peaks
+                .all_peaks_for(calibrant, self.error_tolerance)
+                .iter()
+                .max_by(|a, b| a.intensity.total_cmp(&b.intensity));
+            if let Some(peak) = best {
+                self.observations
+                    .push((time, peak.mz, (peak.mz - calibrant) / calibrant * 1e6));
+                found += 1;
+            }
+        }
+        found
+    }
+
+    fn curve_at(&self, time: f64) -> Option<ErrorCurve> {
+        match self.scope {
+            WebCalibrationScope::RunWide => self.curves.first().map(|(_, c)| *c),
+            WebCalibrationScope::PerScan => {
+                if self.curves.is_empty() {
+                    return None;
+                }
+                let half = self.time_window / 2.0;
+                let start = self.curves.partition_point(|(t, _)| *t < time - half);
+                let end = self.curves.partition_point(|(t, _)| *t <= time + half);
+                if start < end {
+                    return ErrorCurve::mean(self.curves[start..end].iter().map(|(_, c)| c));
+                }
+                let i = self.curves.partition_point(|(t, _)| *t < time);
+                let nearest = if i == 0 {
+                    0
+                } else if i == self.curves.len()
+                    || (time - self.curves[i - 1].0) <= (self.curves[i].0 - time)
+                {
+                    i - 1
+                } else {
+                    i
+                };
+                Some(self.curves[nearest].1)
+            }
+        }
+    }
+
+    /// Apply the calibration to the peaks, raw m/z array and precursor m/z of `spectrum`
+    pub fn apply(&self, spectrum: &mut WebSpectrum) {
+        let time = spectrum.start_time();
+        let curve = match self.curve_at(time) {
+            Some(curve) => curve,
+            None => return,
+        };
+        let inner = spectrum.as_mut();
+
+        if let Some(peaks) = inner.peaks.as_ref() {
+            let peaks: MZPeakSetType<CentroidPeak> = peaks
+                .iter()
+                .map(|p| CentroidPeak::new(curve.correct(p.mz), p.intensity, p.index))
+                .collect();
+            inner.peaks = Some(peaks);
+        }
+
+        if let Some(peaks) = inner.deconvoluted_peaks.as_ref() {
+            let peaks: MassPeakSetType<DeconvolvedSolutionPeak> = peaks
+                .iter()
+                .map(|p| {
+                    let mut p = p.clone();
+                    p.neutral_mass = neutral_mass(curve.correct(p.mz()), p.charge);
+                    for point in p.envelope.iter_mut() {
+                        point.mz = curve.correct(point.mz);
+                    }
+                    p
+                })
+                .collect();
+            inner.deconvoluted_peaks = Some(peaks);
+        }
+
+        if let Some(arrays) = inner.arrays.as_mut() {
+            let corrected = arrays.mzs().map(|mzs| {
+                mzs.iter()
+                    .map(|mz| curve.correct(*mz))
+                    .collect::<Vec<f64>>()
+            });
+            if let Ok(corrected) = corrected {
+                let mut array = DataArray::from_name_and_type(
+                    &ArrayType::MZArray,
+                    BinaryDataArrayType::Float64,
+                );
+                array.extend(&corrected).unwrap();
+                arrays.add(array);
+            }
+        }
+
+        if let Some(precursor) = inner.description_mut().precursor.as_mut() {
+            for ion in precursor.ions.iter_mut() {
+                ion.mz = curve.correct(ion.mz);
+            }
+        }
+    }
+
+    /// Record the calibration as a data processing entry in `metadata`, replacing the
+    /// entry for any calibration recorded before it
+    pub fn record_processing<M: MSDataFileMetadata>(&self, metadata: &mut M) {
+        let software_id = "mzdata_wasm";
+        if !metadata.softwares().iter().any(|s| s.id == software_id) {
+            let mut software = Software::default();
+            software.id = software_id.to_string();
+            software.version = env!("CARGO_PKG_VERSION").to_string();
+            let mut param = Param::new();
+            param.name = "custom unreleased software tool".to_string();
+            param.accession = Some(1000799);
+            param.controlled_vocabulary = Some(ControlledVocabulary::MS);
+            param.value = env!("CARGO_PKG_NAME").into();
+            software.params.push(param);
+            metadata.softwares_mut().push(software);
+        }
+
+        let mut method = ProcessingMethod::default();
+        method.software_reference = software_id.to_string();
+        let mut param = Param::new();
+        param.name = "m/z calibration".to_string();
+        param.accession = Some(1001485);
+        param.controlled_vocabulary = Some(ControlledVocabulary::MS);
+        method.params.push(param);
+        method.params.push(Param::new_key_value(
+            "calibration model",
+            format!("{:?} {:?}", self.scope, self.model),
+        ));
+        method.params.push(Param::new_key_value(
+            "calibrant m/z",
+            self.calibrants
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        ));
+
+        let mut processing = DataProcessing::default();
+        processing.id = MASS_CALIBRATION_PROCESSING_ID.to_string();
+        processing.methods.push(method);
+        let processings = metadata.data_processings_mut();
+        match processings
+            .iter_mut()
+            .find(|p| p.id == MASS_CALIBRATION_PROCESSING_ID)
+        {
+            Some(existing) => *existing = processing,
+            None => processings.push(processing),
+        }
+    }
+
+    /// Remove the data processing entry added by [`MassCalibration::record_processing`]
+    pub fn remove_processing<M: MSDataFileMetadata>(metadata: &mut M) {
+        metadata
+            .data_processings_mut()
+            .retain(|p| p.id != MASS_CALIBRATION_PROCESSING_ID);
+    }
+}
+
+#[wasm_bindgen]
+impl MassCalibration {
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        calibrants: Vec<f64>,
+        error_tolerance: &WebTolerance,
+        model: Option<WebCalibrationModel>,
+        scope: Option<WebCalibrationScope>,
+    ) -> Self {
+        Self {
+            calibrants,
+            error_tolerance: (*error_tolerance).into(),
+            model: model.unwrap_or_default(),
+            scope: scope.unwrap_or_default(),
+            time_window: 1.0,
+            ms_level: 1,
+            observations: Vec::new(),
+            curves: Vec::new(),
+        }
+    }
+
+    /// The width of the retention time window per-scan curves are averaged over
+    #[wasm_bindgen(getter, js_name = "timeWindow")]
+    pub fn time_window(&self) -> f64 {
+        self.time_window
+    }
+
+    #[wasm_bindgen(setter, js_name = "timeWindow")]
+    pub fn set_time_window(&mut self, value: f64) {
+        self.time_window = value;
+    }
+
+    /// The MS level of the spectra calibrants are looked for in
+    #[wasm_bindgen(getter, js_name = "msLevel")]
+    pub fn ms_level(&self) -> u8 {
+        self.ms_level
+    }
+
+    #[wasm_bindgen(setter, js_name = "msLevel")]
+    pub fn set_ms_level(&mut self, value: u8) {
+        self.ms_level = value;
+    }
+
+    #[wasm_bindgen(getter, js_name = "observationCount")]
+    pub fn observation_count(&self) -> usize {
+        self.observations.len()
+    }
+
+    #[wasm_bindgen(getter, js_name = "curveCount")]
+    pub fn curve_count(&self) -> usize {
+        self.curves.len()
+    }
+
+    /// Discard the gathered observations and the fit curves
+    pub fn clear(&mut self) {
+        self.observations.clear();
+        self.curves.clear();
+    }
+
+    /// Fit the calibration to the calibrant observations in `spectra` alone, discarding
+    /// any gathered before
+    #[wasm_bindgen(js_name = "fitSpectra")]
+    pub fn fit_spectra(&mut self, spectra: Vec<WebSpectrum>) -> usize {
+        self.clear();
+        for spectrum in spectra.iter() {
+            self.observe(spectrum);
+        }
+        self.fit()
+    }
+
+    /// Fit the calibration curve(s) from the gathered observations, returning the number of curves
+    pub fn fit(&mut self) -> usize {
+        let degree = self.model.degree();
+        self.curves.clear();
+        match self.scope {
+            WebCalibrationScope::RunWide => {
+                let points: Vec<(f64, f64)> = self
+                    .observations
+                    .iter()
+                    .map(|(_, mz, e)| (*mz, *e))
+                    .collect();
+                if let Some(curve) = ErrorCurve::fit(&points, degree) {
+                    self.curves.push((0.0, curve));
+                }
+            }
+            WebCalibrationScope::PerScan => {
+                self.observations.sort_by(|a, b| a.0.total_cmp(&b.0));
+                for scan in self.observations.chunk_by(|a, b| a.0 == b.0) {
+                    let points: Vec<(f64, f64)> = scan.iter().map(|(_, mz, e)| (*mz, *e)).collect();
+                    if let Some(curve) = ErrorCurve::fit(&points, degree) {
+                        self.curves.push((scan[0].0, curve));
+                    }
+                }
+            }
+        }
+        self.curves.len()
+    }
+
+    /// The PPM error the calibration predicts for `mz` at `time`
+    #[wasm_bindgen(js_name = "ppmErrorAt")]
+    pub fn ppm_error_at(&self, time: f64, mz: f64) -> f64 {
+        self.curve_at(time)
+            .map(|c| c.ppm_error(mz))
+            .unwrap_or_default()
+    }
+
+    /// The calibrated value of `mz` observed at `time`
+    #[wasm_bindgen(js_name = "correctMz")]
+    pub fn correct_mz(&self, time: f64, mz: f64) -> f64 {
+        self.curve_at(time).map(|c| c.correct(mz)).unwrap_or(mz)
+    }
+
+    #[wasm_bindgen(js_name = "applyTo")]
+    pub fn apply_to(&self, spectrum: &mut WebSpectrum) {
+        self.apply(spectrum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibration(model: WebCalibrationModel, scope: WebCalibrationScope) -> MassCalibration {
+        MassCalibration::new(vec![], &WebTolerance::ppm(10.0), Some(model), Some(scope))
+    }
+
+    #[test]
+    fn test_fit_linear_drift() {
+        let drift = |mz: f64| 2.0 + 3.0 * mz / MZ_SCALE;
+        let points: Vec<_> = (1..=10)
+            .map(|i| 100.0 * i as f64)
+            .map(|mz| (mz, drift(mz)))
+            .collect();
+        let curve = ErrorCurve::fit(&points, 1).unwrap();
+        assert!((curve.coefficients[0] - 2.0).abs() < 1e-9);
+        assert!((curve.coefficients[1] - 3.0).abs() < 1e-9);
+        assert_eq!(curve.coefficients[2], 0.0);
+
+        // An ion at 1500 observed with the drift is corrected back to its true m/z
+        let observed = 1500.0 * (1.0 + drift(1500.0) * 1e-6);
+        assert!((curve.correct(observed) - 1500.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fit_quadratic_drift() {
+        let drift = |mz: f64| {
+            let x = mz / MZ_SCALE;
+            -1.0 + 4.0 * x - 2.5 * x * x
+        };
+        let points: Vec<_> = (1..=12)
+            .map(|i| 150.0 * i as f64)
+            .map(|mz| (mz, drift(mz)))
+            .collect();
+        let curve = ErrorCurve::fit(&points, 2).unwrap();
+        for (c, expected) in curve.coefficients.iter().zip([-1.0, 4.0, -2.5]) {
+            assert!((c - expected).abs() < 1e-6, "{c} != {expected}");
+        }
+        for mz in [250.0, 875.0, 1600.0] {
+            assert!((curve.ppm_error(mz) - drift(mz)).abs() < 1e-6);
+        }
+
+        // Too few points lower the degree rather than failing
+        let curve = ErrorCurve::fit(&points[..1], 2).unwrap();
+        assert!((curve.ppm_error(5000.0) - drift(points[0].0)).abs() < 1e-9);
+        assert!(ErrorCurve::fit(&[], 2).is_none());
+    }
+
+    #[test]
+    fn test_curve_at() {
+        let mut calibration =
+            calibration(WebCalibrationModel::Linear, WebCalibrationScope::PerScan);
+        calibration.time_window = 1.0;
+        // A constant offset of 1, 3 and 8 PPM in scans at 1, 1.2 and 5 minutes
+        for (time, offset) in [(1.0, 1.0), (1.2, 3.0), (5.0, 8.0)] {
+            for mz in [400.0, 800.0] {
+                calibration.observations.push((time, mz, offset));
+            }
+        }
+        assert_eq!(calibration.fit(), 3);
+        // Curves within half the window of the time are averaged
+        assert!((calibration.ppm_error_at(1.1, 600.0) - 2.0).abs() < 1e-9);
+        assert!((calibration.ppm_error_at(5.2, 600.0) - 8.0).abs() < 1e-9);
+        // Otherwise the nearest curve is used
+        assert!((calibration.ppm_error_at(3.0, 600.0) - 3.0).abs() < 1e-9);
+        assert!((calibration.ppm_error_at(9.0, 600.0) - 8.0).abs() < 1e-9);
+
+        calibration.scope = WebCalibrationScope::RunWide;
+        assert_eq!(calibration.fit(), 1);
+        let mean = (1.0 + 3.0 + 8.0) / 3.0;
+        assert!((calibration.ppm_error_at(3.0, 600.0) - mean).abs() < 1e-9);
+
+        calibration.clear();
+        assert_eq!(calibration.observation_count(), 0);
+        assert_eq!(calibration.ppm_error_at(3.0, 600.0), 0.0);
+    }
+}
@@ -0,0 +1,301 @@
+use mzdata::params::Param;
+use mzdata::prelude::*;
+use mzdata::spectrum::{
+    PeakDataLevel, Precursor, ScanEvent, SelectedIon, SignalContinuity, SpectrumDescription,
+};
+use mzpeaks::{CentroidPeak, MZPeakSetType, Tolerance};
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{WebSpectrum, WebTolerance};
+
+/// Options controlling how MSn spectra are grouped and how their peaks are merged
+#[wasm_bindgen(js_name = "ConsensusParams")]
+#[derive(Debug, Clone, Copy)]
+pub struct WebConsensusParams {
+    precursor_tolerance: Tolerance,
+    fragment_tolerance: Tolerance,
+    /// The largest retention time difference between a spectrum and its group's first member
+    #[wasm_bindgen(js_name = "timeWindow")]
+    pub time_window: f64,
+    /// The fraction of group members a fragment peak must be found in to be kept
+    #[wasm_bindgen(js_name = "minFraction")]
+    pub min_fraction: f64,
+    /// The smallest group that will produce a consensus spectrum
+    #[wasm_bindgen(js_name = "minMembers")]
+    pub min_members: usize,
+}
+
+impl Default for WebConsensusParams {
+    fn default() -> Self {
+        Self {
+            precursor_tolerance: Tolerance::PPM(10.0),
+            fragment_tolerance: Tolerance::Da(0.02),
+            time_window: 1.0,
+            min_fraction: 0.5,
+            min_members: 2,
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "ConsensusParams")]
+impl WebConsensusParams {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen(getter, js_name = "precursorTolerance")]
+    pub fn precursor_tolerance(&self) -> WebTolerance {
+        self.precursor_tolerance.into()
+    }
+
+    #[wasm_bindgen(setter, js_name = "precursorTolerance")]
+    pub fn set_precursor_tolerance(&mut self, value: WebTolerance) {
+        self.precursor_tolerance = value.into();
+    }
+
+    #[wasm_bindgen(getter, js_name = "fragmentTolerance")]
+    pub fn fragment_tolerance(&self) -> WebTolerance {
+        self.fragment_tolerance.into()
+    }
+
+    #[wasm_bindgen(setter, js_name = "fragmentTolerance")]
+    pub fn set_fragment_tolerance(&mut self, value: WebTolerance) {
+        self.fragment_tolerance = value.into();
+    }
+}
+
+/// The parts of an MSn spectrum needed to group it and merge its peaks
+#[derive(Debug, Clone)]
+pub struct ConsensusMember {
+    id: String,
+    precursor_mz: f64,
+    charge: Option<i32>,
+    time: f64,
+    peaks: Vec<(f64, f32)>,
+}
+
+impl ConsensusMember {
+    pub fn from_spectrum(spectrum: &WebSpectrum) -> Option<Self> {
+        let ion = spectrum.as_ref().precursor().and_then(|p| p.ions.first())?;
+        Some(Self {
+            id: spectrum.id(),
+            precursor_mz: ion.mz,
+            charge: ion.charge,
+            time: spectrum.start_time(),
+            peaks: spectrum
+                .peak_array()
+                .into_iter()
+                .map(|p| (p.mz, p.intensity))
+                .collect(),
+        })
+    }
+}
+
+/// Group members by precursor m/z, charge and time, in m/z order
+fn group_members(
+    mut members: Vec<ConsensusMember>,
+    params: &WebConsensusParams,
+) -> Vec<Vec<ConsensusMember>> {
+    members.sort_by(|a, b| a.precursor_mz.total_cmp(&b.precursor_mz));
+    let mut groups: Vec<Vec<ConsensusMember>> = Vec::new();
+    // Groups that may still accept members, as indices into `groups`
+    let mut open: Vec<usize> = Vec::new();
+    for member in members {
+        open.retain(|i| {
+            let anchor = &groups[*i][0];
+            params
+                .precursor_tolerance
+                .test(member.precursor_mz, anchor.precursor_mz)
+        });
+        let slot = open.iter().copied().find(|i| {
+            let anchor = &groups[*i][0];
+            anchor.charge == member.charge
+                && (anchor.time - member.time).abs() <= params.time_window
+        });
+        match slot {
+            Some(i) => groups[i].push(member),
+            None => {
+                groups.push(vec![member]);
+                open.push(groups.len() - 1);
+            }
+        }
+    }
+    groups
+}
+
+/// Merge the fragment peaks of a group, keeping those found in enough members
+fn merge_peaks(group: &[ConsensusMember], params: &WebConsensusParams) -> Vec<CentroidPeak> {
+    let mut pooled: Vec<(f64, f32, usize)> = group
+        .iter()
+        .enumerate()
+        .flat_map(|(k, m)| m.peaks.iter().map(move |(mz, i)| (*mz, *i, k)))
+        .collect();
+    pooled.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let n = group.len() as f64;
+    let min_count = (params.min_fraction * n).ceil().max(1.0) as usize;
+    let mut peaks = Vec::new();
+    let mut start = 0;
+    while start < pooled.len() {
+        let anchor = pooled[start].0;
+        let mut end = start + 1;
+        while end < pooled.len() && params.fragment_tolerance.test(pooled[end].0, anchor) {
+            end += 1;
+        }
+        let cluster = &pooled[start..end];
+        let mut seen = vec![false; group.len()];
+        for (_, _, k) in cluster {
+            seen[*k] = true;
+        }
+        let count = seen.iter().filter(|s| **s).count();
+        if count >= min_count {
+            let total: f64 = cluster.iter().map(|(_, i, _)| *i as f64).sum();
+            let mz = if total > 0.0 {
+                cluster.iter().map(|(mz, i, _)| mz * *i as f64).sum::<f64>() / total
+            } else {
+                cluster.iter().map(|(mz, _, _)| mz).sum::<f64>() / cluster.len() as f64
+            };
+            peaks.push(CentroidPeak::new(mz, (total / n) as f32, 0));
+        }
+        start = end;
+    }
+    peaks
+}
+
+fn build_consensus(
+    index: usize,
+    group: &[ConsensusMember],
+    params: &WebConsensusParams,
+) -> WebSpectrum {
+    let n = group.len() as f64;
+    let precursor_mz = group.iter().map(|m| m.precursor_mz).sum::<f64>() / n;
+    let time = group.iter().map(|m| m.time).sum::<f64>() / n;
+
+    let mut description = SpectrumDescription::default();
+    description.id = format!("index={index} consensus={}", group.len());
+    description.index = index;
+    description.ms_level = 2;
+    description.signal_continuity = SignalContinuity::Centroid;
+
+    let mut event = ScanEvent::default();
+    event.start_time = time;
+    description.acquisition.scans.push(event);
+
+    let mut ion = SelectedIon::default();
+    ion.mz = precursor_mz;
+    ion.charge = group[0].charge;
+    let mut precursor = Precursor::default();
+    precursor.ions.push(ion);
+    description.precursor = Some(precursor);
+
+    description.add_param(Param::new_key_value(
+        "consensus member count",
+        group.len().to_string(),
+    ));
+    description.add_param(Param::new_key_value(
+        "consensus member scan ids",
+        group
+            .iter()
+            .map(|m| m.id.as_str())
+            .collect::<Vec<_>>()
+            .join(","),
+    ));
+
+    let peaks: MZPeakSetType<CentroidPeak> = merge_peaks(group, params).into_iter().collect();
+    WebSpectrum::new_with_peaks(description, PeakDataLevel::Centroid(peaks))
+}
+
+/// Group `members` and build a consensus spectrum for every sufficiently large group
+pub fn consensus_spectra(
+    members: Vec<ConsensusMember>,
+    params: &WebConsensusParams,
+) -> Vec<WebSpectrum> {
+    group_members(members, params)
+        .into_iter()
+        .filter(|g| g.len() >= params.min_members)
+        .enumerate()
+        .map(|(i, g)| build_consensus(i, &g, params))
+        .collect()
+}
+
+/// Merge repeated MSn spectra of the same precursor into consensus spectra
+#[wasm_bindgen(js_name = "buildConsensusSpectra")]
+pub fn build_consensus_spectra(
+    spectra: Vec<WebSpectrum>,
+    params: Option<WebConsensusParams>,
+) -> Vec<WebSpectrum> {
+    let params = params.unwrap_or_default();
+    let members = spectra
+        .iter()
+        .filter(|s| s.ms_level() > 1)
+        .filter_map(ConsensusMember::from_spectrum)
+        .collect();
+    consensus_spectra(members, &params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(id: &str, precursor_mz: f64, charge: i32, time: f64) -> ConsensusMember {
+        ConsensusMember {
+            id: id.to_string(),
+            precursor_mz,
+            charge: Some(charge),
+            time,
+            peaks: Vec::new(),
+        }
+    }
+
+    fn ids(groups: &[Vec<ConsensusMember>]) -> Vec<Vec<&str>> {
+        groups
+            .iter()
+            .map(|g| g.iter().map(|m| m.id.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_group_members() {
+        let members = vec![
+            member("a", 500.0, 2, 10.0),
+            member("b", 500.002, 2, 10.2),
+            // Within the precursor tolerance of "a", but a different charge
+            member("c", 500.001, 3, 10.0),
+            // Outside the time window of "a"
+            member("d", 500.0, 2, 30.0),
+            member("e", 800.0, 2, 10.0),
+        ];
+        let groups = group_members(members, &WebConsensusParams::default());
+        assert_eq!(
+            ids(&groups),
+            [vec!["a", "b"], vec!["d"], vec!["c"], vec!["e"]]
+        );
+    }
+
+    #[test]
+    fn test_consensus_spectra() {
+        let mut a = member("a", 500.0, 2, 10.0);
+        a.peaks = vec![(100.0, 10.0), (200.0, 10.0)];
+        let mut b = member("b", 500.002, 2, 10.2);
+        b.peaks = vec![(100.001, 30.0), (300.0, 5.0)];
+        let c = member("c", 800.0, 2, 10.0);
+
+        let mut params = WebConsensusParams::default();
+        params.min_fraction = 1.0;
+        let spectra = consensus_spectra(vec![a, b, c], &params);
+        // "c" has no partner, so it is too small a group to keep
+        assert_eq!(spectra.len(), 1);
+        let spectrum = &spectra[0];
+        assert_eq!(spectrum.ms_level(), 2);
+        let ion = &spectrum.as_ref().precursor().unwrap().ions[0];
+        assert!((ion.mz - 500.001).abs() < 1e-9);
+        assert_eq!(ion.charge, Some(2));
+
+        // Only the fragment found in both members is kept, intensity weighted
+        let peaks = spectrum.peak_array();
+        assert_eq!(peaks.len(), 1);
+        assert!((peaks[0].mz - (100.0 * 10.0 + 100.001 * 30.0) / 40.0).abs() < 1e-9);
+        assert_eq!(peaks[0].intensity, 20.0);
+    }
+}
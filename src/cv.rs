@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use mzdata::params::{ControlledVocabulary, Param, ParamLike, Unit, CURIE};
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{param_value_from_js, WebParam};
+
+/// The PSI-MS and Unit Ontology terms, regenerated from the OBO files with `just cv`. See
+/// the header of `psi_ms_terms.tsv` for the layout.
+const TERM_TABLE: &str = include_str!("psi_ms_terms.tsv");
+
+#[derive(Debug)]
+struct TermRecord {
+    accession: &'static str,
+    name: &'static str,
+    parents: Vec<&'static str>,
+    unit: Option<&'static str>,
+    definition: &'static str,
+}
+
+#[derive(Debug, Default)]
+struct TermTable {
+    terms: Vec<TermRecord>,
+    by_accession: HashMap<&'static str, usize>,
+    by_name: HashMap<String, usize>,
+    children: Vec<Vec<usize>>,
+}
+
+impl TermTable {
+    fn parse(text: &'static str) -> Self {
+        let mut this = Self::default();
+        for line in text.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let mut next = || fields.next().unwrap_or_default().trim();
+            let accession = next();
+            let name = next();
+            let parents = next().split(',').filter(|p| !p.is_empty()).collect();
+            let unit = Some(next()).filter(|u| !u.is_empty());
+            let definition = next();
+            this.by_accession.insert(accession, this.terms.len());
+            this.by_name.insert(name.to_lowercase(), this.terms.len());
+            this.terms.push(TermRecord {
+                accession,
+                name,
+                parents,
+                unit,
+                definition,
+            });
+        }
+        this.children = vec![Vec::new(); this.terms.len()];
+        for (i, term) in this.terms.iter().enumerate() {
+            for parent in term.parents.iter() {
+                if let Some(j) = this.by_accession.get(parent) {
+                    this.children[*j].push(i);
+                }
+            }
+        }
+        this
+    }
+
+    /// Find a term by accession CURIE or by case-insensitive name
+    fn find(&self, key: &str) -> Option<usize> {
+        self.by_accession
+            .get(key)
+            .or_else(|| self.by_name.get(&key.to_lowercase()))
+            .copied()
+    }
+
+    fn ancestors(&self, index: usize) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut stack = vec![index];
+        while let Some(i) = stack.pop() {
+            for parent in self.terms[i].parents.iter() {
+                if let Some(j) = self.by_accession.get(parent) {
+                    if !seen.contains(j) {
+                        seen.push(*j);
+                        stack.push(*j);
+                    }
+                }
+            }
+        }
+        seen
+    }
+
+    fn descendants(&self, index: usize) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut stack = vec![index];
+        while let Some(i) = stack.pop() {
+            for j in self.children[i].iter() {
+                if !seen.contains(j) {
+                    seen.push(*j);
+                    stack.push(*j);
+                }
+            }
+        }
+        seen
+    }
+
+    fn is_a(&self, index: usize, parent: usize) -> bool {
+        index == parent || self.ancestors(index).contains(&parent)
+    }
+}
+
+fn table() -> &'static TermTable {
+    static TABLE: OnceLock<TermTable> = OnceLock::new();
+    TABLE.get_or_init(|| TermTable::parse(TERM_TABLE))
+}
+
+/// Whether the term `term` is `parent` or one of its descendants, each given by accession or name.
+///
+/// Terms not in the term table are never related to anything but themselves.
+pub fn term_is_a(term: &str, parent: &str) -> bool {
+    let table = table();
+    match (table.find(term), table.find(parent)) {
+        (Some(i), Some(j)) => table.is_a(i, j),
+        _ => term == parent,
+    }
+}
+
+fn param_accession(param: &Param) -> Option<String> {
+    param.curie().map(|c| c.to_string())
+}
+
+/// A term from the PSI-MS controlled vocabulary or the Unit Ontology
+#[wasm_bindgen(js_name = "CVTerm")]
+#[derive(Debug, Clone, Copy)]
+pub struct WebCVTerm(usize);
+
+impl WebCVTerm {
+    fn record(&self) -> &'static TermRecord {
+        &table().terms[self.0]
+    }
+}
+
+#[wasm_bindgen(js_class = "CVTerm")]
+impl WebCVTerm {
+    /// Find a term by accession, like `MS:1000511`, or by name, ignoring case
+    pub fn lookup(key: &str) -> Option<WebCVTerm> {
+        table().find(key).map(Self)
+    }
+
+    /// All the terms whose name contains `text`, ignoring case
+    pub fn search(text: &str) -> Vec<WebCVTerm> {
+        let text = text.to_lowercase();
+        table()
+            .terms
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.name.to_lowercase().contains(&text))
+            .map(|(i, _)| Self(i))
+            .collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn accession(&self) -> String {
+        self.record().accession.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.record().name.to_string()
+    }
+
+    /// The first sentence of the term's definition
+    #[wasm_bindgen(getter)]
+    pub fn definition(&self) -> String {
+        self.record().definition.to_string()
+    }
+
+    /// The accessions of the direct parents of this term
+    #[wasm_bindgen(getter)]
+    pub fn parents(&self) -> Vec<String> {
+        self.record()
+            .parents
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    /// The accession of the unit values of this term are usually given in
+    #[wasm_bindgen(getter, js_name = "unitAccession")]
+    pub fn unit_accession(&self) -> Option<String> {
+        self.record().unit.map(|u| u.to_string())
+    }
+
+    #[wasm_bindgen(getter, js_name = "unitName")]
+    pub fn unit_name(&self) -> Option<String> {
+        self.record()
+            .unit
+            .and_then(|u| table().find(u))
+            .map(|i| table().terms[i].name.to_string())
+    }
+
+    /// Whether this term is `parent` or one of its descendants, given by accession or name
+    #[wasm_bindgen(js_name = "isA")]
+    pub fn is_a(&self, parent: &str) -> bool {
+        table()
+            .find(parent)
+            .is_some_and(|j| table().is_a(self.0, j))
+    }
+
+    pub fn ancestors(&self) -> Vec<WebCVTerm> {
+        table().ancestors(self.0).into_iter().map(Self).collect()
+    }
+
+    pub fn children(&self) -> Vec<WebCVTerm> {
+        table().children[self.0].iter().copied().map(Self).collect()
+    }
+
+    pub fn descendants(&self) -> Vec<WebCVTerm> {
+        table().descendants(self.0).into_iter().map(Self).collect()
+    }
+
+    /// Create a parameter for this term with `value`, in the term's usual unit
+    #[wasm_bindgen(js_name = "toParam")]
+    pub fn to_param(&self, value: JsValue) -> Result<WebParam, JsError> {
+        let record = self.record();
+        let curie: CURIE = record
+            .accession
+            .parse()
+            .map_err(|e| JsError::new(&format!("Invalid accession {}: {e:?}", record.accession)))?;
+        let mut param = Param::new_key_value(record.name, param_value_from_js(&value)?);
+        param.controlled_vocabulary = Some(curie.controlled_vocabulary);
+        param.accession = Some(curie.accession);
+        if let Some(unit) = record.unit {
+            param.unit = Unit::from_accession(unit);
+        }
+        Ok(param.into())
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        let record = self.record();
+        let entries = js_sys::Array::of5(
+            &js_sys::Array::of2(&"accession".into(), &record.accession.into()),
+            &js_sys::Array::of2(&"name".into(), &record.name.into()),
+            &js_sys::Array::of2(&"definition".into(), &record.definition.into()),
+            &js_sys::Array::of2(&"parents".into(), &self.parents().into()),
+            &js_sys::Array::of2(&"unit".into(), &self.unit_accession().into()),
+        );
+        js_sys::Object::from_entries(&entries)
+            .map(JsValue::from)
+            .map_err(|e| JsError::new(&format!("{e:?}")))
+    }
+}
+
+#[wasm_bindgen(js_class = "Param")]
+impl WebParam {
+    /// Create a parameter for the CV term `accession`, named from the term table.
+    ///
+    /// `unit` is a unit accession like `UO:0000010`, defaulting to the term's usual unit.
+    #[wasm_bindgen(js_name = "fromAccession")]
+    pub fn from_accession(
+        accession: &str,
+        value: JsValue,
+        unit: Option<String>,
+    ) -> Result<WebParam, JsError> {
+        let term = WebCVTerm::lookup(accession)
+            .ok_or_else(|| JsError::new(&format!("{accession} is not a known CV term")))?;
+        let param = term.to_param(value)?;
+        let mut param: Param = param.into();
+        if let Some(unit) = unit {
+            param.unit = Unit::from_accession(&unit);
+            if param.unit == Unit::Unknown {
+                return Err(JsError::new(&format!("{unit} is not a known unit")));
+            }
+        }
+        Ok(param.into())
+    }
+
+    /// The CV term of this parameter, if it is controlled by one in the term table
+    #[wasm_bindgen(getter)]
+    pub fn term(&self) -> Option<WebCVTerm> {
+        param_accession(self.as_param()).and_then(|acc| WebCVTerm::lookup(&acc))
+    }
+
+    /// Whether this parameter's term is `parent` or one of its descendants
+    #[wasm_bindgen(js_name = "isA")]
+    pub fn is_a(&self, parent: &str) -> bool {
+        param_accession(self.as_param()).is_some_and(|acc| term_is_a(&acc, parent))
+    }
+
+    #[wasm_bindgen(getter, js_name = "isControlled")]
+    pub fn is_controlled(&self) -> bool {
+        self.as_param().controlled_vocabulary.is_some()
+    }
+
+    #[wasm_bindgen(getter, js_name = "isPSIMS")]
+    pub fn is_psims(&self) -> bool {
+        self.as_param().controlled_vocabulary == Some(ControlledVocabulary::MS)
+    }
+
+    /// The accession of this parameter's unit, like `UO:0000031`
+    #[wasm_bindgen(getter, js_name = "unitAccession")]
+    pub fn unit_accession(&self) -> Option<String> {
+        let unit = self.as_param().unit;
+        (unit != Unit::Unknown).then(|| unit.for_param().0.to_string())
+    }
+
+    #[wasm_bindgen(getter, js_name = "unitName")]
+    pub fn unit_name(&self) -> Option<String> {
+        let unit = self.as_param().unit;
+        (unit != Unit::Unknown).then(|| unit.for_param().1.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parents_resolve() {
+        let table = TermTable::parse(TERM_TABLE);
+        assert!(!table.terms.is_empty());
+        for term in table.terms.iter() {
+            for parent in term.parents.iter() {
+                assert!(
+                    table.by_accession.contains_key(parent),
+                    "{} has unknown parent {parent}",
+                    term.accession
+                );
+            }
+            if let Some(unit) = term.unit {
+                assert!(
+                    table.by_accession.contains_key(unit),
+                    "{} has unknown unit {unit}",
+                    term.accession
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_a() {
+        assert!(term_is_a("MS:1000016", "MS:0000000"));
+        assert!(term_is_a("scan start time", "scan start time"));
+        assert!(!term_is_a("MS:0000000", "MS:1000016"));
+        let term = WebCVTerm::lookup("MS Level").unwrap();
+        assert_eq!(term.accession(), "MS:1000511");
+    }
+}
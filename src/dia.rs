@@ -0,0 +1,503 @@
+use std::collections::{HashMap, HashSet};
+
+use mzdata::prelude::*;
+use mzpeaks::Tolerance;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::binds::WebSpectrum;
+use crate::pipeline::PipelineSpectrum;
+use crate::qc::WebQCDistribution;
+
+/// How close two isolation window bounds have to be to count as the same window
+const WINDOW_TOLERANCE: f32 = 0.01;
+
+/// An isolation window acquired repeatedly over a data-independent acquisition run
+#[wasm_bindgen(inspectable, js_name = "DIAWindow")]
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDIAWindow {
+    /// The position of this window in the scheme, in order of lower bound
+    pub index: usize,
+    #[wasm_bindgen(js_name = "lowerBound")]
+    pub lower_bound: f64,
+    #[wasm_bindgen(js_name = "upperBound")]
+    pub upper_bound: f64,
+    pub target: f64,
+    /// The number of spectra acquired with this window
+    #[wasm_bindgen(js_name = "scanCount")]
+    pub scan_count: usize,
+    /// Which of the alternating cycles of a staggered scheme this window is acquired in,
+    /// always 0 for a scheme that is not staggered
+    pub phase: usize,
+}
+
+#[wasm_bindgen(js_class = "DIAWindow")]
+impl WebDIAWindow {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> f64 {
+        self.upper_bound - self.lower_bound
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn center(&self) -> f64 {
+        (self.upper_bound + self.lower_bound) / 2.0
+    }
+
+    pub fn contains(&self, mz: f64) -> bool {
+        self.lower_bound <= mz && mz <= self.upper_bound
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(self).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// The isolation window scheme of a data-independent acquisition run, inferred
+/// from the isolation windows of its MSn spectra
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "DIAScheme")]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebDIAScheme {
+    /// The distinct isolation windows, in order of lower bound
+    pub windows: Vec<WebDIAWindow>,
+    /// Whether the windows are revisited cycle after cycle, as opposed to picked per precursor
+    #[wasm_bindgen(js_name = "isDIA")]
+    pub is_dia: bool,
+    #[wasm_bindgen(js_name = "cycleCount")]
+    pub cycle_count: usize,
+    /// The time between the starts of consecutive cycles
+    #[wasm_bindgen(js_name = "cycleTime")]
+    pub cycle_time: WebQCDistribution,
+    /// The number of MSn spectra in each cycle
+    #[wasm_bindgen(js_name = "windowsPerCycle")]
+    pub windows_per_cycle: WebQCDistribution,
+    /// The median overlap in m/z between neighbouring windows of the same cycle, negative
+    /// when there is a gap between them
+    pub overlap: f64,
+    /// Whether alternating cycles use two window sets offset from one another
+    pub staggered: bool,
+    /// The lowest m/z covered by any window
+    #[wasm_bindgen(js_name = "mzStart")]
+    pub mz_start: f64,
+    /// The highest m/z covered by any window
+    #[wasm_bindgen(js_name = "mzEnd")]
+    pub mz_end: f64,
+}
+
+#[wasm_bindgen(js_class = "DIAScheme")]
+impl WebDIAScheme {
+    /// The windows isolating `mz`
+    #[wasm_bindgen(js_name = "windowsFor")]
+    pub fn windows_for(&self, mz: f64) -> Vec<WebDIAWindow> {
+        self.windows
+            .iter()
+            .filter(|w| w.contains(mz))
+            .copied()
+            .collect()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(self).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// The extracted ion chromatograms of a set of fragment m/z in one DIA window
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "FragmentXICMatrix")]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebFragmentXICMatrix {
+    pub window: WebDIAWindow,
+    /// The start time of each spectrum, one per column
+    pub times: Vec<f64>,
+    #[wasm_bindgen(js_name = "spectrumIndices")]
+    pub spectrum_indices: Vec<usize>,
+    /// The fragment m/z of each row
+    #[wasm_bindgen(js_name = "fragmentMzs")]
+    pub fragment_mzs: Vec<f64>,
+    /// The summed intensity of the peaks matching each fragment in each spectrum,
+    /// one row per fragment in row-major order
+    pub intensities: Vec<f32>,
+}
+
+impl WebFragmentXICMatrix {
+    /// Extract the chromatogram of each of `fragment_mzs` from `spectra`, all acquired
+    /// with `window`. When `fragment_mzs` is empty, the `top_n` most intense peaks of the
+    /// spectrum with the largest total ion current are used.
+    pub fn from_spectra(
+        window: WebDIAWindow,
+        spectra: &[WebSpectrum],
+        mut fragment_mzs: Vec<f64>,
+        error_tolerance: Tolerance,
+        top_n: usize,
+    ) -> Self {
+        if fragment_mzs.is_empty() {
+            if let Some(apex) = spectra
+                .iter()
+                .max_by(|a, b| a.total_ion_current().total_cmp(&b.total_ion_current()))
+            {
+                let mut peaks = apex.peak_array();
+                peaks.sort_by(|a, b| b.intensity.total_cmp(&a.intensity));
+                fragment_mzs = peaks.into_iter().take(top_n).map(|p| p.mz).collect();
+            }
+        }
+        fragment_mzs.sort_by(|a, b| a.total_cmp(b));
+
+        let mut intensities = vec![0.0; fragment_mzs.len() * spectra.len()];
+        for (j, spectrum) in spectra.iter().enumerate() {
+            for (i, mz) in fragment_mzs.iter().copied().enumerate() {
+                intensities[i * spectra.len() + j] = spectrum
+                    .peaks_for(mz, error_tolerance)
+                    .iter()
+                    .map(|p| p.intensity)
+                    .sum();
+            }
+        }
+        Self {
+            window,
+            times: spectra.iter().map(|s| s.start_time()).collect(),
+            spectrum_indices: spectra.iter().map(|s| s.index()).collect(),
+            fragment_mzs,
+            intensities,
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "FragmentXICMatrix")]
+impl WebFragmentXICMatrix {
+    /// The chromatogram of the `i`th fragment
+    pub fn xic(&self, i: usize) -> Option<Vec<f32>> {
+        let n = self.times.len();
+        self.intensities
+            .get(i * n..(i + 1) * n)
+            .map(|row| row.to_vec())
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(self).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IsolationBounds {
+    lower: f32,
+    upper: f32,
+    target: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct DIAScan {
+    time: f64,
+    ms_level: u8,
+    window: Option<usize>,
+}
+
+/// The isolation window of every spectrum in a run, for inferring the DIA scheme
+/// and finding the spectra acquired with a given window
+#[derive(Debug, Clone, Default)]
+pub struct DIAIndex {
+    windows: Vec<IsolationBounds>,
+    scans: Vec<DIAScan>,
+}
+
+impl DIAIndex {
+    /// Build the index from every spectrum in a run, in index order. Only the
+    /// spectrum metadata is used.
+    pub fn from_spectra<I: IntoIterator<Item = PipelineSpectrum>>(spectra: I) -> Self {
+        let mut this = Self::default();
+        let mut lookup = HashMap::new();
+        for spectrum in spectra {
+            let window = spectrum
+                .precursor()
+                .map(|p| &p.isolation_window)
+                .filter(|w| spectrum.ms_level() > 1 && w.upper_bound > w.lower_bound)
+                .map(|w| {
+                    this.intern(
+                        &mut lookup,
+                        IsolationBounds {
+                            lower: w.lower_bound,
+                            upper: w.upper_bound,
+                            target: w.target,
+                        },
+                    )
+                });
+            this.scans.push(DIAScan {
+                time: spectrum.start_time(),
+                ms_level: spectrum.ms_level(),
+                window,
+            });
+        }
+        this.sort_windows();
+        this
+    }
+
+    /// The position of the window matching `bounds` within [`WINDOW_TOLERANCE`], adding it
+    /// if there is none. `lookup` holds the windows by their bounds in units of the tolerance,
+    /// so a match is in the same cell or a neighbouring one.
+    fn intern(
+        &mut self,
+        lookup: &mut HashMap<(i64, i64), usize>,
+        bounds: IsolationBounds,
+    ) -> usize {
+        let cell = |x: f32| (x / WINDOW_TOLERANCE).round() as i64;
+        let key = (cell(bounds.lower), cell(bounds.upper));
+        let found = (-1..=1)
+            .flat_map(|i| (-1..=1).map(move |j| (key.0 + i, key.1 + j)))
+            .filter_map(|k| lookup.get(&k).copied())
+            .filter(|i| {
+                let w = &self.windows[*i];
+                (w.lower - bounds.lower).abs() <= WINDOW_TOLERANCE
+                    && (w.upper - bounds.upper).abs() <= WINDOW_TOLERANCE
+            })
+            .min();
+        match found {
+            Some(i) => i,
+            None => {
+                self.windows.push(bounds);
+                lookup.insert(key, self.windows.len() - 1);
+                self.windows.len() - 1
+            }
+        }
+    }
+
+    /// Put the windows in order of lower bound, renumbering the scans to match
+    fn sort_windows(&mut self) {
+        let mut order: Vec<usize> = (0..self.windows.len()).collect();
+        order.sort_by(|a, b| {
+            let (a, b) = (&self.windows[*a], &self.windows[*b]);
+            a.lower
+                .total_cmp(&b.lower)
+                .then(a.upper.total_cmp(&b.upper))
+        });
+        let mut rank = vec![0; order.len()];
+        for (new, old) in order.iter().enumerate() {
+            rank[*old] = new;
+        }
+        self.windows = order.iter().map(|i| self.windows[*i]).collect();
+        for scan in self.scans.iter_mut() {
+            scan.window = scan.window.map(|w| rank[w]);
+        }
+    }
+
+    /// Split the run into acquisition cycles, each the start time and the windows
+    /// acquired in it. A cycle ends at an MS1 spectrum or when a window repeats.
+    fn cycles(&self) -> Vec<(f64, Vec<usize>)> {
+        let mut cycles: Vec<(f64, Vec<usize>)> = Vec::new();
+        let mut current: Option<(f64, Vec<usize>)> = None;
+        for scan in self.scans.iter() {
+            let repeats = match (&current, scan.window) {
+                (Some((_, windows)), Some(w)) => windows.contains(&w),
+                _ => false,
+            };
+            if scan.ms_level == 1 || repeats || current.is_none() {
+                cycles.extend(current.take().filter(|(_, w)| !w.is_empty()));
+                current = Some((scan.time, Vec::new()));
+            }
+            if let (Some((_, windows)), Some(w)) = (current.as_mut(), scan.window) {
+                windows.push(w);
+            }
+        }
+        cycles.extend(current.filter(|(_, w)| !w.is_empty()));
+        cycles
+    }
+
+    /// Infer the window scheme and cycle timing of the run
+    pub fn scheme(&self) -> WebDIAScheme {
+        let cycles = self.cycles();
+        let mut windows: Vec<WebDIAWindow> = self
+            .windows
+            .iter()
+            .enumerate()
+            .map(|(index, w)| WebDIAWindow {
+                index,
+                lower_bound: w.lower as f64,
+                upper_bound: w.upper as f64,
+                target: w.target as f64,
+                scan_count: 0,
+                phase: 0,
+            })
+            .collect();
+        for w in self.scans.iter().filter_map(|s| s.window) {
+            windows[w].scan_count += 1;
+        }
+
+        let windows_per_cycle =
+            WebQCDistribution::from_values(cycles.iter().map(|(_, w)| w.len() as f64));
+        let cycle_time =
+            WebQCDistribution::from_values(cycles.windows(2).map(|pair| pair[1].0 - pair[0].0));
+
+        let overlap = WebQCDistribution::from_values(cycles.iter().flat_map(|(_, ws)| {
+            let mut ws: Vec<&IsolationBounds> = ws.iter().map(|w| &self.windows[*w]).collect();
+            ws.sort_by(|a, b| a.lower.total_cmp(&b.lower));
+            ws.windows(2)
+                .map(|pair| (pair[0].upper - pair[1].lower) as f64)
+                .collect::<Vec<_>>()
+        }))
+        .median;
+
+        // A staggered scheme has about twice as many windows as each cycle acquires, with
+        // each window only ever acquired in every other cycle
+        let mut phase_counts = vec![[0usize; 2]; windows.len()];
+        for (i, (_, ws)) in cycles.iter().enumerate() {
+            for w in ws.iter().collect::<HashSet<_>>() {
+                phase_counts[*w][i % 2] += 1;
+            }
+        }
+        let alternating = phase_counts
+            .iter()
+            .filter(|[even, odd]| {
+                let total = (even + odd) as f64;
+                total > 0.0 && (*even.max(odd) as f64) >= 0.9 * total
+            })
+            .count();
+        let staggered = cycles.len() >= 4
+            && windows.len() as f64 >= 1.5 * windows_per_cycle.median
+            && alternating as f64 >= 0.9 * windows.len() as f64;
+        if staggered {
+            for (window, [even, odd]) in windows.iter_mut().zip(phase_counts.iter()) {
+                window.phase = (odd > even) as usize;
+            }
+        }
+
+        let visits_per_window =
+            WebQCDistribution::from_values(windows.iter().map(|w| w.scan_count as f64)).median;
+        let cycles_per_visit = if staggered { 2.0 } else { 1.0 };
+        let is_dia = windows_per_cycle.median >= 2.0
+            && visits_per_window * cycles_per_visit >= 0.5 * cycles.len() as f64;
+
+        WebDIAScheme {
+            mz_start: windows
+                .iter()
+                .map(|w| w.lower_bound)
+                .reduce(f64::min)
+                .unwrap_or_default(),
+            mz_end: windows
+                .iter()
+                .map(|w| w.upper_bound)
+                .reduce(f64::max)
+                .unwrap_or_default(),
+            windows,
+            is_dia,
+            cycle_count: cycles.len(),
+            cycle_time,
+            windows_per_cycle,
+            overlap,
+            staggered,
+        }
+    }
+
+    /// The indices of the spectra acquired with the window at `window` between
+    /// `start_time` and `end_time`
+    pub fn indices_in(&self, window: usize, start_time: f64, end_time: f64) -> Vec<usize> {
+        self.scans
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.window == Some(window) && s.time >= start_time && s.time <= end_time)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::TestSpectrum;
+
+    /// Build a run of MS1 spectra each followed by MS2 spectra with the windows of one
+    /// entry of `cycles`, one second apart
+    fn run(cycles: &[Vec<(f32, f32)>]) -> Vec<PipelineSpectrum> {
+        let mut spectra = Vec::new();
+        for windows in cycles {
+            spectra.push(TestSpectrum::new(spectra.len(), 1).build());
+            for (lower, upper) in windows.iter().copied() {
+                let spectrum = TestSpectrum::new(spectra.len(), 2).isolation_window(lower, upper);
+                spectra.push(spectrum.build());
+            }
+        }
+        spectra
+    }
+
+    fn tile(start: f32, width: f32, n: usize) -> Vec<(f32, f32)> {
+        (0..n)
+            .map(|i| {
+                let lower = start + width * i as f32;
+                (lower, lower + width)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cycles() {
+        let cycle = tile(400.0, 25.0, 4);
+        let index = DIAIndex::from_spectra(run(&vec![cycle; 5]));
+        let scheme = index.scheme();
+        assert!(scheme.is_dia);
+        assert!(!scheme.staggered);
+        assert_eq!(scheme.cycle_count, 5);
+        assert_eq!(scheme.windows.len(), 4);
+        assert_eq!(scheme.windows_per_cycle.median, 4.0);
+        assert!((scheme.cycle_time.median - 5.0 / 60.0).abs() < 1e-9);
+        assert!(scheme.overlap.abs() < 1e-6);
+        assert_eq!(scheme.mz_start, 400.0);
+        assert_eq!(scheme.mz_end, 500.0);
+        for (i, window) in scheme.windows.iter().enumerate() {
+            assert_eq!(window.index, i);
+            assert_eq!(window.scan_count, 5);
+            assert_eq!(window.phase, 0);
+        }
+        // The second window is the third spectrum of every cycle of five
+        assert_eq!(index.indices_in(1, 0.0, f64::INFINITY), [2, 7, 12, 17, 22]);
+    }
+
+    #[test]
+    fn test_staggered() {
+        let even = tile(400.0, 20.0, 3);
+        let odd = tile(410.0, 20.0, 3);
+        let cycles: Vec<_> = (0..6)
+            .map(|i| {
+                if i % 2 == 0 {
+                    even.clone()
+                } else {
+                    odd.clone()
+                }
+            })
+            .collect();
+        let scheme = DIAIndex::from_spectra(run(&cycles)).scheme();
+        assert!(scheme.is_dia);
+        assert!(scheme.staggered);
+        assert_eq!(scheme.cycle_count, 6);
+        assert_eq!(scheme.windows.len(), 6);
+        // Windows are sorted by lower bound, so the two window sets alternate
+        let phases: Vec<_> = scheme.windows.iter().map(|w| w.phase).collect();
+        assert_eq!(phases, [0, 1, 0, 1, 0, 1]);
+        assert!(scheme.windows.iter().all(|w| w.scan_count == 3));
+    }
+
+    #[test]
+    fn test_window_jitter() {
+        // Bounds reported a little differently in each cycle are still one window
+        let cycles = vec![
+            vec![(400.004, 425.0)],
+            vec![(399.996, 425.006)],
+            vec![(400.0, 424.995)],
+        ];
+        let index = DIAIndex::from_spectra(run(&cycles));
+        assert_eq!(index.windows.len(), 1);
+        assert_eq!(index.indices_in(0, 0.0, f64::INFINITY), [1, 3, 5]);
+    }
+
+    #[test]
+    fn test_dda() {
+        // Every MS2 isolates a different precursor, so no window is revisited
+        let cycles: Vec<_> = (0..5)
+            .map(|i| tile(400.0 + 100.0 * i as f32, 2.0, 3))
+            .collect();
+        let scheme = DIAIndex::from_spectra(run(&cycles)).scheme();
+        assert!(!scheme.is_dia);
+        assert_eq!(scheme.windows.len(), 15);
+    }
+}
@@ -0,0 +1,204 @@
+use mzdata::prelude::*;
+use mzdata::spectrum::MultiLayerSpectrum;
+use mzdeisotope::DeconvolvedSolutionPeak;
+use mzpeaks::{CentroidPeak, Tolerance};
+use wasm_bindgen::prelude::*;
+
+use crate::annotation::formula_mass;
+use crate::binds::{WebIsotopicModel, WebSpectrum};
+
+/// The default glycan oxonium ion panel as name and neutral composition pairs
+const DEFAULT_GLYCAN_PANEL: &[(&str, &str)] = &[
+    ("HexNAc", "C8H13NO5"),
+    ("HexNAc-H2O", "C8H11NO4"),
+    ("HexNAc-2H2O", "C8H9NO3"),
+    ("HexNAc-C2H6O3", "C6H7NO2"),
+    ("HexNAc-C2H4O2", "C6H9NO3"),
+    ("HexNAc-CH6O3", "C7H7NO2"),
+    ("Hex", "C6H10O5"),
+    ("dHex", "C6H10O4"),
+    ("NeuAc", "C11H17NO8"),
+    ("NeuAc-H2O", "C11H15NO7"),
+    ("NeuGc", "C11H17NO9"),
+    ("HexHexNAc", "C14H23NO10"),
+    ("NeuAcHexHexNAc", "C25H40N2O18"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct DiagnosticIon {
+    name: String,
+    neutral_mass: f64,
+}
+
+/// A panel of diagnostic ions, searched for by neutral mass in the deconvoluted
+/// peak list of each MSn spectrum.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticIonPanel {
+    ions: Vec<DiagnosticIon>,
+}
+
+#[wasm_bindgen]
+impl DiagnosticIonPanel {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The common glycan oxonium ions: HexNAc and its dehydration products, Hex,
+    /// dHex, NeuAc, NeuGc, HexHexNAc and NeuAcHexHexNAc
+    pub fn glycan() -> Self {
+        let ions = DEFAULT_GLYCAN_PANEL
+            .iter()
+            .map(|(name, formula)| DiagnosticIon {
+                name: name.to_string(),
+                neutral_mass: formula_mass(formula).unwrap(),
+            })
+            .collect();
+        Self { ions }
+    }
+
+    #[wasm_bindgen(js_name = "addIon")]
+    pub fn add_ion(&mut self, name: String, neutral_mass: f64) {
+        self.ions.push(DiagnosticIon { name, neutral_mass });
+    }
+
+    #[wasm_bindgen(js_name = "addFormula")]
+    pub fn add_formula(&mut self, name: String, formula: &str) -> Result<(), JsError> {
+        let neutral_mass = formula_mass(formula).map_err(|e| JsError::new(&e))?;
+        self.add_ion(name, neutral_mass);
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn names(&self) -> Vec<String> {
+        self.ions.iter().map(|i| i.name.clone()).collect()
+    }
+
+    #[wasm_bindgen(getter, js_name = "neutralMasses")]
+    pub fn neutral_masses(&self) -> Vec<f64> {
+        self.ions.iter().map(|i| i.neutral_mass).collect()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.ions.len()
+    }
+}
+
+impl DiagnosticIonPanel {
+    /// Deconvolve `spectrum` if needed and sum the intensity of peaks matching each ion
+    /// in the panel.
+    pub fn scan(
+        &self,
+        spectrum: MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>,
+        error_tolerance: Tolerance,
+        score_threshold: f32,
+    ) -> DiagnosticIonScan {
+        let mut spectrum = WebSpectrum::from(spectrum);
+        if spectrum.as_ref().deconvoluted_peaks.is_none() {
+            spectrum.deconvolve(score_threshold, vec![WebIsotopicModel::glycopeptide()]);
+        }
+        let intensities = match spectrum.as_ref().deconvoluted_peaks.as_ref() {
+            Some(peaks) => self
+                .ions
+                .iter()
+                .map(|ion| {
+                    peaks
+                        .all_peaks_for(ion.neutral_mass, error_tolerance)
+                        .iter()
+                        .map(|p| p.intensity)
+                        .sum::<f32>()
+                })
+                .collect(),
+            None => vec![0.0; self.ions.len()],
+        };
+        DiagnosticIonScan {
+            index: spectrum.index(),
+            id: spectrum.id(),
+            time: spectrum.start_time(),
+            precursor_mz: spectrum
+                .as_ref()
+                .precursor()
+                .and_then(|p| p.ions.first())
+                .map(|i| i.mz),
+            intensities,
+        }
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "DiagnosticIonScan")]
+#[derive(Debug, Clone)]
+pub struct DiagnosticIonScan {
+    pub index: usize,
+    pub id: String,
+    pub time: f64,
+    #[wasm_bindgen(js_name = "precursorMz")]
+    pub precursor_mz: Option<f64>,
+    /// The matched intensity of each panel ion, in panel order
+    pub intensities: Vec<f32>,
+}
+
+#[wasm_bindgen(js_class = "DiagnosticIonScan")]
+impl DiagnosticIonScan {
+    #[wasm_bindgen(getter, js_name = "totalIntensity")]
+    pub fn total_intensity(&self) -> f32 {
+        self.intensities.iter().sum()
+    }
+
+    #[wasm_bindgen(getter, js_name = "matchedCount")]
+    pub fn matched_count(&self) -> usize {
+        self.intensities.iter().filter(|i| **i > 0.0).count()
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "DiagnosticIonReport")]
+#[derive(Debug, Clone, Default)]
+pub struct DiagnosticIonReport {
+    pub names: Vec<String>,
+    pub scans: Vec<DiagnosticIonScan>,
+    /// The summed intensity of each panel ion over all scans
+    #[wasm_bindgen(js_name = "totalIntensities")]
+    pub total_intensities: Vec<f32>,
+    /// The number of scans each panel ion was found in
+    #[wasm_bindgen(js_name = "scanCounts")]
+    pub scan_counts: Vec<usize>,
+    #[wasm_bindgen(js_name = "scannedCount")]
+    pub scanned_count: usize,
+}
+
+impl DiagnosticIonReport {
+    pub fn new(panel: &DiagnosticIonPanel) -> Self {
+        Self {
+            names: panel.names(),
+            total_intensities: vec![0.0; panel.length()],
+            scan_counts: vec![0; panel.length()],
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, scan: DiagnosticIonScan) {
+        self.scanned_count += 1;
+        for (i, intensity) in scan.intensities.iter().enumerate() {
+            self.total_intensities[i] += intensity;
+            if *intensity > 0.0 {
+                self.scan_counts[i] += 1;
+            }
+        }
+        self.scans.push(scan);
+    }
+}
+
+#[wasm_bindgen(js_class = "DiagnosticIonReport")]
+impl DiagnosticIonReport {
+    /// The scans where at least `minMatched` panel ions were found
+    #[wasm_bindgen(js_name = "scansWithHits")]
+    pub fn scans_with_hits(&self, min_matched: Option<usize>) -> Vec<DiagnosticIonScan> {
+        let min_matched = min_matched.unwrap_or(1);
+        self.scans
+            .iter()
+            .filter(|s| s.matched_count() >= min_matched)
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+
+use mzdata::prelude::*;
+
+use crate::acquisition::IndexGroup;
+use crate::binds::compensation_voltage;
+use crate::pipeline::PipelineSpectrum;
+
+/// How close two compensation voltages have to be to count as the same setting
+const VOLTAGE_TOLERANCE: f64 = 0.05;
+
+/// The header fields of a spectrum needed to group it by compensation voltage
+#[derive(Debug, Clone)]
+struct ScanHeader {
+    id: String,
+    ms_level: u8,
+    precursor_id: Option<String>,
+    voltage: Option<usize>,
+}
+
+/// The FAIMS compensation voltage of every spectrum in a run, for restricting
+/// iteration and precursor-product grouping to a single voltage.
+#[derive(Debug, Clone, Default)]
+pub struct CompensationVoltageIndex {
+    voltages: Vec<f64>,
+    scans: Vec<ScanHeader>,
+    /// The precursor-product groups at each voltage, parallel to `voltages`
+    groups: Vec<Vec<IndexGroup>>,
+}
+
+impl CompensationVoltageIndex {
+    /// Build the index from every spectrum in a run, in index order. Only the
+    /// spectrum metadata is used.
+    pub fn from_spectra<I: IntoIterator<Item = PipelineSpectrum>>(spectra: I) -> Self {
+        let mut this = Self::default();
+        for spectrum in spectra {
+            let voltage = compensation_voltage(spectrum.description()).map(|cv| this.intern(cv));
+            this.scans.push(ScanHeader {
+                id: spectrum.id().to_string(),
+                ms_level: spectrum.ms_level(),
+                precursor_id: spectrum.precursor().and_then(|p| p.precursor_id.clone()),
+                voltage,
+            });
+        }
+        this.sort_voltages();
+        this.groups = (0..this.voltages.len())
+            .map(|v| this.build_groups(v))
+            .collect();
+        this
+    }
+
+    fn intern(&mut self, voltage: f64) -> usize {
+        match self.find(voltage) {
+            Some(i) => i,
+            None => {
+                self.voltages.push(voltage);
+                self.voltages.len() - 1
+            }
+        }
+    }
+
+    /// Put the distinct voltages in ascending order, renumbering the scans to match
+    fn sort_voltages(&mut self) {
+        let mut order: Vec<usize> = (0..self.voltages.len()).collect();
+        order.sort_by(|a, b| self.voltages[*a].total_cmp(&self.voltages[*b]));
+        let mut rank = vec![0; order.len()];
+        for (new, old) in order.iter().enumerate() {
+            rank[*old] = new;
+        }
+        self.voltages = order.iter().map(|i| self.voltages[*i]).collect();
+        for scan in self.scans.iter_mut() {
+            scan.voltage = scan.voltage.map(|v| rank[v]);
+        }
+    }
+
+    fn find(&self, voltage: f64) -> Option<usize> {
+        self.voltages
+            .iter()
+            .position(|v| (v - voltage).abs() <= VOLTAGE_TOLERANCE)
+    }
+
+    /// The distinct compensation voltages in the run, in ascending order
+    pub fn voltages(&self) -> &[f64] {
+        &self.voltages
+    }
+
+    /// The compensation voltage the spectrum at `index` was acquired at
+    pub fn voltage_of(&self, index: usize) -> Option<f64> {
+        self.scans
+            .get(index)
+            .and_then(|s| s.voltage)
+            .map(|v| self.voltages[v])
+    }
+
+    /// The indices of the spectra acquired at `voltage`, in acquisition order
+    pub fn indices_at(&self, voltage: f64) -> Vec<usize> {
+        match self.find(voltage) {
+            Some(target) => self.indices_of(target),
+            None => Vec::new(),
+        }
+    }
+
+    fn indices_of(&self, target: usize) -> Vec<usize> {
+        self.scans
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.voltage == Some(target))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Group the spectra acquired at the voltage numbered `target` into precursors and
+    /// their products.
+    ///
+    /// Product spectra are attached to the precursor spectrum they name if it was
+    /// acquired at the same voltage, otherwise to the most recent precursor at that voltage,
+    /// so interleaved voltages do not steal each other's product spectra.
+    fn build_groups(&self, target: usize) -> Vec<IndexGroup> {
+        let mut groups: Vec<IndexGroup> = Vec::new();
+        let mut group_of_id: HashMap<&str, usize> = HashMap::new();
+        for i in self.indices_of(target) {
+            let scan = &self.scans[i];
+            if scan.ms_level == 1 {
+                group_of_id.insert(scan.id.as_str(), groups.len());
+                groups.push(IndexGroup {
+                    precursor: Some(i),
+                    products: Vec::new(),
+                });
+                continue;
+            }
+            let named = scan
+                .precursor_id
+                .as_deref()
+                .and_then(|id| group_of_id.get(id).copied());
+            match named.or_else(|| groups.len().checked_sub(1)) {
+                Some(g) => groups[g].products.push(i),
+                None => groups.push(IndexGroup {
+                    precursor: None,
+                    products: vec![i],
+                }),
+            }
+        }
+        groups
+    }
+
+    /// The precursor-product groups of the spectra acquired at `voltage`, in acquisition order
+    pub fn groups_at(&self, voltage: f64) -> &[IndexGroup] {
+        match self.find(voltage) {
+            Some(target) => &self.groups[target],
+            None => &[],
+        }
+    }
+
+    /// The group at `voltage` containing the spectrum at `index`, or the first one after it
+    pub fn group_at(&self, voltage: f64, index: usize) -> Option<&IndexGroup> {
+        self.groups_at(voltage).iter().find(|g| {
+            g.precursor.is_some_and(|p| p >= index) || g.products.iter().any(|p| *p >= index)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::TestSpectrum;
+
+    fn spectrum(
+        index: usize,
+        ms_level: u8,
+        precursor_id: Option<usize>,
+        voltage: f64,
+    ) -> PipelineSpectrum {
+        let spectrum = TestSpectrum::new(index, ms_level).compensation_voltage(voltage);
+        match precursor_id {
+            Some(i) => spectrum.precursor_of(i),
+            None => spectrum,
+        }
+        .build()
+    }
+
+    fn group(precursor: Option<usize>, products: &[usize]) -> IndexGroup {
+        IndexGroup {
+            precursor,
+            products: products.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_interleaved_voltages() {
+        let index = CompensationVoltageIndex::from_spectra([
+            spectrum(0, 1, None, -40.0),
+            spectrum(1, 1, None, -60.0),
+            spectrum(2, 2, Some(0), -40.0),
+            // Without a precursor ID, the most recent MS1 at the same voltage is the parent
+            spectrum(3, 2, None, -60.0),
+            spectrum(4, 2, None, -40.0),
+            spectrum(5, 1, None, -40.0),
+            spectrum(6, 2, Some(1), -60.0),
+            // An MSn spectrum before any MS1 at its voltage
+            spectrum(7, 2, None, -80.0),
+        ]);
+        assert_eq!(index.voltages(), [-80.0, -60.0, -40.0]);
+        assert_eq!(index.voltage_of(3), Some(-60.0));
+        assert_eq!(index.indices_at(-40.01), [0, 2, 4, 5]);
+
+        assert_eq!(
+            index.groups_at(-40.0),
+            [group(Some(0), &[2, 4]), group(Some(5), &[])]
+        );
+        assert_eq!(index.groups_at(-60.0), [group(Some(1), &[3, 6])]);
+        assert_eq!(index.groups_at(-80.0), [group(None, &[7])]);
+        assert!(index.groups_at(-50.0).is_empty());
+
+        assert_eq!(index.group_at(-40.0, 3), Some(&group(Some(0), &[2, 4])));
+        assert_eq!(index.group_at(-40.0, 5), Some(&group(Some(5), &[])));
+        assert_eq!(index.group_at(-40.0, 6), None);
+    }
+}
@@ -0,0 +1,303 @@
+use std::io;
+
+use mzdata::io::mgf::MGFReaderType;
+use mzdata::prelude::*;
+use mzdeisotope::DeconvolvedSolutionPeak;
+use mzpeaks::{CentroidPeak, Tolerance};
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{WebSpectrum, WebTolerance};
+use crate::similarity::{score_prepared, PreparedPeaks, WebSimilarityMethod, WebSimilarityParams};
+
+#[derive(Debug, Clone, Default)]
+struct LibraryEntry {
+    name: String,
+    precursor_mz: f64,
+    charge: Option<i32>,
+    peaks: Vec<(f64, f32)>,
+    params: Vec<(String, String)>,
+}
+
+/// Parse the text of an NIST-style MSP file into library entries
+fn parse_msp(text: &str) -> Result<Vec<LibraryEntry>, String> {
+    let mut entries = Vec::new();
+    let mut current: Option<LibraryEntry> = None;
+    let mut peaks_remaining = 0usize;
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            if let Some(entry) = current.take() {
+                entries.push(entry);
+            }
+            peaks_remaining = 0;
+            continue;
+        }
+
+        if peaks_remaining > 0 {
+            let entry = current.as_mut().unwrap();
+            // Some libraries put several peaks on one line separated by `;`
+            for pair in line.split(';').filter(|p| !p.trim().is_empty()) {
+                let mut tokens = pair.split_whitespace();
+                let mz = tokens.next().and_then(|t| t.parse::<f64>().ok());
+                let intensity = tokens.next().and_then(|t| t.parse::<f32>().ok());
+                match (mz, intensity) {
+                    (Some(mz), Some(intensity)) => {
+                        entry.peaks.push((mz, intensity));
+                        peaks_remaining = peaks_remaining.saturating_sub(1);
+                    }
+                    _ => return Err(format!("Malformed peak on line {}: {line}", line_no + 1)),
+                }
+            }
+            continue;
+        }
+
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(format!("Malformed header on line {}: {line}", line_no + 1)),
+        };
+        let key = key.to_ascii_lowercase();
+        // A `Name:` starts the next entry even without a blank line before it
+        if key == "name" && current.as_ref().is_some_and(|e| !e.name.is_empty()) {
+            entries.extend(current.take());
+        }
+        let entry = current.get_or_insert_with(LibraryEntry::default);
+        match key.as_str() {
+            "name" => entry.name = value.to_string(),
+            "precursormz" | "precursor_mz" | "pepmass" => {
+                entry.precursor_mz = value
+                    .split_whitespace()
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_default();
+            }
+            "charge" => {
+                let value = value.trim_end_matches('+');
+                entry.charge = if let Some(value) = value.strip_suffix('-') {
+                    value.parse::<i32>().ok().map(|z| -z)
+                } else {
+                    value.parse().ok()
+                };
+            }
+            "num peaks" | "num_peaks" => {
+                peaks_remaining = value
+                    .parse()
+                    .map_err(|e| format!("Invalid peak count on line {}: {e}", line_no + 1))?;
+            }
+            _ => entry.params.push((key.to_string(), value.to_string())),
+        }
+    }
+    if let Some(entry) = current.take() {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn read_mgf(buffer: &[u8]) -> Vec<LibraryEntry> {
+    let reader: MGFReaderType<_, CentroidPeak, DeconvolvedSolutionPeak> =
+        MGFReaderType::new(io::Cursor::new(buffer));
+    reader
+        .map(|spectrum| {
+            let ion = spectrum.precursor().and_then(|p| p.ions.first()).cloned();
+            let params = spectrum
+                .description()
+                .params
+                .iter()
+                .map(|p| (p.name().to_string(), p.value.to_string()))
+                .collect();
+            let name = spectrum.id().to_string();
+            let spectrum = WebSpectrum::from(spectrum);
+            LibraryEntry {
+                name,
+                precursor_mz: ion.as_ref().map(|i| i.mz).unwrap_or_default(),
+                charge: ion.and_then(|i| i.charge),
+                peaks: spectrum
+                    .peak_array()
+                    .into_iter()
+                    .map(|p| (p.mz, p.intensity))
+                    .collect(),
+                params,
+            }
+        })
+        .collect()
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "LibraryHit")]
+#[derive(Debug, Clone)]
+pub struct WebLibraryHit {
+    #[wasm_bindgen(js_name = "entryIndex")]
+    pub entry_index: usize,
+    pub name: String,
+    #[wasm_bindgen(js_name = "precursorMz")]
+    pub precursor_mz: f64,
+    pub charge: Option<i32>,
+    pub score: f64,
+    #[wasm_bindgen(js_name = "matchedCount")]
+    pub matched_count: usize,
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "LibrarySearchResult")]
+#[derive(Debug, Clone)]
+pub struct WebLibrarySearchResult {
+    #[wasm_bindgen(js_name = "spectrumID")]
+    pub spectrum_id: String,
+    #[wasm_bindgen(js_name = "spectrumIndex")]
+    pub spectrum_index: usize,
+    pub hits: Vec<WebLibraryHit>,
+}
+
+/// A spectral library indexed by precursor m/z
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct SpectralLibrary {
+    entries: Vec<LibraryEntry>,
+}
+
+impl SpectralLibrary {
+    fn from_entries(mut entries: Vec<LibraryEntry>) -> Self {
+        entries.sort_by(|a, b| a.precursor_mz.total_cmp(&b.precursor_mz));
+        Self { entries }
+    }
+
+    fn entries_for(&self, precursor_mz: f64, error_tolerance: Tolerance) -> std::ops::Range<usize> {
+        let (low, high) = error_tolerance.bounds(precursor_mz);
+        let start = self.entries.partition_point(|e| e.precursor_mz < low);
+        let end = self.entries.partition_point(|e| e.precursor_mz <= high);
+        start..end
+    }
+
+    /// Score `spectrum` against every entry whose precursor m/z is within `precursor_tolerance`,
+    /// returning the `top_n` best hits.
+    pub fn search_spectrum(
+        &self,
+        spectrum: &WebSpectrum,
+        precursor_tolerance: Tolerance,
+        method: WebSimilarityMethod,
+        params: &WebSimilarityParams,
+        top_n: usize,
+    ) -> Vec<WebLibraryHit> {
+        let ion = match spectrum.as_ref().precursor().and_then(|p| p.ions.first()) {
+            Some(ion) => ion,
+            None => return Vec::new(),
+        };
+        let query = PreparedPeaks::from_spectrum(spectrum, params);
+        let mut hits: Vec<WebLibraryHit> = self
+            .entries_for(ion.mz, precursor_tolerance)
+            .filter_map(|i| {
+                let entry = &self.entries[i];
+                if let (Some(z), Some(ez)) = (ion.charge, entry.charge) {
+                    if z != ez {
+                        return None;
+                    }
+                }
+                let reference = PreparedPeaks::new(
+                    entry.peaks.iter().copied(),
+                    Some(entry.precursor_mz),
+                    params,
+                );
+                let result = score_prepared(&query, &reference, method, params, None);
+                Some(WebLibraryHit {
+                    entry_index: i,
+                    name: entry.name.clone(),
+                    precursor_mz: entry.precursor_mz,
+                    charge: entry.charge,
+                    score: result.score,
+                    matched_count: result.matched_count(),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(top_n);
+        hits
+    }
+}
+
+#[wasm_bindgen]
+impl SpectralLibrary {
+    #[wasm_bindgen(js_name = "fromMSP")]
+    pub fn from_msp(text: &str) -> Result<SpectralLibrary, JsError> {
+        let entries = parse_msp(text).map_err(|e| JsError::new(&e))?;
+        Ok(Self::from_entries(entries))
+    }
+
+    #[wasm_bindgen(js_name = "fromMGF")]
+    pub fn from_mgf(buffer: &[u8]) -> SpectralLibrary {
+        Self::from_entries(read_mgf(buffer))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get the library entry at `index` as a centroided [`WebSpectrum`]
+    pub fn at(&self, index: usize) -> Option<WebSpectrum> {
+        let entry = self.entries.get(index)?;
+        let mut description = mzdata::spectrum::SpectrumDescription::default();
+        description.id = entry.name.clone();
+        description.index = index;
+        description.ms_level = 2;
+        description.signal_continuity = mzdata::spectrum::SignalContinuity::Centroid;
+        let mut precursor = mzdata::spectrum::Precursor::default();
+        let mut ion = mzdata::spectrum::SelectedIon::default();
+        ion.mz = entry.precursor_mz;
+        ion.charge = entry.charge;
+        precursor.ions.push(ion);
+        description.precursor = Some(precursor);
+        for (name, value) in entry.params.iter() {
+            description.add_param(mzdata::params::Param::new_key_value(
+                name.clone(),
+                value.clone(),
+            ));
+        }
+        let peaks: mzpeaks::MZPeakSetType<CentroidPeak> = entry
+            .peaks
+            .iter()
+            .map(|(mz, intensity)| CentroidPeak::new(*mz, *intensity, 0))
+            .collect();
+        Some(WebSpectrum::new_with_peaks(
+            description,
+            mzdata::spectrum::PeakDataLevel::Centroid(peaks),
+        ))
+    }
+
+    /// Search a single spectrum against the library, returning the `topN` best hits
+    pub fn search(
+        &self,
+        spectrum: &WebSpectrum,
+        precursor_tolerance: Option<WebTolerance>,
+        method: Option<WebSimilarityMethod>,
+        params: Option<WebSimilarityParams>,
+        top_n: Option<usize>,
+    ) -> Vec<WebLibraryHit> {
+        let precursor_tolerance = precursor_tolerance
+            .map(Tolerance::from)
+            .unwrap_or(Tolerance::PPM(20.0));
+        self.search_spectrum(
+            spectrum,
+            precursor_tolerance,
+            method.unwrap_or_default(),
+            &params.unwrap_or_default(),
+            top_n.unwrap_or(5),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_msp_without_blank_lines() {
+        let text = "Name: A\nPrecursorMZ: 500.25\nNum Peaks: 2\n100 10\n200 20\n\
+                    Name: B\nCharge: 2+\nNum Peaks: 1\n150.5 5; \n";
+        let entries = parse_msp(text).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "A");
+        assert_eq!(entries[0].precursor_mz, 500.25);
+        assert_eq!(entries[0].peaks, vec![(100.0, 10.0), (200.0, 20.0)]);
+        assert_eq!(entries[1].name, "B");
+        assert_eq!(entries[1].charge, Some(2));
+        assert_eq!(entries[1].peaks, vec![(150.5, 5.0)]);
+    }
+}
@@ -2,27 +2,87 @@ use std::io;
 use std::sync::Arc;
 
 use js_sys::{Array, Object, Reflect};
+use mzdata::meta::ComponentType;
 use mzdata::spectrum::utils::HasIonMobility;
 use mzdeisotope::DeconvolvedSolutionPeak;
 use mzdeisotope_map::solution::DeconvolvedSolutionFeature;
 use mzpeaks::feature::Feature;
 use mzpeaks::{CentroidPeak, IonMobility, MZ};
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-use mzdata::io::{IMMZReaderType, MZReaderType};
+use mzdata::io::mgf::MGFReaderType;
+use mzdata::io::mzml::MzMLReaderType;
+use mzdata::io::{
+    infer_from_stream, IMMZReaderType, MZReaderType, MassSpectrometryFormat, OffsetIndex,
+};
 use mzdata::prelude::*;
 use mzdata::spectrum::{MultiLayerIonMobilityFrame, MultiLayerSpectrum, SignalContinuity};
 
-use crate::binds::{WebIonMobilityFrame, WebSpectrum};
+use crate::acquisition::{AcquisitionTree, IndexGroup, WebAcquisitionTree};
+use crate::binds::{
+    WebDetailLevel, WebIonMobilityFrame, WebMassAnalyzer, WebSpectrum, WebTolerance,
+};
+use crate::cache::PackedBatch;
+use crate::calibration::MassCalibration;
+use crate::consensus::{consensus_spectra, ConsensusMember, WebConsensusParams};
+use crate::dia::{DIAIndex, WebDIAScheme, WebFragmentXICMatrix};
+use crate::diagnostic::{DiagnosticIonPanel, DiagnosticIonReport};
+use crate::faims::CompensationVoltageIndex;
+use crate::library::{SpectralLibrary, WebLibrarySearchResult};
+use crate::network::{MolecularNetwork, WebNetworkParams};
+use crate::pipeline::{
+    PipelineSpectrum, ProcessingPipeline, ProcessingStep, WebProcessingPipeline,
+};
+use crate::qc::{RunQCBuilder, WebRunQCParams, WebRunQCReport};
+use crate::quant::FeatureTable;
+use crate::similarity::{WebSimilarityMethod, WebSimilarityParams};
 
 #[derive(Debug)]
 pub struct SharedBuffer {
     buffer: Arc<Vec<u8>>,
+    fingerprint: u64,
 }
 
 impl SharedBuffer {
     fn new(buffer: Arc<Vec<u8>>) -> Self {
-        Self { buffer }
+        let fingerprint = Self::hash(&buffer);
+        Self {
+            buffer,
+            fingerprint,
+        }
+    }
+
+    fn from_js(handle: js_sys::Uint8Array) -> Self {
+        let n = handle.length() as usize;
+        let mut buf = Vec::with_capacity(n);
+        buf.resize(n, 0);
+        handle.copy_to(&mut buf);
+        Self::new(Arc::new(buf))
+    }
+
+    /// A content fingerprint: an FNV-1a hash of the buffer's length and all of its bytes,
+    /// taken a word at a time, so a saved index is never used for a file that differs from
+    /// the one it was built from anywhere. Computed once when the buffer is created.
+    fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    fn hash(buffer: &[u8]) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut mix = |word: u64| {
+            hash ^= word;
+            hash = hash.wrapping_mul(0x100000001b3);
+        };
+        mix(buffer.len() as u64);
+        let mut words = buffer.chunks_exact(8);
+        for word in &mut words {
+            mix(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        for byte in words.remainder() {
+            mix(*byte as u64);
+        }
+        hash
     }
 }
 
@@ -30,6 +90,7 @@ impl Clone for SharedBuffer {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer.clone(),
+            fingerprint: self.fingerprint,
         }
     }
 }
@@ -42,7 +103,8 @@ impl AsRef<[u8]> for SharedBuffer {
 
 type BufferType = SharedBuffer;
 
-type ReaderType = MZReaderType<io::Cursor<BufferType>, CentroidPeak, DeconvolvedSolutionPeak>;
+pub(crate) type ReaderType =
+    MZReaderType<io::Cursor<BufferType>, CentroidPeak, DeconvolvedSolutionPeak>;
 type IMReaderType = IMMZReaderType<
     io::Cursor<BufferType>,
     Feature<MZ, IonMobility>,
@@ -51,11 +113,51 @@ type IMReaderType = IMMZReaderType<
     DeconvolvedSolutionPeak,
 >;
 
+/// Bumped whenever the layout of [`IndexCache`] changes so stale caches are rejected
+const INDEX_CACHE_VERSION: u32 = 3;
+
+/// A saved spectrum offset index and a summary of the file it was built from
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexCache {
+    version: u32,
+    file_size: usize,
+    fingerprint: u64,
+    format: String,
+    length: usize,
+    index: OffsetIndex,
+    /// The acquisition tree, if it had been built when the index was saved
+    #[serde(default)]
+    tree: Option<AcquisitionTree>,
+}
+
+impl IndexCache {
+    fn matches(&self, buffer: &SharedBuffer, format: MassSpectrometryFormat) -> bool {
+        self.version == INDEX_CACHE_VERSION
+            && self.file_size == buffer.as_ref().len()
+            && self.format == format.to_string()
+            && self.fingerprint == buffer.fingerprint()
+            && self.index.len() == self.length
+    }
+}
+
 #[wasm_bindgen]
 pub struct MemWebMZReader {
     handle: ReaderType,
+    /// The spectrum offset index, shared with clones of this reader. mzdata readers own
+    /// their index, so a clone only copies it into `handle` when it first seeks.
+    index: Arc<OffsetIndex>,
+    index_installed: bool,
+    /// The index of the spectrum `next` reads, so passes over the whole run can put the
+    /// cursor back where they found it
+    position: usize,
     peak_picking: bool,
     buffer_handle: Option<SharedBuffer>,
+    calibration: Option<MassCalibration>,
+    pipeline: Option<ProcessingPipeline>,
+    // Indices derived from a pass over the whole run, shared with clones of this reader
+    compensation_voltages: Option<Arc<CompensationVoltageIndex>>,
+    dia_index: Option<Arc<DIAIndex>>,
+    acquisition_tree: Option<Arc<AcquisitionTree>>,
 }
 
 impl MemWebMZReader {
@@ -70,22 +172,347 @@ impl MemWebMZReader {
     pub fn buffer(&self) -> Option<&SharedBuffer> {
         self.buffer_handle.as_ref()
     }
+
+    /// Wrap a reader that has indexed the file itself
+    fn from_reader(handle: ReaderType, buffer: SharedBuffer) -> Self {
+        let index = Arc::new(handle.get_index().clone());
+        let mut this = Self::from_shared_index(handle, buffer, index);
+        this.index_installed = true;
+        this
+    }
+
+    /// Wrap a reader opened with [`Self::open_unindexed`] over the file `index` was built from
+    fn from_shared_index(
+        handle: ReaderType,
+        buffer: SharedBuffer,
+        index: Arc<OffsetIndex>,
+    ) -> Self {
+        Self {
+            handle,
+            index,
+            index_installed: false,
+            position: 0,
+            peak_picking: false,
+            buffer_handle: Some(buffer),
+            calibration: None,
+            pipeline: None,
+            compensation_voltages: None,
+            dia_index: None,
+            acquisition_tree: None,
+        }
+    }
+
+    /// Detect the format of the file in `buffer`, and whether it is compressed
+    fn detect_format(buffer: &SharedBuffer) -> Result<(MassSpectrometryFormat, bool), String> {
+        let mut stream = io::Cursor::new(buffer.clone());
+        infer_from_stream(&mut stream).map_err(|e| format!("Failed to detect file format: {e}"))
+    }
+
+    /// Open a reader over `buffer`, indexing its spectra and building its acquisition tree
+    fn open_indexing(buffer: SharedBuffer) -> Result<MemWebMZReader, String> {
+        let handle = MZReaderType::open_read_seek(io::Cursor::new(buffer.clone()))
+            .map_err(|e| format!("Failed to open file: {e}"))?;
+        let mut this = Self::from_reader(handle, buffer);
+        this.acquisition_tree_ref();
+        Ok(this)
+    }
+
+    /// Open a reader over `buffer` without indexing it, to be given an already-built offset
+    /// index. Only uncompressed mzML and MGF can be opened this way, anything else is an error
+    /// rather than a silent re-scan of the file.
+    fn open_unindexed(buffer: SharedBuffer) -> Result<ReaderType, String> {
+        let (format, compressed) = Self::detect_format(&buffer)?;
+        let stream = io::Cursor::new(buffer);
+        match (format, compressed) {
+            (MassSpectrometryFormat::MzML, false) => {
+                Ok(ReaderType::MzML(MzMLReaderType::new(stream)))
+            }
+            (MassSpectrometryFormat::MGF, false) => Ok(ReaderType::MGF(MGFReaderType::new(stream))),
+            (format, true) => Err(format!(
+                "Cannot reuse the offset index of a compressed {format} file"
+            )),
+            (format, false) => Err(format!("Cannot reuse the offset index of a {format} file")),
+        }
+    }
+
+    /// Open a second reader over the same bytes sharing this reader's offset index, carrying
+    /// over its settings and any run-wide indices it has built
+    fn fork_reader(&self) -> Result<MemWebMZReader, String> {
+        let buf = self.buffer().ok_or("No shared buffer found")?.clone();
+        let mut handle = Self::open_unindexed(buf.clone())?;
+        handle.set_detail_level(*self.handle.detail_level());
+        let mut this = Self::from_shared_index(handle, buf, self.index.clone());
+        this.peak_picking = self.peak_picking;
+        this.calibration = self.calibration.clone();
+        this.pipeline = self.pipeline.clone();
+        this.compensation_voltages = self.compensation_voltages.clone();
+        this.dia_index = self.dia_index.clone();
+        this.acquisition_tree = self.acquisition_tree.clone();
+        Ok(this)
+    }
+
+    /// The reader, with the shared offset index copied into it if it has not been already
+    fn indexed(&mut self) -> &mut ReaderType {
+        if !self.index_installed {
+            self.handle.set_index(OffsetIndex::clone(&self.index));
+            self.index_installed = true;
+        }
+        &mut self.handle
+    }
+
+    /// Move the cursor to the spectrum at `index`. A reader that has not copied the shared
+    /// offset index yet seeks with a one-entry index instead, so that a clone which only
+    /// reads on from one point never copies it.
+    fn seek_to_index(&mut self, index: usize) -> Result<(), String> {
+        if !self.index_installed {
+            let (id, offset) = self
+                .index
+                .get_index(index)
+                .ok_or_else(|| format!("Spectrum index {index} out of range"))?;
+            let id = id.to_string();
+            let mut entry = OffsetIndex::new(self.index.name.clone());
+            entry.init = true;
+            entry.insert(id.clone(), offset);
+            self.handle.set_index(entry);
+            self.handle.start_from_id(&id).map_err(|e| e.to_string())?;
+        } else {
+            self.handle
+                .start_from_index(index)
+                .map_err(|e| e.to_string())?;
+        }
+        self.position = index;
+        Ok(())
+    }
+
+    /// Move the cursor back to `position`, which may be the end of the run
+    fn restore_position(&mut self, position: usize) -> Result<(), String> {
+        let n = self.length();
+        if position < n {
+            return self.seek_to_index(position);
+        }
+        if n > 0 {
+            // Read past the last spectrum so that `next` finds the run exhausted
+            self.seek_to_index(n - 1)?;
+            let dl = *self.handle.detail_level();
+            self.handle
+                .set_detail_level(mzdata::io::DetailLevel::MetadataOnly);
+            self.handle.next();
+            self.handle.set_detail_level(dl);
+        }
+        self.position = n;
+        Ok(())
+    }
+
+    /// Run `f` with the detail level set to `detail_level` for the call, leaving the
+    /// reader's own detail level as it was. When `detail_level` is not given the reader's
+    /// detail level is used.
+    fn with_detail_level<T>(
+        &mut self,
+        detail_level: Option<WebDetailLevel>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let dl = *self.handle.detail_level();
+        if let Some(detail_level) = detail_level {
+            self.handle.set_detail_level(detail_level.into());
+        }
+        let value = f(self);
+        self.handle.set_detail_level(dl);
+        value
+    }
+
+    /// Run `f` over every spectrum in the run read at `detail_level`, then put the reader's
+    /// detail level and cursor back as they were, so `next` carries on where it left off
+    fn scan_run<T>(
+        &mut self,
+        detail_level: mzdata::io::DetailLevel,
+        f: impl FnOnce(&mut dyn Iterator<Item = PipelineSpectrum>) -> T,
+    ) -> Result<T, String> {
+        let dl = *self.handle.detail_level();
+        let position = self.position;
+        self.handle.set_detail_level(detail_level);
+        let value = if self.length() > 0 {
+            self.indexed()
+                .start_from_index(0)
+                .map(|handle| f(&mut handle.iter()))
+                .map_err(|e| format!("Failed to read the run: {e}"))
+        } else {
+            Ok(f(&mut std::iter::empty()))
+        };
+        self.handle.set_detail_level(dl);
+        self.restore_position(position)?;
+        value
+    }
+
+    /// Run `f` over every spectrum in the run with its signal decoded, for the analyses
+    /// that make a pass over the whole run
+    fn scan_full_run<T>(
+        &mut self,
+        f: impl FnOnce(&mut dyn Iterator<Item = PipelineSpectrum>) -> T,
+    ) -> Result<T, JsError> {
+        self.scan_run(mzdata::io::DetailLevel::Full, f)
+            .map_err(|e| JsError::new(&e))
+    }
+
+    /// Run `f` over the metadata of every spectrum in the run, without decoding any signal
+    fn scan_metadata<T: Default>(
+        &mut self,
+        f: impl FnOnce(&mut dyn Iterator<Item = PipelineSpectrum>) -> T,
+    ) -> T {
+        self.scan_run(mzdata::io::DetailLevel::MetadataOnly, f)
+            .unwrap_or_else(|e| {
+                log::error!("{e}");
+                T::default()
+            })
+    }
+
+    /// The FAIMS compensation voltage of every spectrum, built on first use from a
+    /// metadata-only pass over the run
+    fn compensation_voltage_index(&mut self) -> &CompensationVoltageIndex {
+        if self.compensation_voltages.is_none() {
+            let index = self.scan_metadata(|it| CompensationVoltageIndex::from_spectra(it));
+            self.compensation_voltages = Some(Arc::new(index));
+        }
+        self.compensation_voltages.as_ref().unwrap()
+    }
+
+    /// The precursor-product tree of the run. Readers build it when they open the file,
+    /// right after indexing it, unless it was restored from a saved index.
+    fn acquisition_tree_ref(&mut self) -> &AcquisitionTree {
+        if self.acquisition_tree.is_none() {
+            let tree = self.scan_metadata(|it| AcquisitionTree::from_spectra(it));
+            self.acquisition_tree = Some(Arc::new(tree));
+        }
+        self.acquisition_tree.as_ref().unwrap()
+    }
+
+    /// The isolation window of every spectrum, built on first use from a metadata-only
+    /// pass over the run
+    fn dia_index(&mut self) -> &DIAIndex {
+        if self.dia_index.is_none() {
+            let index = self.scan_metadata(|it| DIAIndex::from_spectra(it));
+            self.dia_index = Some(Arc::new(index));
+        }
+        self.dia_index.as_ref().unwrap()
+    }
+
+    fn group_object(&self, precursor: Option<WebSpectrum>, products: Vec<WebSpectrum>) -> Object {
+        let obj = Object::new();
+        let precursor = precursor.map(JsValue::from).unwrap_or(JsValue::null());
+        Reflect::set(&obj, &JsValue::from_str("precursor"), &precursor).unwrap();
+        let products: Array = products.into_iter().map(JsValue::from).collect();
+        Reflect::set(&obj, &JsValue::from_str("products"), &products).unwrap();
+        obj
+    }
+
+    fn load_index_group(&mut self, group: IndexGroup) -> Object {
+        let precursor = group
+            .precursor
+            .and_then(|i| self.get_spectrum_by_index(i, None));
+        let products = group
+            .products
+            .into_iter()
+            .filter_map(|i| self.get_spectrum_by_index(i, None))
+            .collect();
+        self.group_object(precursor, products)
+    }
 }
 
 #[wasm_bindgen]
 impl MemWebMZReader {
-    pub fn from_buffer(handle: js_sys::Uint8Array) -> Self {
-        let n = handle.length() as usize;
-        let mut buf = Vec::with_capacity(n);
-        buf.resize(n, 0);
-        handle.copy_to(&mut buf);
+    /// Open a buffer, indexing its spectra and building its acquisition tree
+    pub fn from_buffer(handle: js_sys::Uint8Array) -> Result<MemWebMZReader, JsError> {
+        Self::open_indexing(SharedBuffer::from_js(handle)).map_err(|e| JsError::new(&e))
+    }
 
-        let buf = SharedBuffer::new(Arc::new(buf));
-        Self {
-            handle: MZReaderType::open_read_seek(io::Cursor::new(buf.clone())).unwrap(),
-            peak_picking: false,
-            buffer_handle: Some(buf),
+    /// Open a buffer re-using an offset index previously saved with `exportIndex`,
+    /// skipping the scan over the file to build it.
+    ///
+    /// If the saved index was made from a different file, the index is rebuilt as
+    /// in `from_buffer`.
+    pub fn from_buffer_with_index(
+        handle: js_sys::Uint8Array,
+        index: &[u8],
+    ) -> Result<MemWebMZReader, JsError> {
+        let buf = SharedBuffer::from_js(handle);
+        let cache: IndexCache =
+            rmp_serde::from_slice(index).map_err(|e| JsError::new(&e.to_string()))?;
+        let (format, _) = Self::detect_format(&buf).map_err(|e| JsError::new(&e))?;
+        if !cache.matches(&buf, format) {
+            log::warn!("Saved index does not match this file, rebuilding it");
+            return Self::open_indexing(buf).map_err(|e| JsError::new(&e));
+        }
+
+        let handle = Self::open_unindexed(buf.clone()).map_err(|e| JsError::new(&e))?;
+        let mut this = Self::from_shared_index(handle, buf, Arc::new(cache.index));
+        this.acquisition_tree = cache
+            .tree
+            .filter(|tree| tree.len() == cache.length)
+            .map(Arc::new);
+        this.acquisition_tree_ref();
+        Ok(this)
+    }
+
+    /// Save the spectrum offset index as a compact byte blob for `from_buffer_with_index`,
+    /// along with the acquisition tree
+    #[wasm_bindgen(js_name = "exportIndex")]
+    pub fn export_index(&self) -> Result<Vec<u8>, JsError> {
+        let buf = self
+            .buffer()
+            .ok_or_else(|| JsError::new("No shared buffer found"))?;
+        let cache = IndexCache {
+            version: INDEX_CACHE_VERSION,
+            file_size: buf.as_ref().len(),
+            fingerprint: buf.fingerprint(),
+            format: self.handle.as_format().to_string(),
+            length: self.index.len(),
+            index: OffsetIndex::clone(&self.index),
+            tree: self.acquisition_tree.as_deref().cloned(),
+        };
+        rmp_serde::to_vec(&cache).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Open a second reader over the same bytes, sharing this reader's offset index and
+    /// any run-wide indices it has built instead of re-reading the file. The new reader
+    /// has its own cursor and starts with the same detail level, calibration and processing.
+    ///
+    /// Only uncompressed mzML and MGF files can be shared this way, others are an error.
+    #[wasm_bindgen(js_name = "clone")]
+    pub fn fork(&self) -> Result<MemWebMZReader, JsError> {
+        self.fork_reader().map_err(|e| JsError::new(&e))
+    }
+
+    /// A key identifying the contents of the file this reader was opened from
+    #[wasm_bindgen(getter)]
+    pub fn fingerprint(&self) -> Option<String> {
+        self.buffer()
+            .map(|buf| format!("{:x}-{:016x}", buf.as_ref().len(), buf.fingerprint()))
+    }
+
+    /// The size in bytes of the file this reader was opened from
+    #[wasm_bindgen(getter, js_name = "fileSize")]
+    pub fn file_size(&self) -> usize {
+        self.buffer()
+            .map(|buf| buf.as_ref().len())
+            .unwrap_or_default()
+    }
+
+    /// Copy the bytes of the file this reader was opened from into `target`, which must be
+    /// `fileSize` bytes long, so they can be handed to another reader without JS keeping a
+    /// copy of the file for as long as this reader lives
+    #[wasm_bindgen(js_name = "copyFileBytes")]
+    pub fn copy_file_bytes(&self, target: &js_sys::Uint8Array) -> Result<(), JsError> {
+        let buf = self
+            .buffer()
+            .ok_or_else(|| JsError::new("No shared buffer found"))?;
+        if target.length() as usize != buf.as_ref().len() {
+            return Err(JsError::new(&format!(
+                "Expected a target of {} bytes, got {}",
+                buf.as_ref().len(),
+                target.length()
+            )));
         }
+        target.copy_from(buf.as_ref());
+        Ok(())
     }
 
     pub fn set_data_loading(&mut self, load_data: bool) {
@@ -97,6 +524,17 @@ impl MemWebMZReader {
         }
     }
 
+    /// How much of each spectrum is read by calls that are not given their own detail level
+    #[wasm_bindgen(getter, js_name = "detailLevel")]
+    pub fn detail_level(&self) -> WebDetailLevel {
+        (*self.handle.detail_level()).into()
+    }
+
+    #[wasm_bindgen(setter, js_name = "detailLevel")]
+    pub fn set_detail_level(&mut self, detail_level: WebDetailLevel) {
+        self.handle.set_detail_level(detail_level.into());
+    }
+
     pub fn set_peak_picking(&mut self, pick_peaks: bool) {
         self.peak_picking = pick_peaks;
     }
@@ -108,91 +546,635 @@ impl MemWebMZReader {
 
     #[wasm_bindgen(getter)]
     pub fn length(&self) -> usize {
-        self.handle.len()
+        self.index.len()
     }
 
     fn convert_spectrum(
         &self,
         mut spectrum: MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>,
+    ) -> WebSpectrum {
+        if *self.handle.detail_level() == mzdata::io::DetailLevel::Lazy {
+            if !self.peak_picking && self.calibration.is_none() && self.pipeline.is_none() {
+                return WebSpectrum::from_lazy(spectrum);
+            }
+            // Processing needs the decoded signal, so there is nothing to gain from waiting
+            if let Some(arrays) = spectrum.arrays.as_mut() {
+                if let Err(e) = arrays.decode_all_arrays() {
+                    log::error!("Failed to decode spectrum {}: {e}", spectrum.id());
+                }
+            }
+        }
+        let mut spectrum = Self::prepare_spectrum(spectrum, self.peak_picking);
+        if let Some(calibration) = self.calibration.as_ref() {
+            calibration.apply(&mut spectrum);
+        }
+        if let Some(pipeline) = self.pipeline.as_ref() {
+            if let Err(e) = pipeline.process_spectrum(spectrum.as_mut()) {
+                log::error!("Failed to process spectrum {}: {e}", spectrum.as_ref().id());
+            }
+        }
+        spectrum
+    }
+
+    fn prepare_spectrum(
+        mut spectrum: MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>,
+        peak_picking: bool,
     ) -> WebSpectrum {
         // if spectrum.peaks.is_none() && spectrum.deconvoluted_peaks.is_none() {
         //     spectrum.pick_peaks(1.0).unwrap();
         //     spectrum.description_mut().signal_continuity = SignalContinuity::Centroid;
         // }
-        if self.peak_picking && spectrum.signal_continuity() == SignalContinuity::Profile {
+        if peak_picking && spectrum.signal_continuity() == SignalContinuity::Profile {
             spectrum.pick_peaks(1.0).unwrap();
             spectrum.description_mut().signal_continuity = SignalContinuity::Centroid;
         }
         WebSpectrum::from(spectrum)
     }
 
-    pub fn get_spectrum_by_id(&mut self, id: &str) -> Option<WebSpectrum> {
-        let spectrum = self.handle.get_spectrum_by_id(id)?;
-        Some(self.convert_spectrum(spectrum))
+    pub fn get_spectrum_by_id(
+        &mut self,
+        id: &str,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Option<WebSpectrum> {
+        self.with_detail_level(detail_level, |this| {
+            let spectrum = this.indexed().get_spectrum_by_id(id)?;
+            Some(this.convert_spectrum(spectrum))
+        })
     }
 
-    pub fn get_spectrum_by_index(&mut self, index: usize) -> Option<WebSpectrum> {
-        let spectrum = self.handle.get_spectrum_by_index(index)?;
-        Some(self.convert_spectrum(spectrum))
+    pub fn get_spectrum_by_index(
+        &mut self,
+        index: usize,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Option<WebSpectrum> {
+        self.with_detail_level(detail_level, |this| {
+            let spectrum = this.indexed().get_spectrum_by_index(index)?;
+            Some(this.convert_spectrum(spectrum))
+        })
     }
 
-    pub fn get_spectrum_by_time(&mut self, time: f64) -> Option<WebSpectrum> {
-        let spectrum = self.handle.get_spectrum_by_time(time)?;
-        Some(self.convert_spectrum(spectrum))
+    pub fn get_spectrum_by_time(
+        &mut self,
+        time: f64,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Option<WebSpectrum> {
+        self.with_detail_level(detail_level, |this| {
+            let spectrum = this.indexed().get_spectrum_by_time(time)?;
+            Some(this.convert_spectrum(spectrum))
+        })
     }
 
     pub fn next(&mut self) -> Option<WebSpectrum> {
-        self.handle.next().map(|s| self.convert_spectrum(s))
+        match self.handle.next() {
+            Some(spectrum) => {
+                self.position = spectrum.index() + 1;
+                Some(self.convert_spectrum(spectrum))
+            }
+            None => {
+                self.position = self.length();
+                None
+            }
+        }
+    }
+
+    /// Read the spectra at each of `indices` in one call, skipping indices past the end
+    /// of the run. If `detailLevel` is given it overrides the reader's detail level for
+    /// this call only.
+    #[wasm_bindgen(js_name = "getSpectraByIndices")]
+    pub fn get_spectra_by_indices(
+        &mut self,
+        indices: Vec<usize>,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Vec<WebSpectrum> {
+        self.with_detail_level(detail_level, |this| {
+            indices
+                .into_iter()
+                .filter_map(|i| this.get_spectrum_by_index(i, None))
+                .collect()
+        })
+    }
+
+    /// Read the spectra with indices in `start..end` in one call, like `getSpectraByIndices`
+    #[wasm_bindgen(js_name = "getRange")]
+    pub fn get_range(
+        &mut self,
+        start: usize,
+        end: usize,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Vec<WebSpectrum> {
+        let end = end.min(self.length());
+        if start >= end {
+            return Vec::new();
+        }
+        self.with_detail_level(detail_level, |this| {
+            this.indexed().start_from_index(start).unwrap();
+            let mut spectra = Vec::with_capacity(end - start);
+            while spectra.len() < end - start {
+                match this.next() {
+                    Some(spectrum) => spectra.push(spectrum),
+                    None => break,
+                }
+            }
+            spectra
+        })
+    }
+
+    /// An iterator over the spectra with indices in `start..end`, defaulting to the whole
+    /// run, with its own cursor so it does not disturb this reader's position or settings
+    pub fn iter(
+        &self,
+        start: Option<usize>,
+        end: Option<usize>,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Result<WebSpectrumIterator, JsError> {
+        let start = start.unwrap_or(0);
+        let end = end.unwrap_or(usize::MAX).min(self.length());
+        let mut reader = self.fork_reader().map_err(|e| JsError::new(&e))?;
+        if let Some(detail_level) = detail_level {
+            reader.set_detail_level(detail_level);
+        }
+        if start < end {
+            reader.seek_to_index(start).map_err(|e| JsError::new(&e))?;
+        }
+        Ok(WebSpectrumIterator {
+            reader,
+            position: start,
+            end,
+        })
     }
 
     pub fn start_from_index(&mut self, index: usize) {
-        self.handle.start_from_index(index).unwrap();
+        self.indexed().start_from_index(index).unwrap();
+        self.position = index;
     }
 
     pub fn start_from_time(&mut self, time: f64) {
-        self.handle.start_from_time(time).unwrap();
+        let index = self
+            .get_spectrum_by_time(time, Some(WebDetailLevel::MetadataOnly))
+            .map(|s| s.index())
+            .unwrap();
+        self.start_from_index(index);
     }
 
-    pub fn group_at(&mut self, index: usize) -> Option<Object> {
-        let mut it = self.handle.iter();
-        let mut it = it.groups();
-        it.start_from_index(index).unwrap();
-        let group = it.next();
-        drop(it);
+    /// The acquisition group the spectrum at `index` belongs to, its MS1 precursor and
+    /// every MSn spectrum descended from it, looked up in the run's acquisition tree
+    pub fn group_at(
+        &mut self,
+        index: usize,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Option<Object> {
+        let group = self.acquisition_tree_ref().group_of(index)?;
+        Some(self.with_detail_level(detail_level, |this| this.load_index_group(group)))
+    }
 
-        let obj = Object::new();
-        let group = match group {
-            Some(group) => group,
-            None => return None,
-        };
+    /// The precursor-product tree of every spectrum in the run
+    #[wasm_bindgen(js_name = "acquisitionTree")]
+    pub fn acquisition_tree(&mut self) -> WebAcquisitionTree {
+        AcquisitionTree::clone(self.acquisition_tree_ref()).into()
+    }
 
-        let (prec, products) = group.into_parts();
-        if let Some(prec) = prec {
-            let spec = self.convert_spectrum(prec);
-            let val = JsValue::from(spec);
-            Reflect::set(&obj, &JsValue::from_str(&"precursor"), &val).unwrap();
-        } else {
-            Reflect::set(&obj, &JsValue::from_str(&"precursor"), &JsValue::null()).unwrap();
+    /// The distinct FAIMS compensation voltages in the run, in ascending order. Empty if
+    /// the run was not acquired with FAIMS.
+    #[wasm_bindgen(js_name = "compensationVoltages")]
+    pub fn compensation_voltages(&mut self) -> Vec<f64> {
+        self.compensation_voltage_index().voltages().to_vec()
+    }
+
+    /// The FAIMS compensation voltage the spectrum at `index` was acquired at
+    #[wasm_bindgen(js_name = "compensationVoltageAt")]
+    pub fn compensation_voltage_at(&mut self, index: usize) -> Option<f64> {
+        self.compensation_voltage_index().voltage_of(index)
+    }
+
+    /// The indices of the spectra acquired at the compensation voltage `voltage`
+    #[wasm_bindgen(js_name = "indicesAtCompensationVoltage")]
+    pub fn indices_at_compensation_voltage(&mut self, voltage: f64) -> Vec<usize> {
+        self.compensation_voltage_index().indices_at(voltage)
+    }
+
+    /// Like `group_at`, but only considering spectra acquired at the compensation voltage
+    /// `voltage`, returning the group containing the spectrum at `index` or the next one after it.
+    /// If `detailLevel` is given it overrides the reader's detail level for this call only.
+    #[wasm_bindgen(js_name = "groupAtCompensationVoltage")]
+    pub fn group_at_compensation_voltage(
+        &mut self,
+        voltage: f64,
+        index: usize,
+        detail_level: Option<WebDetailLevel>,
+    ) -> Option<Object> {
+        let group = self
+            .compensation_voltage_index()
+            .group_at(voltage, index)?
+            .clone();
+        Some(self.with_detail_level(detail_level, |this| this.load_index_group(group)))
+    }
+
+    /// The spectrum indices of every precursor-product group acquired at the compensation
+    /// voltage `voltage`, as `{precursor, products}` objects.
+    #[wasm_bindgen(js_name = "groupIndicesAtCompensationVoltage")]
+    pub fn group_indices_at_compensation_voltage(&mut self, voltage: f64) -> Array {
+        self.compensation_voltage_index()
+            .groups_at(voltage)
+            .iter()
+            .map(|group| {
+                let obj = Object::new();
+                let precursor = group
+                    .precursor
+                    .map(|i| JsValue::from(i as u32))
+                    .unwrap_or(JsValue::null());
+                Reflect::set(&obj, &JsValue::from_str("precursor"), &precursor).unwrap();
+                let products: Array = group
+                    .products
+                    .iter()
+                    .map(|i| JsValue::from(*i as u32))
+                    .collect();
+                Reflect::set(&obj, &JsValue::from_str("products"), &products).unwrap();
+                JsValue::from(obj)
+            })
+            .collect()
+    }
+
+    /// Scan every MSn spectrum's header and link it to the feature in `table` it
+    /// was most likely triggered from, returning the number of spectra linked. Any
+    /// links already in `table` are replaced.
+    #[wasm_bindgen(js_name = "linkFeatureTable")]
+    pub fn link_feature_table(
+        &mut self,
+        table: &mut FeatureTable,
+        error_tolerance: &WebTolerance,
+        time_padding: Option<f64>,
+    ) -> usize {
+        let error_tolerance: Tolerance = (*error_tolerance).into();
+        let time_padding = time_padding.unwrap_or(0.1);
+        table.clear_links();
+        self.scan_metadata(|it| {
+            it.filter(|s| s.ms_level() > 1)
+                .filter_map(|s| table.link_spectrum(&s, error_tolerance, time_padding))
+                .count()
+        })
+    }
+
+    /// Search every MSn spectrum for the ions in `panel`, deconvolving spectra with the
+    /// glycopeptide isotopic model when they do not already have deconvoluted peaks.
+    #[wasm_bindgen(js_name = "scanDiagnosticIons")]
+    pub fn scan_diagnostic_ions(
+        &mut self,
+        panel: &DiagnosticIonPanel,
+        error_tolerance: Option<WebTolerance>,
+        score_threshold: Option<f32>,
+    ) -> Result<DiagnosticIonReport, JsError> {
+        let error_tolerance = error_tolerance
+            .map(Tolerance::from)
+            .unwrap_or(Tolerance::PPM(10.0));
+        let score_threshold = score_threshold.unwrap_or(5.0);
+        let mut report = DiagnosticIonReport::new(panel);
+        self.scan_full_run(|spectra| {
+            for (i, spectrum) in spectra.enumerate() {
+                if spectrum.ms_level() < 2 {
+                    continue;
+                }
+                if i % 1000 == 0 {
+                    log::info!("Scanning {} for diagnostic ions", spectrum.id());
+                }
+                report.push(panel.scan(spectrum, error_tolerance, score_threshold));
+            }
+        })?;
+        Ok(report)
+    }
+
+    /// Search every MSn spectrum against `library`, returning the `topN` best hits for each
+    /// spectrum with at least one hit scoring `minScore` or better.
+    #[wasm_bindgen(js_name = "searchLibrary")]
+    pub fn search_library(
+        &mut self,
+        library: &SpectralLibrary,
+        precursor_tolerance: Option<WebTolerance>,
+        method: Option<WebSimilarityMethod>,
+        params: Option<WebSimilarityParams>,
+        top_n: Option<usize>,
+        min_score: Option<f64>,
+    ) -> Result<Vec<WebLibrarySearchResult>, JsError> {
+        let precursor_tolerance = precursor_tolerance
+            .map(Tolerance::from)
+            .unwrap_or(Tolerance::PPM(20.0));
+        let method = method.unwrap_or_default();
+        let params = params.unwrap_or_default();
+        let top_n = top_n.unwrap_or(5);
+        let min_score = min_score.unwrap_or(0.0);
+        let peak_picking = self.peak_picking;
+
+        self.scan_full_run(|spectra| {
+            spectra
+                .filter(|s| s.ms_level() > 1)
+                .filter_map(|s| {
+                    let spectrum = Self::prepare_spectrum(s, peak_picking);
+                    let hits: Vec<_> = library
+                        .search_spectrum(&spectrum, precursor_tolerance, method, &params, top_n)
+                        .into_iter()
+                        .filter(|h| h.score >= min_score)
+                        .collect();
+                    if hits.is_empty() {
+                        None
+                    } else {
+                        Some(WebLibrarySearchResult {
+                            spectrum_id: spectrum.id(),
+                            spectrum_index: spectrum.index(),
+                            hits,
+                        })
+                    }
+                })
+                .collect()
+        })
+    }
+
+    /// Build a molecular network over every MSn spectrum in the run
+    #[wasm_bindgen(js_name = "buildMolecularNetwork")]
+    pub fn build_molecular_network(
+        &mut self,
+        similarity_params: Option<WebSimilarityParams>,
+        network_params: Option<WebNetworkParams>,
+    ) -> Result<MolecularNetwork, JsError> {
+        let similarity_params = similarity_params.unwrap_or_default();
+        let network_params = network_params.unwrap_or_default();
+        let peak_picking = self.peak_picking;
+        let mut network = MolecularNetwork::default();
+
+        self.scan_full_run(|spectra| {
+            for spectrum in spectra.filter(|s| s.ms_level() > 1) {
+                let spectrum = Self::prepare_spectrum(spectrum, peak_picking);
+                network.push(&spectrum, &similarity_params);
+            }
+        })?;
+
+        network.build_edges(&similarity_params, &network_params);
+        Ok(network)
+    }
+
+    /// Merge repeated MSn spectra of the same precursor across the run into consensus spectra.
+    ///
+    /// The result can be written out with `write_spectra_to_mgf`.
+    #[wasm_bindgen(js_name = "buildConsensusSpectra")]
+    pub fn build_consensus_spectra(
+        &mut self,
+        params: Option<WebConsensusParams>,
+    ) -> Result<Vec<WebSpectrum>, JsError> {
+        let params = params.unwrap_or_default();
+        let peak_picking = self.peak_picking;
+        let mut members = Vec::new();
+
+        self.scan_full_run(|spectra| {
+            for spectrum in spectra.filter(|s| s.ms_level() > 1) {
+                let spectrum = Self::prepare_spectrum(spectrum, peak_picking);
+                members.extend(ConsensusMember::from_spectrum(&spectrum));
+            }
+        })?;
+
+        Ok(consensus_spectra(members, &params))
+    }
+
+    /// Compute run-level quality control metrics in a single pass over the run, or
+    /// over only the spectra acquired at the FAIMS compensation voltage `compensationVoltage`
+    #[wasm_bindgen(js_name = "runQC")]
+    pub fn run_qc(
+        &mut self,
+        params: Option<WebRunQCParams>,
+        compensation_voltage: Option<f64>,
+    ) -> Result<WebRunQCReport, JsError> {
+        let mut builder = RunQCBuilder::new(params.unwrap_or_default());
+        let indices =
+            compensation_voltage.map(|cv| self.compensation_voltage_index().indices_at(cv));
+
+        match indices {
+            Some(indices) => self.with_detail_level(Some(WebDetailLevel::Full), |this| {
+                for index in indices {
+                    if let Some(spectrum) = this.indexed().get_spectrum_by_index(index) {
+                        builder.push(spectrum);
+                    }
+                }
+            }),
+            None => self.scan_full_run(|spectra| {
+                for spectrum in spectra {
+                    builder.push(spectrum);
+                }
+            })?,
         }
 
-        let products: Array = products
-            .into_iter()
-            .map(|spec| JsValue::from(self.convert_spectrum(spec)))
-            .collect();
-        Reflect::set(&obj, &JsValue::from_str(&"products"), &products).unwrap();
-        Some(obj)
+        Ok(builder.finish())
+    }
+
+    /// Infer the data-independent acquisition window scheme of the run: its windows,
+    /// cycle time, window overlap and whether it is staggered
+    #[wasm_bindgen(js_name = "diaScheme")]
+    pub fn dia_scheme(&mut self) -> WebDIAScheme {
+        self.dia_index().scheme()
+    }
+
+    /// Extract the chromatograms of fragment ions from the spectra acquired with the
+    /// DIA window at `windowIndex` in the scheme between `startTime` and `endTime`.
+    ///
+    /// When `fragmentMzs` is not given, the `topN` (default 10) most intense peaks of
+    /// the most intense spectrum in the range are used.
+    #[wasm_bindgen(js_name = "diaFragmentXICs")]
+    pub fn dia_fragment_xics(
+        &mut self,
+        window_index: usize,
+        start_time: f64,
+        end_time: f64,
+        fragment_mzs: Option<Vec<f64>>,
+        error_tolerance: Option<WebTolerance>,
+        top_n: Option<usize>,
+    ) -> Result<WebFragmentXICMatrix, JsError> {
+        let error_tolerance = error_tolerance
+            .map(Tolerance::from)
+            .unwrap_or(Tolerance::PPM(20.0));
+        let window = self
+            .dia_index()
+            .scheme()
+            .windows
+            .get(window_index)
+            .copied()
+            .ok_or_else(|| JsError::new(&format!("No DIA window at index {window_index}")))?;
+        let indices = self
+            .dia_index()
+            .indices_in(window_index, start_time, end_time);
+
+        let spectra: Vec<WebSpectrum> =
+            self.with_detail_level(Some(WebDetailLevel::Full), |this| {
+                indices
+                    .into_iter()
+                    .filter_map(|i| this.indexed().get_spectrum_by_index(i))
+                    .map(|s| Self::prepare_spectrum(s, true))
+                    .collect()
+            });
+
+        Ok(WebFragmentXICMatrix::from_spectra(
+            window,
+            &spectra,
+            fragment_mzs.unwrap_or_default(),
+            error_tolerance,
+            top_n.unwrap_or(10),
+        ))
+    }
+
+    /// Fit the calibration to the calibrant observations in every spectrum at its MS level,
+    /// discarding any gathered before, and return the number of calibration curves.
+    #[wasm_bindgen(js_name = "fitMassCalibration")]
+    pub fn fit_mass_calibration(
+        &mut self,
+        calibration: &mut MassCalibration,
+    ) -> Result<usize, JsError> {
+        let ms_level = calibration.ms_level();
+        calibration.clear();
+        self.scan_full_run(|spectra| {
+            for spectrum in spectra.filter(|s| s.ms_level() == ms_level) {
+                let spectrum = Self::prepare_spectrum(spectrum, true);
+                calibration.observe(&spectrum);
+            }
+        })?;
+
+        Ok(calibration.fit())
+    }
+
+    /// Apply `calibration` to every spectrum read from now on, and record it in the
+    /// file's data processing metadata in place of any calibration set before it.
+    #[wasm_bindgen(js_name = "setMassCalibration")]
+    pub fn set_mass_calibration(&mut self, calibration: &MassCalibration) {
+        calibration.record_processing(&mut self.handle);
+        self.calibration = Some(calibration.clone());
+    }
+
+    /// Stop applying the mass calibration and remove it from the data processing metadata
+    #[wasm_bindgen(js_name = "clearMassCalibration")]
+    pub fn clear_mass_calibration(&mut self) {
+        MassCalibration::remove_processing(&mut self.handle);
+        self.calibration = None;
+    }
+
+    /// Run `pipeline` on every spectrum read from now on, after any mass calibration.
+    #[wasm_bindgen(js_name = "setProcessingPipeline")]
+    pub fn set_processing_pipeline(&mut self, pipeline: &WebProcessingPipeline) {
+        self.pipeline = Some(pipeline.as_inner().clone());
+    }
+
+    #[wasm_bindgen(js_name = "clearProcessingPipeline")]
+    pub fn clear_processing_pipeline(&mut self) {
+        self.pipeline = None;
+    }
+
+    /// The mass analyzer `spectrum` was acquired with, from the instrument configuration
+    /// its scan refers to, falling back to guessing from the scan's filter string.
+    #[wasm_bindgen(js_name = "analyzerFor")]
+    pub fn analyzer_for(&self, spectrum: &WebSpectrum) -> WebMassAnalyzer {
+        spectrum
+            .description()
+            .acquisition
+            .first_scan()
+            .and_then(|scan| {
+                self.handle
+                    .instrument_configurations()
+                    .get(&scan.instrument_configuration_id)
+            })
+            .and_then(|config| {
+                config
+                    .components
+                    .iter()
+                    .filter(|c| c.component_type == ComponentType::Analyzer)
+                    .flat_map(|c| c.params().iter())
+                    .filter_map(|p| p.curie())
+                    .map(|curie| WebMassAnalyzer::from_term(&curie.to_string()))
+                    .find(|kind| *kind != WebMassAnalyzer::Unknown)
+            })
+            .unwrap_or_else(|| spectrum.analyzer())
+    }
+
+    /// Process the spectra with indices in `start..end` with the serialized pipeline
+    /// `options`, which defaults to picking peaks, passing them to
+    /// `callback` as packed batches of up to `chunkSize` spectra along with the index
+    /// of the first spectrum in the batch. Returns the number of spectra processed.
+    ///
+    /// This is the unit of work a processing worker runs on its own reader, so the
+    /// reader's peak picking, mass calibration and pipeline are not applied, only `options`.
+    /// Otherwise the same range would come back processed differently depending on
+    /// whether it ran in a worker or on the calling thread.
+    #[wasm_bindgen(js_name = "processRange")]
+    pub fn process_range(
+        &mut self,
+        start: usize,
+        end: usize,
+        options: JsValue,
+        chunk_size: Option<usize>,
+        callback: &js_sys::Function,
+    ) -> Result<usize, JsError> {
+        let options: ProcessingPipeline = if options.is_undefined() || options.is_null() {
+            ProcessingPipeline::new(vec![ProcessingStep::PickPeaks {
+                signal_to_noise: 1.0,
+            }])
+        } else {
+            serde_wasm_bindgen::from_value(options).map_err(|e| JsError::new(&e.to_string()))?
+        };
+        let chunk_size = chunk_size.unwrap_or(50).max(1);
+        let end = end.min(self.length());
+
+        let emit = |batch: &PackedBatch, first: usize| -> Result<(), JsError> {
+            let bytes = js_sys::Uint8Array::from(batch.pack()?.as_slice());
+            callback
+                .call2(&JsValue::NULL, &bytes, &JsValue::from(first as u32))
+                .map_err(|e| JsError::new(&format!("{e:?}")))?;
+            Ok(())
+        };
+
+        let dl = *self.handle.detail_level();
+        self.handle.set_detail_level(mzdata::io::DetailLevel::Full);
+        let mut batch = PackedBatch::default();
+        // The index of the first spectrum in `batch`, which need not be `start` or follow on
+        // from the last batch when spectra are missing
+        let mut first = None;
+        let mut processed = 0;
+        let mut status = Ok(());
+        for index in start..end {
+            let spectrum = match self.indexed().get_spectrum_by_index(index) {
+                Some(spectrum) => spectrum,
+                None => continue,
+            };
+            let mut spectrum = WebSpectrum::from(spectrum);
+            status = options
+                .process_spectrum(spectrum.as_mut())
+                .map_err(|e| JsError::new(&e));
+            if status.is_err() {
+                break;
+            }
+            batch.push_spectrum(spectrum);
+            let batch_first = *first.get_or_insert(index);
+            processed += 1;
+            if batch.length() >= chunk_size {
+                status = emit(&batch, batch_first);
+                if status.is_err() {
+                    break;
+                }
+                batch = PackedBatch::default();
+                first = None;
+            }
+        }
+        self.handle.set_detail_level(dl);
+        status?;
+        if let Some(first) = first {
+            emit(&batch, first)?;
+        }
+        Ok(processed)
     }
 
     pub fn to_frame_reader(&mut self) -> Result<MemWebIMMZReader, String> {
         if let Some(im) = self.handle.has_ion_mobility() {
             if matches!(im, HasIonMobility::Dimension) {
                 let buffer = self.buffer().ok_or("No shared buffer found")?.clone();
-                let reader = ReaderType::open_read_seek(io::Cursor::new(buffer))
+                let mut reader = Self::open_unindexed(buffer)
                     .map_err(|e| format!("Failed to reuse shared buffer: {e}"))?;
+                reader.set_index(OffsetIndex::clone(&self.index));
                 let handle = reader.into_frame_source();
                 let this = MemWebIMMZReader {
                     handle,
                     feature_extraction: self.peak_picking,
+                    pipeline: self.pipeline.clone(),
                 };
                 return Ok(this);
             } else {
@@ -203,10 +1185,64 @@ impl MemWebMZReader {
     }
 }
 
+/// An iterator over a range of spectra with its own cursor into the file, following
+/// the JS iterator protocol
+#[wasm_bindgen(js_name = "SpectrumIterator")]
+pub struct WebSpectrumIterator {
+    reader: MemWebMZReader,
+    position: usize,
+    end: usize,
+}
+
+#[wasm_bindgen(js_class = "SpectrumIterator")]
+impl WebSpectrumIterator {
+    /// The next spectrum as a `{done, value}` iterator result
+    pub fn next(&mut self) -> Object {
+        let value = if self.position < self.end {
+            self.position += 1;
+            self.reader.next()
+        } else {
+            None
+        };
+        if value.is_none() {
+            self.position = self.end;
+        }
+        let obj = Object::new();
+        Reflect::set(&obj, &JsValue::from_str("done"), &value.is_none().into()).unwrap();
+        let value = value.map(JsValue::from).unwrap_or(JsValue::undefined());
+        Reflect::set(&obj, &JsValue::from_str("value"), &value).unwrap();
+        obj
+    }
+
+    /// Read up to `size` spectra at once, an empty batch meaning the iterator is exhausted
+    #[wasm_bindgen(js_name = "nextBatch")]
+    pub fn next_batch(&mut self, size: usize) -> Vec<WebSpectrum> {
+        let mut batch = Vec::with_capacity(size.min(self.remaining()));
+        while batch.len() < size && self.position < self.end {
+            self.position += 1;
+            match self.reader.next() {
+                Some(spectrum) => batch.push(spectrum),
+                None => {
+                    self.position = self.end;
+                    break;
+                }
+            }
+        }
+        batch
+    }
+
+    /// The number of spectra left in the iterator's range
+    #[wasm_bindgen(getter)]
+    pub fn remaining(&self) -> usize {
+        self.end.saturating_sub(self.position)
+    }
+}
+
 #[wasm_bindgen]
 pub struct MemWebIMMZReader {
     handle: IMReaderType,
     feature_extraction: bool,
+    pipeline: Option<ProcessingPipeline>,
 }
 
 impl MemWebIMMZReader {
@@ -234,6 +1270,7 @@ impl MemWebIMMZReader {
         Self {
             handle: handle,
             feature_extraction: false,
+            pipeline: None,
         }
     }
 
@@ -250,6 +1287,17 @@ impl MemWebIMMZReader {
         self.feature_extraction = feature_extraction;
     }
 
+    /// Run `pipeline` on every frame read from now on, after any feature extraction.
+    #[wasm_bindgen(js_name = "setProcessingPipeline")]
+    pub fn set_processing_pipeline(&mut self, pipeline: &WebProcessingPipeline) {
+        self.pipeline = Some(pipeline.as_inner().clone());
+    }
+
+    #[wasm_bindgen(js_name = "clearProcessingPipeline")]
+    pub fn clear_processing_pipeline(&mut self) {
+        self.pipeline = None;
+    }
+
     #[wasm_bindgen(getter)]
     pub fn length(&self) -> usize {
         self.handle.len()
@@ -268,6 +1316,11 @@ impl MemWebIMMZReader {
                 .unwrap();
             frame.description_mut().signal_continuity = SignalContinuity::Centroid;
         }
+        if let Some(pipeline) = self.pipeline.as_ref() {
+            if let Err(e) = pipeline.process_frame(&mut frame) {
+                log::error!("Failed to process frame {}: {e}", frame.id());
+            }
+        }
         WebIonMobilityFrame::from(frame)
     }
 
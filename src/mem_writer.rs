@@ -1,6 +1,8 @@
 use std::io;
 
 use mzdata::meta::MSDataFileMetadata;
+use mzdata::params::{Param, ParamLike, Value as ParamValue};
+use serde_json::{json, Value};
 use wasm_bindgen::prelude::*;
 
 use mzdata::prelude::*;
@@ -9,6 +11,7 @@ use mzdata::io::mzml::MzMLWriterType;
 
 
 use crate::WebSpectrum;
+use crate::qc::WebRunQCReport;
 
 #[wasm_bindgen]
 pub fn write_spectra_to_mgf(spectra: Vec<WebSpectrum>) -> String {
@@ -22,7 +25,7 @@ pub fn write_spectra_to_mgf(spectra: Vec<WebSpectrum>) -> String {
     > = MGFWriterType::new(writer);
 
     for spec in spectra.iter() {
-        writer.write(spec.as_ref()).unwrap();
+        writer.write(&*spec.decoded()).unwrap();
     }
     drop(writer);
     let content = String::from_utf8_lossy(&buffer);
@@ -30,8 +33,7 @@ pub fn write_spectra_to_mgf(spectra: Vec<WebSpectrum>) -> String {
 }
 
 
-#[wasm_bindgen]
-pub fn _write_spectra_to_mzml(spectra: Vec<WebSpectrum>, reader: &crate::mem_reader::MemWebMZReader) -> String {
+fn write_spectra_to_mzml(spectra: &[WebSpectrum], metadata: Option<&crate::mem_reader::ReaderType>) -> String {
     let mut buffer = Vec::new();
     let writer = io::Cursor::new(&mut buffer);
     let mut writer: MzMLWriterType<
@@ -39,11 +41,187 @@ pub fn _write_spectra_to_mzml(spectra: Vec<WebSpectrum>, reader: &crate::mem_rea
         mzpeaks::CentroidPeak,
         mzdeisotope::DeconvolvedSolutionPeak,
     > = MzMLWriterType::new(writer);
-    writer.copy_metadata_from(reader.get_ref());
+    if let Some(metadata) = metadata {
+        writer.copy_metadata_from(metadata);
+    }
     for spec in spectra.iter() {
-        writer.write(spec.as_ref()).unwrap();
+        writer.write(&*spec.decoded()).unwrap();
     }
     drop(writer);
     let content = String::from_utf8_lossy(&buffer);
     content.to_string()
-}
\ No newline at end of file
+}
+
+
+#[wasm_bindgen]
+pub fn _write_spectra_to_mzml(spectra: Vec<WebSpectrum>, reader: &crate::mem_reader::MemWebMZReader) -> String {
+    write_spectra_to_mzml(&spectra, Some(reader.get_ref()))
+}
+
+
+/// Write spectra that did not come from a reader, like those built from JS, with only
+/// the file-level metadata the writer adds itself.
+#[wasm_bindgen]
+pub fn _write_standalone_spectra_to_mzml(spectra: Vec<WebSpectrum>) -> String {
+    write_spectra_to_mzml(&spectra, None)
+}
+
+fn cv_term(accession: &str, name: &str) -> Value {
+    json!({"accession": accession, "name": name})
+}
+
+/// mzQC only allows controlled vocabulary parameters, so a user param has no equivalent
+fn cv_param_to_mzqc(param: &Param) -> Option<Value> {
+    let accession = param.curie()?;
+    Some(json!({
+        "accession": accession.to_string(),
+        "name": param.name(),
+        "value": param_value_to_json(&param.value),
+    }))
+}
+
+/// Keep numbers and booleans as JSON numbers and booleans rather than their text
+fn param_value_to_json(value: &ParamValue) -> Value {
+    match value {
+        ParamValue::Float(x) => json!(x),
+        ParamValue::Int(i) => json!(i),
+        ParamValue::Boolean(b) => json!(b),
+        ParamValue::Empty => Value::Null,
+        value => json!(value.to_string()),
+    }
+}
+
+fn file_format_for_name(name: &str) -> Value {
+    let lower = name.to_ascii_lowercase();
+    if lower.ends_with(".mzml") || lower.ends_with(".mzml.gz") {
+        cv_term("MS:1000584", "mzML format")
+    } else if lower.ends_with(".mgf") || lower.ends_with(".mgf.gz") {
+        cv_term("MS:1001062", "Mascot MGF format")
+    } else {
+        cv_term("MS:1000560", "mass spectrometer file format")
+    }
+}
+
+/// Serialize a run QC report as an HUPO-PSI mzQC document.
+///
+/// The input files are `file_name`, the file the reader was opened from, followed by the
+/// source files listed in the reader's metadata. Only metrics with a PSI-MS term are written,
+/// and source file parameters without a CV accession are left out.
+/// Times in `report` are in minutes and are converted to seconds. `creation_date` is the
+/// ISO 8601 timestamp of the document, like `new Date().toISOString()`.
+#[wasm_bindgen]
+pub fn write_run_qc_to_mzqc(
+    report: &WebRunQCReport,
+    reader: &crate::mem_reader::MemWebMZReader,
+    creation_date: String,
+    file_name: Option<String>,
+) -> Result<String, JsError> {
+    let mut input_files = Vec::new();
+    if let Some(file_name) = file_name {
+        input_files.push(json!({
+            "location": file_name,
+            "name": file_name,
+            "fileFormat": file_format_for_name(&file_name),
+            "fileProperties": [],
+        }));
+    }
+    for source in reader.get_ref().file_description().source_files.iter() {
+        let location = if source.location.is_empty() {
+            source.name.clone()
+        } else {
+            format!("{}/{}", source.location.trim_end_matches('/'), source.name)
+        };
+        let file_format = source
+            .file_format
+            .as_ref()
+            .and_then(cv_param_to_mzqc)
+            .unwrap_or_else(|| file_format_for_name(&source.name));
+        input_files.push(json!({
+            "location": location,
+            "name": source.name,
+            "fileFormat": file_format,
+            "fileProperties": source.params.iter().filter_map(cv_param_to_mzqc).collect::<Vec<_>>(),
+        }));
+    }
+
+    let analysis_software = json!({
+        "accession": "MS:1000799",
+        "name": "custom unreleased software tool",
+        "value": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "uri": env!("CARGO_PKG_REPOSITORY"),
+    });
+
+    let seconds = |t: f64| t * 60.0;
+    // MS:4000063 is the fraction among precursors of known charge only
+    let n_charged = report.charge_counts.iter().sum::<usize>().max(1) as f64;
+    let quality_metrics = vec![
+        json!({
+            "accession": "MS:4000059",
+            "name": "number of MS1 spectra",
+            "value": report.ms1_count,
+        }),
+        json!({
+            "accession": "MS:4000060",
+            "name": "number of MS2 spectra",
+            "value": report.msn_count,
+        }),
+        json!({
+            "accession": "MS:4000053",
+            "name": "chromatography duration",
+            "value": seconds(report.end_time - report.start_time),
+            "unit": cv_term("UO:0000010", "second"),
+        }),
+        json!({
+            "accession": "MS:4000070",
+            "name": "retention time acquisition range",
+            "value": [seconds(report.start_time), seconds(report.end_time)],
+            "unit": cv_term("UO:0000010", "second"),
+        }),
+        json!({
+            "accession": "MS:4000104",
+            "name": "total ion currents",
+            "value": {
+                "MS:1000894": report.tic_times.iter().copied().map(seconds).collect::<Vec<_>>(),
+                "MS:1000285": report.tic_intensities,
+            },
+        }),
+        json!({
+            "accession": "MS:4000063",
+            "name": "MS2 known precursor charges fractions",
+            "value": {
+                "MS:1000041": report.charge_states,
+                "UO:0000191": report
+                    .charge_counts
+                    .iter()
+                    .map(|n| *n as f64 / n_charged)
+                    .collect::<Vec<_>>(),
+            },
+        }),
+    ];
+
+    let document = json!({
+        "mzQC": {
+            "version": "1.0.0",
+            "creationDate": creation_date,
+            "runQualities": [{
+                "metadata": {
+                    "inputFiles": input_files,
+                    "analysisSoftware": [analysis_software],
+                },
+                "qualityMetrics": quality_metrics,
+            }],
+            "controlledVocabularies": [
+                {
+                    "name": "Proteomics Standards Initiative Mass Spectrometry Ontology",
+                    "uri": "https://raw.githubusercontent.com/HUPO-PSI/psi-ms-CV/master/psi-ms.obo",
+                },
+                {
+                    "name": "Unit Ontology",
+                    "uri": "http://purl.obolibrary.org/obo/uo.obo",
+                },
+            ],
+        }
+    });
+    serde_json::to_string_pretty(&document).map_err(|e| JsError::new(&e.to_string()))
+}
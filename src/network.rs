@@ -0,0 +1,318 @@
+use std::fmt::Write as _;
+
+use mzdata::prelude::*;
+use mzdata::utils::neutral_mass;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::binds::WebSpectrum;
+use crate::similarity::{score_prepared, PreparedPeaks, WebSimilarityMethod, WebSimilarityParams};
+
+/// Options controlling which spectrum pairs are compared and which edges are kept
+#[wasm_bindgen(js_name = "NetworkParams")]
+#[derive(Debug, Clone, Copy)]
+pub struct WebNetworkParams {
+    /// The largest precursor neutral mass difference between two spectra that will be compared
+    #[wasm_bindgen(js_name = "maxPrecursorShift")]
+    pub max_precursor_shift: f64,
+    #[wasm_bindgen(js_name = "minScore")]
+    pub min_score: f64,
+    #[wasm_bindgen(js_name = "minMatchedPeaks")]
+    pub min_matched_peaks: usize,
+    /// Keep an edge only if it is among the `topK` best edges of both of its nodes
+    #[wasm_bindgen(js_name = "topK")]
+    pub top_k: usize,
+}
+
+impl Default for WebNetworkParams {
+    fn default() -> Self {
+        Self {
+            max_precursor_shift: 200.0,
+            min_score: 0.7,
+            min_matched_peaks: 6,
+            top_k: 10,
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "NetworkParams")]
+impl WebNetworkParams {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "NetworkNode")]
+#[derive(Debug, Clone, Serialize)]
+pub struct WebNetworkNode {
+    pub id: String,
+    pub index: usize,
+    #[wasm_bindgen(js_name = "precursorMz")]
+    pub precursor_mz: f64,
+    pub charge: Option<i32>,
+    /// The neutral mass of the precursor, taking it to be singly charged if its charge is unknown
+    #[wasm_bindgen(js_name = "neutralMass")]
+    pub neutral_mass: f64,
+    pub time: f64,
+}
+
+#[wasm_bindgen(inspectable, js_name = "NetworkEdge")]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WebNetworkEdge {
+    /// The position of the source node in [`MolecularNetwork::nodes`]
+    pub source: usize,
+    /// The position of the target node in [`MolecularNetwork::nodes`]
+    pub target: usize,
+    pub score: f64,
+    #[wasm_bindgen(js_name = "matchedCount")]
+    pub matched_count: usize,
+    /// The precursor neutral mass of the target less that of the source
+    #[wasm_bindgen(js_name = "massDelta")]
+    pub mass_delta: f64,
+}
+
+/// A similarity network over MSn spectra, scored with the modified cosine which also
+/// aligns fragments shifted by the precursor neutral mass difference.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct MolecularNetwork {
+    nodes: Vec<WebNetworkNode>,
+    peaks: Vec<PreparedPeaks>,
+    edges: Vec<WebNetworkEdge>,
+}
+
+impl MolecularNetwork {
+    /// Add a spectrum as a node. Spectra without a precursor are skipped.
+    pub fn push(&mut self, spectrum: &WebSpectrum, params: &WebSimilarityParams) -> bool {
+        let ion = match spectrum.as_ref().precursor().and_then(|p| p.ions.first()) {
+            Some(ion) => ion,
+            None => return false,
+        };
+        self.nodes.push(WebNetworkNode {
+            id: spectrum.id(),
+            index: spectrum.index(),
+            precursor_mz: ion.mz,
+            charge: ion.charge,
+            neutral_mass: neutral_mass(ion.mz, ion.charge.filter(|z| *z != 0).unwrap_or(1)),
+            time: spectrum.start_time(),
+        });
+        self.peaks
+            .push(PreparedPeaks::from_spectrum(spectrum, params));
+        true
+    }
+
+    /// Compute all pairwise scores within the precursor window and prune the edges
+    pub fn build_edges(
+        &mut self,
+        similarity_params: &WebSimilarityParams,
+        network_params: &WebNetworkParams,
+    ) {
+        let mut order: Vec<usize> = (0..self.nodes.len()).collect();
+        order.sort_by(|a, b| {
+            self.nodes[*a]
+                .neutral_mass
+                .total_cmp(&self.nodes[*b].neutral_mass)
+        });
+
+        let mut edges = Vec::new();
+        for (k, i) in order.iter().copied().enumerate() {
+            let a = &self.nodes[i];
+            for j in order[k + 1..].iter().copied() {
+                let b = &self.nodes[j];
+                // Fragments shifted by a modification are mostly singly charged, so they move
+                // by the mass of the modification whatever the charge of the precursor
+                let mass_delta = b.neutral_mass - a.neutral_mass;
+                if mass_delta > network_params.max_precursor_shift {
+                    break;
+                }
+                if let (Some(za), Some(zb)) = (a.charge, b.charge) {
+                    if za != zb {
+                        continue;
+                    }
+                }
+                let result = score_prepared(
+                    &self.peaks[i],
+                    &self.peaks[j],
+                    WebSimilarityMethod::Cosine,
+                    similarity_params,
+                    Some(mass_delta),
+                );
+                if result.score < network_params.min_score
+                    || result.matched_count() < network_params.min_matched_peaks
+                {
+                    continue;
+                }
+                edges.push(WebNetworkEdge {
+                    source: i,
+                    target: j,
+                    score: result.score,
+                    matched_count: result.matched_count(),
+                    mass_delta,
+                });
+            }
+        }
+        self.edges = Self::prune_top_k(self.nodes.len(), edges, network_params.top_k);
+    }
+
+    fn prune_top_k(n: usize, edges: Vec<WebNetworkEdge>, top_k: usize) -> Vec<WebNetworkEdge> {
+        if top_k == 0 {
+            return edges;
+        }
+        let mut incident: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for (e, edge) in edges.iter().enumerate() {
+            incident[edge.source].push(e);
+            incident[edge.target].push(e);
+        }
+        let mut rank_ok = vec![0u8; edges.len()];
+        for node_edges in incident.iter_mut() {
+            node_edges.sort_by(|a, b| edges[*b].score.total_cmp(&edges[*a].score));
+            for e in node_edges.iter().take(top_k) {
+                rank_ok[*e] += 1;
+            }
+        }
+        edges
+            .into_iter()
+            .zip(rank_ok)
+            .filter(|(_, ok)| *ok == 2)
+            .map(|(e, _)| e)
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl MolecularNetwork {
+    #[wasm_bindgen(js_name = "fromSpectra")]
+    pub fn from_spectra(
+        spectra: Vec<WebSpectrum>,
+        similarity_params: Option<WebSimilarityParams>,
+        network_params: Option<WebNetworkParams>,
+    ) -> MolecularNetwork {
+        let similarity_params = similarity_params.unwrap_or_default();
+        let network_params = network_params.unwrap_or_default();
+        let mut this = Self::default();
+        for spectrum in spectra.iter().filter(|s| s.ms_level() > 1) {
+            this.push(spectrum, &similarity_params);
+        }
+        this.build_edges(&similarity_params, &network_params);
+        this
+    }
+
+    pub fn nodes(&self) -> Vec<WebNetworkNode> {
+        self.nodes.clone()
+    }
+
+    pub fn edges(&self) -> Vec<WebNetworkEdge> {
+        self.edges.clone()
+    }
+
+    /// The connected components of the network as lists of node positions
+    pub fn components(&self) -> js_sys::Array {
+        let mut parent: Vec<usize> = (0..self.nodes.len()).collect();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for edge in self.edges.iter() {
+            let a = find(&mut parent, edge.source);
+            let b = find(&mut parent, edge.target);
+            if a != b {
+                parent[a] = b;
+            }
+        }
+        let mut groups: std::collections::BTreeMap<usize, Vec<usize>> = Default::default();
+        for i in 0..self.nodes.len() {
+            let root = find(&mut parent, i);
+            groups.entry(root).or_default().push(i);
+        }
+        groups
+            .into_values()
+            .map(|members| -> js_sys::Array {
+                members
+                    .into_iter()
+                    .map(|i| JsValue::from(i as u32))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        #[derive(Serialize)]
+        struct Graph<'a> {
+            nodes: &'a [WebNetworkNode],
+            edges: &'a [WebNetworkEdge],
+        }
+        serde_wasm_bindgen::to_value(&Graph {
+            nodes: &self.nodes,
+            edges: &self.edges,
+        })
+        .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = "toGraphML")]
+    pub fn to_graphml(&self) -> String {
+        let mut buffer = String::new();
+        buffer.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        buffer.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        for (key, target, name, tp) in [
+            ("d0", "node", "scan_id", "string"),
+            ("d1", "node", "precursor_mz", "double"),
+            ("d2", "node", "charge", "int"),
+            ("d3", "node", "time", "double"),
+            ("d4", "edge", "score", "double"),
+            ("d5", "edge", "matched_peaks", "int"),
+            ("d6", "edge", "mass_delta", "double"),
+        ] {
+            writeln!(
+                buffer,
+                "  <key id=\"{key}\" for=\"{target}\" attr.name=\"{name}\" attr.type=\"{tp}\"/>"
+            )
+            .unwrap();
+        }
+        buffer.push_str("  <graph id=\"G\" edgedefault=\"undirected\">\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let id = node
+                .id
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;");
+            writeln!(buffer, "    <node id=\"n{i}\">").unwrap();
+            writeln!(buffer, "      <data key=\"d0\">{id}</data>").unwrap();
+            writeln!(
+                buffer,
+                "      <data key=\"d1\">{}</data>",
+                node.precursor_mz
+            )
+            .unwrap();
+            if let Some(z) = node.charge {
+                writeln!(buffer, "      <data key=\"d2\">{z}</data>").unwrap();
+            }
+            writeln!(buffer, "      <data key=\"d3\">{}</data>", node.time).unwrap();
+            buffer.push_str("    </node>\n");
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                buffer,
+                "    <edge id=\"e{i}\" source=\"n{}\" target=\"n{}\">",
+                edge.source, edge.target
+            )
+            .unwrap();
+            writeln!(buffer, "      <data key=\"d4\">{}</data>", edge.score).unwrap();
+            writeln!(
+                buffer,
+                "      <data key=\"d5\">{}</data>",
+                edge.matched_count
+            )
+            .unwrap();
+            writeln!(buffer, "      <data key=\"d6\">{}</data>", edge.mass_delta).unwrap();
+            buffer.push_str("    </edge>\n");
+        }
+        buffer.push_str("  </graph>\n</graphml>\n");
+        buffer
+    }
+}
@@ -0,0 +1,140 @@
+//! JS bindings for the processing pipeline in `mzdata-pipeline`, which is shared with the
+//! desktop backend.
+
+use mzdeisotope::isotopic_model::IsotopicModels;
+use mzpeaks::Tolerance;
+use wasm_bindgen::prelude::*;
+
+pub use mzdata_pipeline::{
+    deconvolve_frame_features, deconvolve_spectrum, default_error_tolerance,
+    extract_frame_features, PipelineFrame, PipelineSpectrum, ProcessingPipeline, ProcessingStep,
+};
+
+use crate::binds::{WebIonMobilityFrame, WebIsotopicModel, WebSpectrum, WebTolerance};
+
+#[wasm_bindgen(js_name = "ProcessingPipeline")]
+#[derive(Debug, Clone, Default)]
+pub struct WebProcessingPipeline(ProcessingPipeline);
+
+impl WebProcessingPipeline {
+    pub fn as_inner(&self) -> &ProcessingPipeline {
+        &self.0
+    }
+}
+
+impl From<ProcessingPipeline> for WebProcessingPipeline {
+    fn from(value: ProcessingPipeline) -> Self {
+        Self(value)
+    }
+}
+
+#[wasm_bindgen(js_class = "ProcessingPipeline")]
+impl WebProcessingPipeline {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.0.steps.len()
+    }
+
+    pub fn reprofile(&mut self, dx: Option<f64>, fwhm: Option<f32>) {
+        self.0.push(ProcessingStep::Reprofile {
+            dx: dx.unwrap_or(0.001),
+            fwhm: fwhm.unwrap_or(0.005),
+        });
+    }
+
+    pub fn denoise(&mut self, scale: f32) {
+        self.0.push(ProcessingStep::Denoise { scale });
+    }
+
+    #[wasm_bindgen(js_name = "pickPeaks")]
+    pub fn pick_peaks(&mut self, signal_to_noise: Option<f32>) {
+        self.0.push(ProcessingStep::PickPeaks {
+            signal_to_noise: signal_to_noise.unwrap_or(1.0),
+        });
+    }
+
+    pub fn deconvolve(&mut self, score_threshold: f32, isotopic_models: Vec<WebIsotopicModel>) {
+        self.0.push(ProcessingStep::Deconvolve {
+            score_threshold,
+            isotopic_models: isotopic_models
+                .into_iter()
+                .map(IsotopicModels::from)
+                .collect(),
+        });
+    }
+
+    #[wasm_bindgen(js_name = "extractFeatures")]
+    pub fn extract_features(
+        &mut self,
+        min_length: usize,
+        maximum_gap_size: f64,
+        error_tolerance: Option<WebTolerance>,
+    ) {
+        self.0.push(ProcessingStep::ExtractFeatures {
+            min_length,
+            maximum_gap_size,
+            error_tolerance: error_tolerance
+                .map(Tolerance::from)
+                .unwrap_or_else(default_error_tolerance),
+        });
+    }
+
+    #[wasm_bindgen(js_name = "deconvolveFeatures")]
+    pub fn deconvolve_features(
+        &mut self,
+        min_length: usize,
+        maximum_gap_size: f64,
+        score_threshold: f32,
+        isotopic_models: Vec<WebIsotopicModel>,
+        error_tolerance: Option<WebTolerance>,
+    ) {
+        self.0.push(ProcessingStep::DeconvolveFeatures {
+            min_length,
+            maximum_gap_size,
+            score_threshold,
+            isotopic_models: isotopic_models
+                .into_iter()
+                .map(IsotopicModels::from)
+                .collect(),
+            error_tolerance: error_tolerance
+                .map(Tolerance::from)
+                .unwrap_or_else(default_error_tolerance),
+        });
+    }
+
+    /// Run the spectrum steps of the pipeline on `spectrum` in place
+    pub fn apply(&self, spectrum: &mut WebSpectrum) -> Result<(), JsError> {
+        self.0
+            .process_spectrum(spectrum.as_mut())
+            .map_err(|e| JsError::new(&e))
+    }
+
+    /// Run the frame steps of the pipeline on `frame` in place
+    #[wasm_bindgen(js_name = "applyToFrame")]
+    pub fn apply_to_frame(&self, frame: &mut WebIonMobilityFrame) -> Result<(), JsError> {
+        self.0
+            .process_frame(frame.as_mut())
+            .map_err(|e| JsError::new(&e))
+    }
+
+    pub fn copy(&self) -> Self {
+        self.clone()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(&self.0).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    #[wasm_bindgen(js_name = "fromJSON")]
+    pub fn from_json(val: JsValue) -> Result<Self, JsError> {
+        let inner =
+            serde_wasm_bindgen::from_value(val).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(Self(inner))
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+
+use mzdata::prelude::*;
+use mzdata::spectrum::{MultiLayerSpectrum, SignalContinuity};
+use mzdeisotope::DeconvolvedSolutionPeak;
+use mzpeaks::{prelude::*, CentroidPeak, Tolerance};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{WebIsotopicModel, WebSpectrum, WebTolerance};
+
+/// Options for the run quality control report
+#[wasm_bindgen(getter_with_clone, js_name = "RunQCParams")]
+#[derive(Debug, Clone)]
+pub struct WebRunQCParams {
+    /// The width of the time bins scan counts are tallied over
+    #[wasm_bindgen(js_name = "binWidth")]
+    pub bin_width: f64,
+    /// The m/z of lock mass or background ions to track the mass error of in MS1 spectra
+    #[wasm_bindgen(js_name = "lockMasses")]
+    pub lock_masses: Vec<f64>,
+    /// The minimum isotopic fit score for a precursor to count as deconvolvable
+    #[wasm_bindgen(js_name = "scoreThreshold")]
+    pub score_threshold: f32,
+    error_tolerance: Tolerance,
+}
+
+impl Default for WebRunQCParams {
+    fn default() -> Self {
+        Self {
+            bin_width: 1.0,
+            lock_masses: Vec::new(),
+            score_threshold: 10.0,
+            error_tolerance: Tolerance::PPM(20.0),
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "RunQCParams")]
+impl WebRunQCParams {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The tolerance used to find lock masses and to match precursors to deconvolved peaks
+    #[wasm_bindgen(getter, js_name = "errorTolerance")]
+    pub fn error_tolerance(&self) -> WebTolerance {
+        self.error_tolerance.into()
+    }
+
+    #[wasm_bindgen(setter, js_name = "errorTolerance")]
+    pub fn set_error_tolerance(&mut self, value: WebTolerance) {
+        self.error_tolerance = value.into();
+    }
+}
+
+/// Summary statistics of a set of values
+#[wasm_bindgen(inspectable, js_name = "QCDistribution")]
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct WebQCDistribution {
+    pub count: usize,
+    pub min: f64,
+    pub q1: f64,
+    pub median: f64,
+    pub q3: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl WebQCDistribution {
+    pub fn from_values(values: impl IntoIterator<Item = f64>) -> Self {
+        let mut values: Vec<f64> = values.into_iter().filter(|v| v.is_finite()).collect();
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let quantile = |q: f64| {
+            let pos = q * (values.len() - 1) as f64;
+            let lo = pos.floor() as usize;
+            let hi = pos.ceil() as usize;
+            values[lo] + (values[hi] - values[lo]) * (pos - lo as f64)
+        };
+        Self {
+            count: values.len(),
+            min: values[0],
+            q1: quantile(0.25),
+            median: quantile(0.5),
+            q3: quantile(0.75),
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        }
+    }
+}
+
+#[wasm_bindgen(inspectable, js_name = "LockMassObservation")]
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebLockMassObservation {
+    pub time: f64,
+    #[wasm_bindgen(js_name = "lockMass")]
+    pub lock_mass: f64,
+    #[wasm_bindgen(js_name = "observedMz")]
+    pub observed_mz: f64,
+    #[wasm_bindgen(js_name = "ppmError")]
+    pub ppm_error: f64,
+}
+
+/// Run-level quality control metrics, computed in a single pass over a run
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "RunQCReport")]
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebRunQCReport {
+    #[wasm_bindgen(js_name = "spectrumCount")]
+    pub spectrum_count: usize,
+    #[wasm_bindgen(js_name = "ms1Count")]
+    pub ms1_count: usize,
+    #[wasm_bindgen(js_name = "msnCount")]
+    pub msn_count: usize,
+    #[wasm_bindgen(js_name = "startTime")]
+    pub start_time: f64,
+    #[wasm_bindgen(js_name = "endTime")]
+    pub end_time: f64,
+
+    /// The start time of each bin of `ms1CountsOverTime` and `msnCountsOverTime`
+    #[wasm_bindgen(js_name = "timeBins")]
+    pub time_bins: Vec<f64>,
+    #[wasm_bindgen(js_name = "ms1CountsOverTime")]
+    pub ms1_counts_over_time: Vec<usize>,
+    #[wasm_bindgen(js_name = "msnCountsOverTime")]
+    pub msn_counts_over_time: Vec<usize>,
+
+    /// The MS1 total ion current chromatogram
+    #[wasm_bindgen(js_name = "ticTimes")]
+    pub tic_times: Vec<f64>,
+    #[wasm_bindgen(js_name = "ticIntensities")]
+    pub tic_intensities: Vec<f32>,
+
+    #[wasm_bindgen(js_name = "ms1InjectionTimes")]
+    pub ms1_injection_times: WebQCDistribution,
+    #[wasm_bindgen(js_name = "msnInjectionTimes")]
+    pub msn_injection_times: WebQCDistribution,
+
+    /// The distinct precursor charge states, in ascending order
+    #[wasm_bindgen(js_name = "chargeStates")]
+    pub charge_states: Vec<i32>,
+    /// The number of precursors with each charge in `chargeStates`
+    #[wasm_bindgen(js_name = "chargeCounts")]
+    pub charge_counts: Vec<usize>,
+    #[wasm_bindgen(js_name = "unknownChargeCount")]
+    pub unknown_charge_count: usize,
+
+    /// The time between consecutive MS1 scans
+    #[wasm_bindgen(js_name = "cycleTimes")]
+    pub cycle_times: WebQCDistribution,
+    /// The number of MSn scans acquired after each MS1 scan
+    #[wasm_bindgen(js_name = "msnPerCycle")]
+    pub msn_per_cycle: WebQCDistribution,
+    /// The fraction of acquisition time spent on MSn scans
+    #[wasm_bindgen(js_name = "msnDutyCycle")]
+    pub msn_duty_cycle: f64,
+
+    /// The fraction of MSn precursors matching an isotopic envelope in the preceding MS1 scan
+    #[wasm_bindgen(js_name = "deconvolvablePrecursorFraction")]
+    pub deconvolvable_precursor_fraction: f64,
+
+    #[wasm_bindgen(js_name = "lockMassErrors")]
+    pub lock_mass_errors: Vec<WebLockMassObservation>,
+    /// The distribution of `lockMassErrors` in PPM
+    #[wasm_bindgen(js_name = "lockMassDrift")]
+    pub lock_mass_drift: WebQCDistribution,
+}
+
+#[wasm_bindgen(js_class = "RunQCReport")]
+impl WebRunQCReport {
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> Result<JsValue, JsError> {
+        serde_wasm_bindgen::to_value(self).map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// The most recent MS1 scan, deconvolved on demand when an MSn scan asks about its precursor
+struct SurveyScan {
+    spectrum: WebSpectrum,
+    deconvolved: bool,
+}
+
+/// Accumulates [`WebRunQCReport`] metrics one spectrum at a time
+pub struct RunQCBuilder {
+    params: WebRunQCParams,
+    /// (time, MS level) of every scan in acquisition order
+    scans: Vec<(f64, u8)>,
+    tic_times: Vec<f64>,
+    tic_intensities: Vec<f32>,
+    ms1_injection_times: Vec<f64>,
+    msn_injection_times: Vec<f64>,
+    charges: BTreeMap<i32, usize>,
+    unknown_charge_count: usize,
+    msn_per_cycle: Vec<usize>,
+    precursors_checked: usize,
+    precursors_deconvolvable: usize,
+    lock_mass_errors: Vec<WebLockMassObservation>,
+    survey: Option<SurveyScan>,
+}
+
+impl RunQCBuilder {
+    pub fn new(params: WebRunQCParams) -> Self {
+        Self {
+            params,
+            scans: Vec::new(),
+            tic_times: Vec::new(),
+            tic_intensities: Vec::new(),
+            ms1_injection_times: Vec::new(),
+            msn_injection_times: Vec::new(),
+            charges: BTreeMap::new(),
+            unknown_charge_count: 0,
+            msn_per_cycle: Vec::new(),
+            precursors_checked: 0,
+            precursors_deconvolvable: 0,
+            lock_mass_errors: Vec::new(),
+            survey: None,
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        mut spectrum: MultiLayerSpectrum<CentroidPeak, DeconvolvedSolutionPeak>,
+    ) {
+        let time = spectrum.start_time();
+        let ms_level = spectrum.ms_level();
+        // An injection time of zero means it was not recorded
+        let injection_time = spectrum
+            .acquisition()
+            .first_scan()
+            .map(|s| s.injection_time as f64)
+            .filter(|t| *t > 0.0);
+        self.scans.push((time, ms_level));
+
+        if ms_level == 1 {
+            self.tic_times.push(time);
+            self.tic_intensities.push(spectrum.total_ion_current());
+            self.ms1_injection_times.extend(injection_time);
+            self.msn_per_cycle.push(0);
+
+            if spectrum.signal_continuity() == SignalContinuity::Profile {
+                if let Err(e) = spectrum.pick_peaks(1.0) {
+                    log::warn!(
+                        "Failed to pick peaks of {}, skipping its peak metrics: {e}",
+                        spectrum.id()
+                    );
+                    self.survey = None;
+                    return;
+                }
+                spectrum.description_mut().signal_continuity = SignalContinuity::Centroid;
+            }
+            let spectrum = WebSpectrum::from(spectrum);
+            self.observe_lock_masses(&spectrum);
+            self.survey = Some(SurveyScan {
+                spectrum,
+                deconvolved: false,
+            });
+        } else {
+            self.msn_injection_times.extend(injection_time);
+            if let Some(n) = self.msn_per_cycle.last_mut() {
+                *n += 1;
+            }
+            let ion = spectrum.precursor().and_then(|p| p.ions.first());
+            if let Some(ion) = ion {
+                match ion.charge {
+                    Some(z) => *self.charges.entry(z).or_default() += 1,
+                    None => self.unknown_charge_count += 1,
+                }
+                let precursor_mz = ion.mz;
+                self.check_precursor(precursor_mz);
+            }
+        }
+    }
+
+    fn observe_lock_masses(&mut self, spectrum: &WebSpectrum) {
+        let decoded = spectrum.decoded();
+        let peaks = match decoded.peaks.as_ref() {
+            Some(peaks) => peaks,
+            None => return,
+        };
+        let time = spectrum.start_time();
+        for lock_mass in self.params.lock_masses.iter().copied() {
+            let best = peaks
+                .all_peaks_for(lock_mass, self.params.error_tolerance)
+                .iter()
+                .max_by(|a, b| a.intensity.total_cmp(&b.intensity));
+            if let Some(peak) = best {
+                self.lock_mass_errors.push(WebLockMassObservation {
+                    time,
+                    lock_mass,
+                    observed_mz: peak.mz,
+                    ppm_error: (peak.mz - lock_mass) / lock_mass * 1e6,
+                });
+            }
+        }
+    }
+
+    fn check_precursor(&mut self, precursor_mz: f64) {
+        let survey = match self.survey.as_mut() {
+            Some(survey) => survey,
+            None => return,
+        };
+        if !survey.deconvolved {
+            survey.spectrum.deconvolve(
+                self.params.score_threshold,
+                vec![WebIsotopicModel::peptide()],
+            );
+            survey.deconvolved = true;
+        }
+        self.precursors_checked += 1;
+        let tol = self.params.error_tolerance;
+        let found = survey
+            .spectrum
+            .as_ref()
+            .deconvoluted_peaks
+            .as_ref()
+            .map(|peaks| peaks.iter().any(|p| tol.test(p.mz(), precursor_mz)))
+            .unwrap_or_default();
+        if found {
+            self.precursors_deconvolvable += 1;
+        }
+    }
+
+    pub fn finish(self) -> WebRunQCReport {
+        let mut report = WebRunQCReport {
+            spectrum_count: self.scans.len(),
+            ms1_count: self.scans.iter().filter(|(_, l)| *l == 1).count(),
+            ..Default::default()
+        };
+        report.msn_count = report.spectrum_count - report.ms1_count;
+        report.start_time = self
+            .scans
+            .iter()
+            .map(|(t, _)| *t)
+            .fold(f64::INFINITY, f64::min);
+        report.end_time = self
+            .scans
+            .iter()
+            .map(|(t, _)| *t)
+            .fold(f64::NEG_INFINITY, f64::max);
+        if self.scans.is_empty() {
+            report.start_time = 0.0;
+            report.end_time = 0.0;
+        }
+
+        let bin_width = if self.params.bin_width > 0.0 {
+            self.params.bin_width
+        } else {
+            1.0
+        };
+        let n_bins = ((report.end_time - report.start_time) / bin_width).floor() as usize + 1;
+        report.time_bins = (0..n_bins)
+            .map(|i| report.start_time + i as f64 * bin_width)
+            .collect();
+        report.ms1_counts_over_time = vec![0; n_bins];
+        report.msn_counts_over_time = vec![0; n_bins];
+        for (time, level) in self.scans.iter() {
+            let i = (((time - report.start_time) / bin_width).floor() as usize).min(n_bins - 1);
+            if *level == 1 {
+                report.ms1_counts_over_time[i] += 1;
+            } else {
+                report.msn_counts_over_time[i] += 1;
+            }
+        }
+
+        // Each scan occupies the instrument until the next scan begins
+        let mut total_time = 0.0;
+        let mut msn_time = 0.0;
+        for pair in self.scans.windows(2) {
+            let duration = (pair[1].0 - pair[0].0).max(0.0);
+            total_time += duration;
+            if pair[0].1 > 1 {
+                msn_time += duration;
+            }
+        }
+        report.msn_duty_cycle = if total_time > 0.0 {
+            msn_time / total_time
+        } else {
+            0.0
+        };
+        report.cycle_times =
+            WebQCDistribution::from_values(self.tic_times.windows(2).map(|w| w[1] - w[0]));
+        report.msn_per_cycle =
+            WebQCDistribution::from_values(self.msn_per_cycle.iter().map(|n| *n as f64));
+
+        report.ms1_injection_times = WebQCDistribution::from_values(self.ms1_injection_times);
+        report.msn_injection_times = WebQCDistribution::from_values(self.msn_injection_times);
+
+        report.charge_states = self.charges.keys().copied().collect();
+        report.charge_counts = self.charges.values().copied().collect();
+        report.unknown_charge_count = self.unknown_charge_count;
+
+        report.deconvolvable_precursor_fraction = if self.precursors_checked > 0 {
+            self.precursors_deconvolvable as f64 / self.precursors_checked as f64
+        } else {
+            0.0
+        };
+
+        report.lock_mass_drift =
+            WebQCDistribution::from_values(self.lock_mass_errors.iter().map(|o| o.ppm_error));
+        report.lock_mass_errors = self.lock_mass_errors;
+        report.tic_times = self.tic_times;
+        report.tic_intensities = self.tic_intensities;
+        report
+    }
+}
@@ -0,0 +1,316 @@
+use mzdata::prelude::*;
+use mzpeaks::Tolerance;
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{FeatureFit, WebDeconvolvedFeature, WebFeature, WebSpectrum, WebTolerance};
+
+/// A feature's quantitative summary, independent of whether it came from a
+/// m/z feature or a charge-deconvolved feature.
+#[derive(Debug, Clone)]
+struct QuantFeature {
+    mz: f64,
+    neutral_mass: Option<f64>,
+    charge: Option<i32>,
+    start_time: f64,
+    end_time: f64,
+    apex_time: f64,
+    apex_intensity: f32,
+    area: f32,
+    fit: FeatureFit,
+    scan_ids: Vec<String>,
+}
+
+impl QuantFeature {
+    fn from_parts(
+        mz: f64,
+        neutral_mass: Option<f64>,
+        charge: Option<i32>,
+        times: &[f64],
+        intensities: &[f32],
+        area: f32,
+        fit: FeatureFit,
+    ) -> Self {
+        let (apex_time, apex_intensity) = times
+            .iter()
+            .copied()
+            .zip(intensities.iter().copied())
+            .fold((0.0, 0.0f32), |(best_t, best_i), (t, i)| {
+                if i > best_i {
+                    (t, i)
+                } else {
+                    (best_t, best_i)
+                }
+            });
+        Self {
+            mz,
+            neutral_mass,
+            charge,
+            start_time: times.first().copied().unwrap_or_default(),
+            end_time: times.last().copied().unwrap_or_default(),
+            apex_time,
+            apex_intensity,
+            area,
+            fit,
+            scan_ids: Vec::new(),
+        }
+    }
+
+    /// The m/z a precursor ion selected from this feature would be reported at
+    fn precursor_mz(&self) -> f64 {
+        match (self.neutral_mass, self.charge) {
+            (Some(mass), Some(z)) if z != 0 => mzdata::utils::mass_charge_ratio(mass, z),
+            _ => self.mz,
+        }
+    }
+
+    fn peak_shapes_json(&self) -> String {
+        serde_json::to_string(self.fit.as_models()).unwrap_or_default()
+    }
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "FeatureTableRow")]
+#[derive(Debug, Clone)]
+pub struct FeatureTableRow {
+    pub index: usize,
+    pub mz: f64,
+    #[wasm_bindgen(js_name = "neutralMass")]
+    pub neutral_mass: Option<f64>,
+    pub charge: Option<i32>,
+    #[wasm_bindgen(js_name = "startTime")]
+    pub start_time: f64,
+    #[wasm_bindgen(js_name = "apexTime")]
+    pub apex_time: f64,
+    #[wasm_bindgen(js_name = "endTime")]
+    pub end_time: f64,
+    #[wasm_bindgen(js_name = "apexIntensity")]
+    pub apex_intensity: f32,
+    pub area: f32,
+    #[wasm_bindgen(js_name = "fittedArea")]
+    pub fitted_area: f64,
+    #[wasm_bindgen(js_name = "rSquared")]
+    pub r_squared: f64,
+    #[wasm_bindgen(js_name = "peakShapes")]
+    pub peak_shapes: String,
+    #[wasm_bindgen(js_name = "scanIDs")]
+    pub scan_ids: Vec<String>,
+}
+
+/// A label-free quantification table linking LC-MS features to the MS2 scans
+/// that were triggered from them.
+#[wasm_bindgen]
+#[derive(Debug, Default, Clone)]
+pub struct FeatureTable {
+    features: Vec<QuantFeature>,
+    /// The positions of `features` sorted by their precursor m/z, rebuilt when features
+    /// have been added since it was last sorted
+    by_mz: Vec<usize>,
+}
+
+impl FeatureTable {
+    fn sort_by_mz(&mut self) {
+        if self.by_mz.len() == self.features.len() {
+            return;
+        }
+        self.by_mz = (0..self.features.len()).collect();
+        self.by_mz.sort_by(|a, b| {
+            self.features[*a]
+                .precursor_mz()
+                .total_cmp(&self.features[*b].precursor_mz())
+        });
+    }
+
+    /// Link a single MSn spectrum to the feature it was most likely triggered from,
+    /// returning the index of that feature, or `None` if there is no such feature or
+    /// the spectrum was already linked to it.
+    pub fn link_spectrum<S: SpectrumLike>(
+        &mut self,
+        spectrum: &S,
+        error_tolerance: Tolerance,
+        time_padding: f64,
+    ) -> Option<usize> {
+        if spectrum.ms_level() < 2 {
+            return None;
+        }
+        let ion = spectrum.precursor().and_then(|p| p.ions.first())?;
+        let time = spectrum.start_time();
+
+        self.sort_by_mz();
+        let (low, high) = error_tolerance.bounds(ion.mz);
+        let start = self
+            .by_mz
+            .partition_point(|i| self.features[*i].precursor_mz() < low);
+        let end = self
+            .by_mz
+            .partition_point(|i| self.features[*i].precursor_mz() <= high);
+
+        let (_, i) = self.by_mz[start..end]
+            .iter()
+            .copied()
+            .filter(|i| {
+                let feature = &self.features[*i];
+                let same_charge = match (ion.charge, feature.charge) {
+                    (Some(z), Some(fz)) => z == fz,
+                    _ => true,
+                };
+                same_charge
+                    && time >= feature.start_time - time_padding
+                    && time <= feature.end_time + time_padding
+            })
+            .map(|i| ((self.features[i].apex_time - time).abs(), i))
+            .min_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))?;
+
+        let scan_ids = &mut self.features[i].scan_ids;
+        if scan_ids.iter().any(|id| id == spectrum.id()) {
+            return None;
+        }
+        scan_ids.push(spectrum.id().to_string());
+        Some(i)
+    }
+
+    /// Remove every spectrum linked to the features, before linking a whole run again
+    pub fn clear_links(&mut self) {
+        for feature in self.features.iter_mut() {
+            feature.scan_ids.clear();
+        }
+    }
+
+    fn to_delimited(&self, sep: char) -> String {
+        let mut buffer = String::new();
+        let header = [
+            "index",
+            "mz",
+            "neutral_mass",
+            "charge",
+            "start_time",
+            "apex_time",
+            "end_time",
+            "apex_intensity",
+            "area",
+            "fitted_area",
+            "r_squared",
+            "peak_shapes",
+            "scan_ids",
+        ];
+        buffer.push_str(&header.join(&sep.to_string()));
+        buffer.push('\n');
+        for row in self.rows() {
+            let peak_shapes = if sep == ',' {
+                format!("\"{}\"", row.peak_shapes.replace('"', "\"\""))
+            } else {
+                row.peak_shapes
+            };
+            let fields = [
+                row.index.to_string(),
+                row.mz.to_string(),
+                row.neutral_mass.map(|v| v.to_string()).unwrap_or_default(),
+                row.charge.map(|v| v.to_string()).unwrap_or_default(),
+                row.start_time.to_string(),
+                row.apex_time.to_string(),
+                row.end_time.to_string(),
+                row.apex_intensity.to_string(),
+                row.area.to_string(),
+                row.fitted_area.to_string(),
+                row.r_squared.to_string(),
+                peak_shapes,
+                row.scan_ids.join(";"),
+            ];
+            buffer.push_str(&fields.join(&sep.to_string()));
+            buffer.push('\n');
+        }
+        buffer
+    }
+}
+
+#[wasm_bindgen]
+impl FeatureTable {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.features.len()
+    }
+
+    #[wasm_bindgen(js_name = "addFeature")]
+    pub fn add_feature(&mut self, feature: &WebFeature) -> usize {
+        let fit = feature.fit_peaks(None);
+        let entry = QuantFeature::from_parts(
+            feature.average_mz(),
+            None,
+            None,
+            &feature.times(),
+            &feature.intensities(),
+            feature.area(),
+            fit,
+        );
+        self.features.push(entry);
+        self.features.len() - 1
+    }
+
+    #[wasm_bindgen(js_name = "addDeconvolvedFeature")]
+    pub fn add_deconvolved_feature(&mut self, feature: &WebDeconvolvedFeature) -> usize {
+        let fit = feature.fit_peaks(None);
+        let entry = QuantFeature::from_parts(
+            feature.mz(),
+            Some(feature.neutral_mass()),
+            Some(feature.charge()),
+            &feature.times(),
+            &feature.intensities(),
+            feature.area(),
+            fit,
+        );
+        self.features.push(entry);
+        self.features.len() - 1
+    }
+
+    /// Associate each MSn spectrum with the feature whose m/z, charge and time span
+    /// best explain its precursor, returning the number of spectra newly linked.
+    #[wasm_bindgen(js_name = "linkSpectra")]
+    pub fn link_spectra(
+        &mut self,
+        spectra: Vec<WebSpectrum>,
+        error_tolerance: &WebTolerance,
+        time_padding: Option<f64>,
+    ) -> usize {
+        let error_tolerance: Tolerance = (*error_tolerance).into();
+        let time_padding = time_padding.unwrap_or(0.1);
+        spectra
+            .iter()
+            .filter_map(|s| self.link_spectrum(s.as_ref(), error_tolerance, time_padding))
+            .count()
+    }
+
+    pub fn rows(&self) -> Vec<FeatureTableRow> {
+        self.features
+            .iter()
+            .enumerate()
+            .map(|(index, f)| FeatureTableRow {
+                index,
+                mz: f.mz,
+                neutral_mass: f.neutral_mass,
+                charge: f.charge,
+                start_time: f.start_time,
+                apex_time: f.apex_time,
+                end_time: f.end_time,
+                apex_intensity: f.apex_intensity,
+                area: f.area,
+                fitted_area: f.fit.area_under_fit(None, None),
+                r_squared: f.fit.r_squared(),
+                peak_shapes: f.peak_shapes_json(),
+                scan_ids: f.scan_ids.clone(),
+            })
+            .collect()
+    }
+
+    #[wasm_bindgen(js_name = "toCSV")]
+    pub fn to_csv(&self) -> String {
+        self.to_delimited(',')
+    }
+
+    #[wasm_bindgen(js_name = "toTSV")]
+    pub fn to_tsv(&self) -> String {
+        self.to_delimited('\t')
+    }
+}
@@ -0,0 +1,486 @@
+use std::f64::consts::PI;
+
+use mzdata::prelude::*;
+use mzpeaks::Tolerance;
+use wasm_bindgen::prelude::*;
+
+use crate::binds::{SimpleWebPeak, WebSpectrum, WebTolerance};
+
+#[wasm_bindgen(js_name = "SimilarityMethod")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WebSimilarityMethod {
+    #[default]
+    Cosine,
+    SpectralContrastAngle,
+    Entropy,
+}
+
+/// Options controlling how two peak lists are prepared and aligned before scoring
+#[wasm_bindgen(js_name = "SimilarityParams")]
+#[derive(Debug, Clone, Copy)]
+pub struct WebSimilarityParams {
+    error_tolerance: Tolerance,
+    /// Take the square root of intensities before scoring
+    #[wasm_bindgen(js_name = "sqrtTransform")]
+    pub sqrt_transform: bool,
+    /// Drop peaks within `precursorWindow` m/z of a spectrum's precursor
+    #[wasm_bindgen(js_name = "removePrecursor")]
+    pub remove_precursor: bool,
+    #[wasm_bindgen(js_name = "precursorWindow")]
+    pub precursor_window: f64,
+}
+
+impl Default for WebSimilarityParams {
+    fn default() -> Self {
+        Self {
+            error_tolerance: Tolerance::Da(0.02),
+            sqrt_transform: true,
+            remove_precursor: true,
+            precursor_window: 1.5,
+        }
+    }
+}
+
+#[wasm_bindgen(js_class = "SimilarityParams")]
+impl WebSimilarityParams {
+    #[wasm_bindgen(constructor)]
+    pub fn new(error_tolerance: Option<WebTolerance>) -> Self {
+        let mut this = Self::default();
+        if let Some(error_tolerance) = error_tolerance {
+            this.error_tolerance = error_tolerance.into();
+        }
+        this
+    }
+
+    #[wasm_bindgen(getter, js_name = "errorTolerance")]
+    pub fn error_tolerance(&self) -> WebTolerance {
+        self.error_tolerance.into()
+    }
+
+    #[wasm_bindgen(setter, js_name = "errorTolerance")]
+    pub fn set_error_tolerance(&mut self, error_tolerance: WebTolerance) {
+        self.error_tolerance = error_tolerance.into();
+    }
+}
+
+impl WebSimilarityParams {
+    pub fn tolerance(&self) -> Tolerance {
+        self.error_tolerance
+    }
+}
+
+#[wasm_bindgen(inspectable, js_name = "MatchedPeakPair")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WebMatchedPeakPair {
+    #[wasm_bindgen(js_name = "mzA")]
+    pub mz_a: f64,
+    #[wasm_bindgen(js_name = "intensityA")]
+    pub intensity_a: f32,
+    #[wasm_bindgen(js_name = "mzB")]
+    pub mz_b: f64,
+    #[wasm_bindgen(js_name = "intensityB")]
+    pub intensity_b: f32,
+}
+
+#[wasm_bindgen(getter_with_clone, inspectable, js_name = "SimilarityResult")]
+#[derive(Debug, Clone, Default)]
+pub struct WebSimilarityResult {
+    pub score: f64,
+    /// The aligned peak pairs, with intensities as they were in the original spectra
+    pub pairs: Vec<WebMatchedPeakPair>,
+}
+
+#[wasm_bindgen(js_class = "SimilarityResult")]
+impl WebSimilarityResult {
+    #[wasm_bindgen(getter, js_name = "matchedCount")]
+    pub fn matched_count(&self) -> usize {
+        self.pairs.len()
+    }
+}
+
+/// A peak list prepared for scoring, sorted by m/z
+#[derive(Debug, Clone, Default)]
+pub struct PreparedPeaks {
+    pub mzs: Vec<f64>,
+    /// The original intensities, reported in matched pairs
+    pub raw: Vec<f32>,
+    /// The transformed intensities, used for scoring
+    pub weights: Vec<f64>,
+    pub precursor_mz: Option<f64>,
+}
+
+impl PreparedPeaks {
+    pub fn new(
+        peaks: impl IntoIterator<Item = (f64, f32)>,
+        precursor_mz: Option<f64>,
+        params: &WebSimilarityParams,
+    ) -> Self {
+        let mut peaks: Vec<(f64, f32)> = peaks
+            .into_iter()
+            .filter(|(mz, intensity)| {
+                if *intensity <= 0.0 {
+                    return false;
+                }
+                match precursor_mz {
+                    Some(pmz) if params.remove_precursor => {
+                        (mz - pmz).abs() > params.precursor_window
+                    }
+                    _ => true,
+                }
+            })
+            .collect();
+        peaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mzs = peaks.iter().map(|p| p.0).collect();
+        let raw: Vec<f32> = peaks.iter().map(|p| p.1).collect();
+        let weights = raw
+            .iter()
+            .map(|i| {
+                let i = *i as f64;
+                if params.sqrt_transform {
+                    i.sqrt()
+                } else {
+                    i
+                }
+            })
+            .collect();
+        Self {
+            mzs,
+            raw,
+            weights,
+            precursor_mz,
+        }
+    }
+
+    pub fn from_spectrum(spectrum: &WebSpectrum, params: &WebSimilarityParams) -> Self {
+        let precursor_mz = spectrum
+            .as_ref()
+            .precursor()
+            .and_then(|p| p.ions.first())
+            .map(|i| i.mz);
+        let peaks = spectrum
+            .peak_array()
+            .into_iter()
+            .map(|p| (p.mz, p.intensity));
+        Self::new(peaks, precursor_mz, params)
+    }
+
+    pub fn len(&self) -> usize {
+        self.mzs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mzs.is_empty()
+    }
+
+    fn norm(&self) -> f64 {
+        self.weights.iter().map(|w| w * w).sum::<f64>().sqrt()
+    }
+
+    /// Find the index range of peaks within `error_tolerance` of `query`
+    fn search(&self, query: f64, error_tolerance: Tolerance) -> std::ops::Range<usize> {
+        let (low, high) = error_tolerance.bounds(query);
+        let start = self.mzs.partition_point(|mz| *mz < low);
+        let end = self.mzs.partition_point(|mz| *mz <= high);
+        start..end
+    }
+}
+
+/// Greedily align peaks in `a` to peaks in `b`, one-to-one, highest intensity product
+/// first. Peaks in `b` may also match peaks in `a` offset by `shift`, which allows a
+/// precursor mass difference to be accounted for.
+pub fn align_peaks(
+    a: &PreparedPeaks,
+    b: &PreparedPeaks,
+    error_tolerance: Tolerance,
+    shift: Option<f64>,
+) -> Vec<(usize, usize)> {
+    let mut candidates = Vec::new();
+    for (i, mz) in a.mzs.iter().enumerate() {
+        for j in b.search(*mz, error_tolerance) {
+            candidates.push((a.weights[i] * b.weights[j], i, j));
+        }
+        if let Some(shift) = shift {
+            // Only search shifted peaks when the shift is wider than the tolerance window
+            // at this m/z, otherwise they are the unshifted matches again
+            let (low, high) = error_tolerance.bounds(*mz);
+            if shift.abs() > (high - low) / 2.0 {
+                for j in b.search(*mz + shift, error_tolerance) {
+                    candidates.push((a.weights[i] * b.weights[j], i, j));
+                }
+            }
+        }
+    }
+    candidates.sort_by(|x, y| y.0.total_cmp(&x.0));
+
+    let mut used_a = vec![false; a.len()];
+    let mut used_b = vec![false; b.len()];
+    let mut pairs = Vec::new();
+    for (_, i, j) in candidates {
+        if used_a[i] || used_b[j] {
+            continue;
+        }
+        used_a[i] = true;
+        used_b[j] = true;
+        pairs.push((i, j));
+    }
+    pairs.sort();
+    pairs
+}
+
+fn cosine(a: &PreparedPeaks, b: &PreparedPeaks, pairs: &[(usize, usize)]) -> f64 {
+    let denom = a.norm() * b.norm();
+    if denom == 0.0 {
+        return 0.0;
+    }
+    let dot: f64 = pairs
+        .iter()
+        .map(|(i, j)| a.weights[*i] * b.weights[*j])
+        .sum();
+    (dot / denom).min(1.0)
+}
+
+fn spectral_entropy(intensities: &[f64]) -> f64 {
+    let total: f64 = intensities.iter().sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    -intensities
+        .iter()
+        .filter(|i| **i > 0.0)
+        .map(|i| {
+            let p = i / total;
+            p * p.ln()
+        })
+        .sum::<f64>()
+}
+
+/// Re-weight a spectrum's intensities by its entropy, following Li et al. 2021
+fn entropy_weights(intensities: &[f64]) -> Vec<f64> {
+    let total: f64 = intensities.iter().sum();
+    if total == 0.0 {
+        return intensities.to_vec();
+    }
+    let normalized: Vec<f64> = intensities.iter().map(|i| i / total).collect();
+    let entropy = spectral_entropy(&normalized);
+    if entropy >= 3.0 {
+        return normalized;
+    }
+    let exponent = 0.25 + entropy * 0.25;
+    let weighted: Vec<f64> = normalized.iter().map(|i| i.powf(exponent)).collect();
+    let total: f64 = weighted.iter().sum();
+    weighted.into_iter().map(|i| i / total).collect()
+}
+
+fn entropy_similarity(a: &PreparedPeaks, b: &PreparedPeaks, pairs: &[(usize, usize)]) -> f64 {
+    // Entropy similarity is defined over the untransformed intensities
+    let a_int: Vec<f64> = entropy_weights(&a.raw.iter().map(|i| *i as f64).collect::<Vec<_>>());
+    let b_int: Vec<f64> = entropy_weights(&b.raw.iter().map(|i| *i as f64).collect::<Vec<_>>());
+    if a_int.is_empty() || b_int.is_empty() {
+        return 0.0;
+    }
+
+    let mut merged = Vec::with_capacity(a_int.len() + b_int.len());
+    let mut matched_a = vec![false; a_int.len()];
+    let mut matched_b = vec![false; b_int.len()];
+    for (i, j) in pairs {
+        matched_a[*i] = true;
+        matched_b[*j] = true;
+        merged.push((a_int[*i] + b_int[*j]) / 2.0);
+    }
+    merged.extend(
+        a_int
+            .iter()
+            .zip(matched_a)
+            .filter(|(_, m)| !m)
+            .map(|(i, _)| i / 2.0),
+    );
+    merged.extend(
+        b_int
+            .iter()
+            .zip(matched_b)
+            .filter(|(_, m)| !m)
+            .map(|(i, _)| i / 2.0),
+    );
+
+    let s_ab = spectral_entropy(&merged);
+    let s_a = spectral_entropy(&a_int);
+    let s_b = spectral_entropy(&b_int);
+    (1.0 - (2.0 * s_ab - s_a - s_b) / 4.0f64.ln()).clamp(0.0, 1.0)
+}
+
+/// Score two prepared peak lists with `method`, permitting matches shifted by `shift`
+pub fn score_prepared(
+    a: &PreparedPeaks,
+    b: &PreparedPeaks,
+    method: WebSimilarityMethod,
+    params: &WebSimilarityParams,
+    shift: Option<f64>,
+) -> WebSimilarityResult {
+    let pairs = align_peaks(a, b, params.tolerance(), shift);
+    let score = match method {
+        WebSimilarityMethod::Cosine => cosine(a, b, &pairs),
+        WebSimilarityMethod::SpectralContrastAngle => {
+            1.0 - 2.0 * cosine(a, b, &pairs).clamp(-1.0, 1.0).acos() / PI
+        }
+        WebSimilarityMethod::Entropy => entropy_similarity(a, b, &pairs),
+    };
+    let pairs = pairs
+        .into_iter()
+        .map(|(i, j)| WebMatchedPeakPair {
+            mz_a: a.mzs[i],
+            intensity_a: a.raw[i],
+            mz_b: b.mzs[j],
+            intensity_b: b.raw[j],
+        })
+        .collect();
+    WebSimilarityResult { score, pairs }
+}
+
+/// Compare two spectra's most processed peak lists
+#[wasm_bindgen(js_name = "spectrumSimilarity")]
+pub fn spectrum_similarity(
+    a: &WebSpectrum,
+    b: &WebSpectrum,
+    method: Option<WebSimilarityMethod>,
+    params: Option<WebSimilarityParams>,
+) -> WebSimilarityResult {
+    let params = params.unwrap_or_default();
+    let a = PreparedPeaks::from_spectrum(a, &params);
+    let b = PreparedPeaks::from_spectrum(b, &params);
+    score_prepared(&a, &b, method.unwrap_or_default(), &params, None)
+}
+
+/// Compare a spectrum's most processed peak list against a plain peak list
+#[wasm_bindgen(js_name = "peakListSimilarity")]
+pub fn peak_list_similarity(
+    a: &WebSpectrum,
+    peaks: Vec<SimpleWebPeak>,
+    method: Option<WebSimilarityMethod>,
+    params: Option<WebSimilarityParams>,
+) -> WebSimilarityResult {
+    let params = params.unwrap_or_default();
+    let a = PreparedPeaks::from_spectrum(a, &params);
+    let b = PreparedPeaks::new(
+        peaks.into_iter().map(|p| (p.mz, p.intensity)),
+        None,
+        &params,
+    );
+    score_prepared(&a, &b, method.unwrap_or_default(), &params, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(error_tolerance: Tolerance) -> WebSimilarityParams {
+        WebSimilarityParams {
+            error_tolerance,
+            sqrt_transform: false,
+            remove_precursor: false,
+            precursor_window: 1.5,
+        }
+    }
+
+    fn prepare(peaks: &[(f64, f32)], params: &WebSimilarityParams) -> PreparedPeaks {
+        PreparedPeaks::new(peaks.iter().copied(), None, params)
+    }
+
+    fn score(
+        a: &[(f64, f32)],
+        b: &[(f64, f32)],
+        method: WebSimilarityMethod,
+        error_tolerance: Tolerance,
+        shift: Option<f64>,
+    ) -> WebSimilarityResult {
+        let params = params(error_tolerance);
+        score_prepared(
+            &prepare(a, &params),
+            &prepare(b, &params),
+            method,
+            &params,
+            shift,
+        )
+    }
+
+    #[test]
+    fn test_cosine() {
+        let a = [(100.0, 1.0), (200.0, 1.0)];
+        for error_tolerance in [Tolerance::Da(0.02), Tolerance::PPM(20.0)] {
+            let result = score(&a, &a, WebSimilarityMethod::Cosine, error_tolerance, None);
+            assert!((result.score - 1.0).abs() < 1e-9);
+            assert_eq!(result.matched_count(), 2);
+
+            let b = [(100.001, 1.0), (300.0, 1.0)];
+            let result = score(&a, &b, WebSimilarityMethod::Cosine, error_tolerance, None);
+            assert!((result.score - 0.5).abs() < 1e-9);
+            assert_eq!(result.matched_count(), 1);
+        }
+
+        // 0.01 Da is inside the Da tolerance but 50 ppm away at m/z 200
+        let b = [(100.0, 1.0), (200.01, 1.0)];
+        let result = score(
+            &a,
+            &b,
+            WebSimilarityMethod::Cosine,
+            Tolerance::Da(0.02),
+            None,
+        );
+        assert!((result.score - 1.0).abs() < 1e-9);
+        let result = score(
+            &a,
+            &b,
+            WebSimilarityMethod::Cosine,
+            Tolerance::PPM(20.0),
+            None,
+        );
+        assert!((result.score - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_modified_cosine() {
+        let a = [(100.0, 1.0), (200.0, 1.0)];
+        let b = [(100.0, 1.0), (214.0157, 1.0)];
+        for error_tolerance in [Tolerance::Da(0.02), Tolerance::PPM(20.0)] {
+            let plain = score(&a, &b, WebSimilarityMethod::Cosine, error_tolerance, None);
+            assert!((plain.score - 0.5).abs() < 1e-9);
+
+            let shifted = score(
+                &a,
+                &b,
+                WebSimilarityMethod::Cosine,
+                error_tolerance,
+                Some(14.0157),
+            );
+            assert!((shifted.score - 1.0).abs() < 1e-9);
+            assert_eq!(shifted.matched_count(), 2);
+            assert_eq!(shifted.pairs[1].mz_b, 214.0157);
+        }
+
+        // A shift inside the tolerance window adds nothing over the unshifted match
+        let result = score(
+            &a,
+            &b,
+            WebSimilarityMethod::Cosine,
+            Tolerance::Da(0.02),
+            Some(0.01),
+        );
+        assert!((result.score - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_entropy() {
+        let a = [(100.0, 1.0), (200.0, 1.0)];
+        let b = [(300.0, 1.0), (400.0, 1.0)];
+        for error_tolerance in [Tolerance::Da(0.02), Tolerance::PPM(20.0)] {
+            let same = score(&a, &a, WebSimilarityMethod::Entropy, error_tolerance, None);
+            assert!((same.score - 1.0).abs() < 1e-9);
+
+            let disjoint = score(&a, &b, WebSimilarityMethod::Entropy, error_tolerance, None);
+            assert!(disjoint.score.abs() < 1e-9);
+
+            let c = [(100.0, 1.0), (400.0, 1.0)];
+            let partial = score(&a, &c, WebSimilarityMethod::Entropy, error_tolerance, None);
+            assert!(partial.score > 0.0 && partial.score < 1.0);
+        }
+    }
+}
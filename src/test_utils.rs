@@ -0,0 +1,58 @@
+//! Spectrum metadata fixtures shared by the unit tests of the run-wide indices
+
+use mzdata::params::{ControlledVocabulary, Param};
+use mzdata::prelude::*;
+use mzdata::spectrum::{IsolationWindow, PeakDataLevel, Precursor, ScanEvent, SpectrumDescription};
+
+use crate::pipeline::PipelineSpectrum;
+
+/// A spectrum with only metadata, identified as `scan={index}` and acquired at
+/// `index` seconds, with an empty precursor if it is MSn
+pub struct TestSpectrum(SpectrumDescription);
+
+impl TestSpectrum {
+    pub fn new(index: usize, ms_level: u8) -> Self {
+        let mut description = SpectrumDescription::default();
+        description.id = format!("scan={index}");
+        description.index = index;
+        description.ms_level = ms_level;
+        let mut event = ScanEvent::default();
+        event.start_time = index as f64 / 60.0;
+        description.acquisition.scans.push(event);
+        if ms_level > 1 {
+            description.precursor = Some(Precursor::default());
+        }
+        Self(description)
+    }
+
+    fn precursor(&mut self) -> &mut Precursor {
+        self.0.precursor.get_or_insert_with(Precursor::default)
+    }
+
+    /// Name the spectrum at `index` as the precursor scan
+    pub fn precursor_of(mut self, index: usize) -> Self {
+        self.precursor().precursor_id = Some(format!("scan={index}"));
+        self
+    }
+
+    pub fn isolation_window(mut self, lower: f32, upper: f32) -> Self {
+        let mut window = IsolationWindow::default();
+        window.lower_bound = lower;
+        window.upper_bound = upper;
+        window.target = (lower + upper) / 2.0;
+        self.precursor().isolation_window = window;
+        self
+    }
+
+    pub fn compensation_voltage(mut self, voltage: f64) -> Self {
+        let mut param = Param::new_key_value("FAIMS compensation voltage", voltage);
+        param.controlled_vocabulary = Some(ControlledVocabulary::MS);
+        param.accession = Some(1001581);
+        self.0.add_param(param);
+        self
+    }
+
+    pub fn build(self) -> PipelineSpectrum {
+        PipelineSpectrum::from_peaks_data_levels_and_description(PeakDataLevel::Missing, self.0)
+    }
+}